@@ -60,6 +60,7 @@ fn setup(
         border_size: 2,
         mip_level_count: 4,
         format: AttachmentFormat::R16,
+        lod_range: None,
     })
     .add_attachment(AttachmentConfig {
         name: "albedo".to_string(),
@@ -67,6 +68,7 @@ fn setup(
         border_size: 2,
         mip_level_count: 4,
         format: AttachmentFormat::Rgba8,
+        lod_range: None,
     });
 
     // Configure the quality settings of the terrain view. Adapt the settings to your liking.
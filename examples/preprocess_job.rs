@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+use bevy_terrain::prelude::*;
+use std::env;
+
+const DEFAULT_JOB: &str = "jobs/preprocess_planar.ron";
+
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins.build().disable::<TransformPlugin>(),
+            TerrainPlugin,
+            TerrainPreprocessPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let job_path = env::args().nth(1).unwrap_or(DEFAULT_JOB.to_string());
+    let job = PreprocessJob::load_file(format!("assets/{job_path}")).unwrap();
+
+    let mut tile_atlas = TileAtlas::new(&job.terrain_config());
+    let preprocessor = Preprocessor::from_job(&job, &asset_server, &mut tile_atlas);
+
+    commands.spawn((tile_atlas, preprocessor));
+}
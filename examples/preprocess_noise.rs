@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+use bevy_terrain::prelude::*;
+
+const PATH: &str = "terrains/noise";
+const TEXTURE_SIZE: u32 = 512;
+const LOD_COUNT: u32 = 6;
+
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins.build().disable::<TransformPlugin>(),
+            TerrainPlugin,
+            TerrainPreprocessPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let config = TerrainConfig {
+        lod_count: LOD_COUNT,
+        model: TerrainModel::sphere(default(), 6371000.0, -12000.0, 9000.0),
+        path: PATH.to_string(),
+        atlas_size: 2048,
+        ..default()
+    }
+    .add_attachment(AttachmentConfig {
+        name: "height".to_string(),
+        texture_size: TEXTURE_SIZE,
+        border_size: 2,
+        format: AttachmentFormat::R16,
+        lod_range: None,
+        ..default()
+    });
+
+    let mut tile_atlas = TileAtlas::new(&config);
+
+    let noise = NoiseConfig {
+        kind: NoiseKind::Warped { strength: 0.5 },
+        seed: 42,
+        octaves: 12,
+        ..default()
+    };
+
+    let preprocessor = Preprocessor::new()
+        .clear_attachment(0, &mut tile_atlas)
+        .preprocess_spherical(
+            SphericalDataset {
+                attachment_index: 0,
+                sources: vec![noise.into(); 6],
+                lod_range: 0..LOD_COUNT,
+                nodata: None,
+            },
+            &asset_server,
+            &mut tile_atlas,
+        );
+
+    commands.spawn((tile_atlas, preprocessor));
+}
@@ -23,6 +23,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         texture_size: TEXTURE_SIZE,
         border_size: 2,
         format: AttachmentFormat::R16,
+        lod_range: None,
         ..default()
     })
     .add_attachment(AttachmentConfig {
@@ -30,6 +31,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         texture_size: TEXTURE_SIZE,
         border_size: 2,
         format: AttachmentFormat::Rgba8,
+        lod_range: None,
         ..default()
     });
 
@@ -41,7 +43,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         .preprocess_tile(
             PreprocessDataset {
                 attachment_index: 0,
-                path: format!("{PATH}/source/height.png"),
+                source: format!("{PATH}/source/height.png").into(),
                 lod_range: 0..LOD_COUNT,
                 ..default()
             },
@@ -51,7 +53,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         .preprocess_tile(
             PreprocessDataset {
                 attachment_index: 1,
-                path: format!("{PATH}/source/albedo.png"),
+                source: format!("{PATH}/source/albedo.png").into(),
                 lod_range: 0..LOD_COUNT,
                 ..default()
             },
@@ -28,6 +28,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         texture_size: TEXTURE_SIZE,
         border_size: 2,
         format: AttachmentFormat::R16,
+        lod_range: None,
         ..default()
     });
 
@@ -38,10 +39,11 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         .preprocess_spherical(
             SphericalDataset {
                 attachment_index: 0,
-                paths: (0..6)
-                    .map(|side| format!("{PATH}/source/height/face{side}.tif"))
+                sources: (0..6)
+                    .map(|side| format!("{PATH}/source/height/face{side}.tif").into())
                     .collect(),
                 lod_range: 0..LOD_COUNT,
+                nodata: None,
             },
             &asset_server,
             &mut tile_atlas,
@@ -0,0 +1,43 @@
+use bevy::prelude::*;
+use bevy_terrain::prelude::*;
+
+const PATH: &str = "terrains/planar";
+const TEXTURE_SIZE: u32 = 512;
+const LOD_COUNT: u32 = 4;
+
+fn main() {
+    // the same config, that was used to preprocess the terrain
+    let config = TerrainConfig {
+        lod_count: LOD_COUNT,
+        path: PATH.to_string(),
+        ..default()
+    }
+    .add_attachment(AttachmentConfig {
+        name: "height".to_string(),
+        texture_size: TEXTURE_SIZE,
+        border_size: 2,
+        format: AttachmentFormat::R16,
+        lod_range: None,
+        ..default()
+    })
+    .add_attachment(AttachmentConfig {
+        name: "albedo".to_string(),
+        texture_size: TEXTURE_SIZE,
+        border_size: 2,
+        format: AttachmentFormat::Rgba8,
+        lod_range: None,
+        ..default()
+    });
+
+    let report = validate_terrain(&config, &ValidationConfig::default()).unwrap();
+
+    report
+        .save_file(&format!("assets/{PATH}/validation.ron"))
+        .unwrap();
+
+    println!(
+        "Validated {} tiles and found {} issues.",
+        report.tile_count,
+        report.issues.len()
+    );
+}
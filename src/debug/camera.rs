@@ -68,7 +68,6 @@ impl DebugCameraBundle {
                 translation_speed: speed,
                 ..default()
             },
-            ..default()
         }
     }
 }
@@ -0,0 +1,100 @@
+//! Registers the [`TileAtlasStats`] of all terrains as Bevy diagnostics.
+
+use crate::terrain_data::tile_atlas::{TileAtlas, TileAtlasStats};
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+
+/// Adds diagnostics for the residency and streaming of the tile atlases.
+///
+/// The measurements are summed over all terrains, use [`TileAtlas::stats`] to inspect
+/// a single terrain.
+pub struct TerrainDiagnosticsPlugin;
+
+impl Plugin for TerrainDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(Self::RESIDENT_TILES))
+            .register_diagnostic(Diagnostic::new(Self::REQUESTED_TILES))
+            .register_diagnostic(Diagnostic::new(Self::PINNED_TILES))
+            .register_diagnostic(Diagnostic::new(Self::LOADING_TILES))
+            .register_diagnostic(Diagnostic::new(Self::UNUSED_TILES))
+            .register_diagnostic(Diagnostic::new(Self::EVICTIONS))
+            .register_diagnostic(Diagnostic::new(Self::RELOADS))
+            .register_diagnostic(Diagnostic::new(Self::LOAD_LATENCY).with_suffix("ms"))
+            .register_diagnostic(Diagnostic::new(Self::BYTES_LOADED).with_suffix("B"))
+            .register_diagnostic(Diagnostic::new(Self::BYTES_UPLOADED).with_suffix("B"))
+            .add_systems(Last, Self::diagnostic_system.after(TileAtlas::update));
+    }
+}
+
+impl TerrainDiagnosticsPlugin {
+    pub const RESIDENT_TILES: DiagnosticPath = DiagnosticPath::const_new("terrain/resident_tiles");
+    pub const REQUESTED_TILES: DiagnosticPath =
+        DiagnosticPath::const_new("terrain/requested_tiles");
+    pub const PINNED_TILES: DiagnosticPath = DiagnosticPath::const_new("terrain/pinned_tiles");
+    pub const LOADING_TILES: DiagnosticPath = DiagnosticPath::const_new("terrain/loading_tiles");
+    pub const UNUSED_TILES: DiagnosticPath = DiagnosticPath::const_new("terrain/unused_tiles");
+    /// The tiles evicted in the last frame.
+    pub const EVICTIONS: DiagnosticPath = DiagnosticPath::const_new("terrain/evictions");
+    /// The previously evicted tiles, that started loading again in the last frame.
+    pub const RELOADS: DiagnosticPath = DiagnosticPath::const_new("terrain/reloads");
+    /// The mean load latency of the tiles, which finished loading in the last frame.
+    pub const LOAD_LATENCY: DiagnosticPath = DiagnosticPath::const_new("terrain/load_latency");
+    pub const BYTES_LOADED: DiagnosticPath = DiagnosticPath::const_new("terrain/bytes_loaded");
+    pub const BYTES_UPLOADED: DiagnosticPath = DiagnosticPath::const_new("terrain/bytes_uploaded");
+
+    pub fn diagnostic_system(
+        mut diagnostics: Diagnostics,
+        mut previous: Local<TileAtlasStats>,
+        tile_atlases: Query<&TileAtlas>,
+    ) {
+        let mut total = TileAtlasStats::default();
+
+        for tile_atlas in &tile_atlases {
+            let stats = tile_atlas.stats();
+
+            total.atlas_size += stats.atlas_size;
+            total.resident_tiles += stats.resident_tiles;
+            total.requested_tiles += stats.requested_tiles;
+            total.pinned_tiles += stats.pinned_tiles;
+            total.loading_tiles += stats.loading_tiles;
+            total.unused_tiles += stats.unused_tiles;
+            total.evictions += stats.evictions;
+            total.reloads += stats.reloads;
+            total.bytes_loaded += stats.bytes_loaded;
+            total.bytes_uploaded += stats.bytes_uploaded;
+            total.load_latency.merge(&stats.load_latency);
+        }
+
+        // the counters are cumulative, so only the change since the last frame is measured
+        let evictions = total.evictions.saturating_sub(previous.evictions);
+        let reloads = total.reloads.saturating_sub(previous.reloads);
+        let loads = total
+            .load_latency
+            .count()
+            .saturating_sub(previous.load_latency.count());
+        let latency = total
+            .load_latency
+            .total
+            .saturating_sub(previous.load_latency.total);
+
+        diagnostics.add_measurement(&Self::RESIDENT_TILES, || total.resident_tiles as f64);
+        diagnostics.add_measurement(&Self::REQUESTED_TILES, || total.requested_tiles as f64);
+        diagnostics.add_measurement(&Self::PINNED_TILES, || total.pinned_tiles as f64);
+        diagnostics.add_measurement(&Self::LOADING_TILES, || total.loading_tiles as f64);
+        diagnostics.add_measurement(&Self::UNUSED_TILES, || total.unused_tiles as f64);
+        diagnostics.add_measurement(&Self::EVICTIONS, || evictions as f64);
+        diagnostics.add_measurement(&Self::RELOADS, || reloads as f64);
+        diagnostics.add_measurement(&Self::BYTES_LOADED, || total.bytes_loaded as f64);
+        diagnostics.add_measurement(&Self::BYTES_UPLOADED, || total.bytes_uploaded as f64);
+
+        if loads > 0 {
+            diagnostics.add_measurement(&Self::LOAD_LATENCY, || {
+                latency.as_secs_f64() * 1000.0 / loads as f64
+            });
+        }
+
+        *previous = total;
+    }
+}
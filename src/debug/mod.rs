@@ -13,6 +13,7 @@ use bevy::{
 };
 
 pub mod camera;
+pub mod diagnostics;
 
 #[derive(Asset, AsBindGroup, TypePath, Clone, Default)]
 pub struct DebugTerrainMaterial {}
@@ -1,20 +1,43 @@
 pub mod tiff;
 
-use crate::math::TileCoordinate;
+use crate::{math::TileCoordinate, terrain_data::TileStatistics};
 use anyhow::Result;
 use bincode::{config, Decode, Encode};
 use std::{fs, path::Path};
 
-#[derive(Encode, Decode, Debug)]
+#[derive(Encode, Decode, Debug, Default)]
 pub struct TC {
     pub tiles: Vec<TileCoordinate>,
+    /// The height range measured from the source data during preprocessing.
+    pub height_range: Option<(f32, f32)>,
+    /// The normalized height statistics of the tiles of the height attachment.
+    pub tile_statistics: Vec<(TileCoordinate, TileStatistics)>,
+}
+
+/// The layout of tile configs written before the height range was stored.
+#[derive(Decode)]
+struct LegacyTC {
+    tiles: Vec<TileCoordinate>,
 }
 
 impl TC {
     pub fn decode_alloc(encoded: &[u8]) -> Result<Self> {
         let config = config::standard();
-        let decoded = bincode::decode_from_slice(encoded, config)?;
-        Ok(decoded.0)
+
+        match bincode::decode_from_slice(encoded, config) {
+            Ok((decoded, _)) => Ok(decoded),
+            Err(error) => {
+                let Ok((LegacyTC { tiles }, _)) = bincode::decode_from_slice(encoded, config)
+                else {
+                    return Err(error.into());
+                };
+
+                Ok(Self {
+                    tiles,
+                    ..Default::default()
+                })
+            }
+        }
     }
 
     pub fn encode_alloc(&self) -> Result<Vec<u8>> {
@@ -30,17 +30,44 @@ impl AssetLoader for TiffLoader {
 
         let (width, height) = decoder.dimensions().unwrap();
 
-        let data = match decoder.read_image().unwrap() {
-            DecodingResult::U8(data) => cast_slice(&data).to_vec(),
-            DecodingResult::U16(data) => cast_slice(&data).to_vec(),
-            DecodingResult::U32(data) => cast_slice(&data).to_vec(),
-            DecodingResult::U64(data) => cast_slice(&data).to_vec(),
-            DecodingResult::F32(data) => cast_slice(&data).to_vec(),
-            DecodingResult::F64(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I8(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I16(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I32(data) => cast_slice(&data).to_vec(),
-            DecodingResult::I64(data) => cast_slice(&data).to_vec(),
+        // Float and signed data is stored in physical units (e.g. metres), and is normalized
+        // by the preprocessor once its value range is known.
+        let (data, format) = match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (cast_slice(&data).to_vec(), TextureFormat::R16Unorm),
+            DecodingResult::U16(data) => (cast_slice(&data).to_vec(), TextureFormat::R16Unorm),
+            DecodingResult::U32(data) => (cast_slice(&data).to_vec(), TextureFormat::R16Unorm),
+            DecodingResult::U64(data) => (cast_slice(&data).to_vec(), TextureFormat::R16Unorm),
+            DecodingResult::F32(data) => (cast_slice(&data).to_vec(), TextureFormat::R32Float),
+            DecodingResult::F64(data) => (
+                data.iter()
+                    .flat_map(|&value| (value as f32).to_le_bytes())
+                    .collect(),
+                TextureFormat::R32Float,
+            ),
+            DecodingResult::I8(data) => (
+                data.iter()
+                    .flat_map(|&value| (value as f32).to_le_bytes())
+                    .collect(),
+                TextureFormat::R32Float,
+            ),
+            DecodingResult::I16(data) => (
+                data.iter()
+                    .flat_map(|&value| (value as f32).to_le_bytes())
+                    .collect(),
+                TextureFormat::R32Float,
+            ),
+            DecodingResult::I32(data) => (
+                data.iter()
+                    .flat_map(|&value| (value as f32).to_le_bytes())
+                    .collect(),
+                TextureFormat::R32Float,
+            ),
+            DecodingResult::I64(data) => (
+                data.iter()
+                    .flat_map(|&value| (value as f32).to_le_bytes())
+                    .collect(),
+                TextureFormat::R32Float,
+            ),
         };
 
         Ok(Image::new(
@@ -51,7 +78,7 @@ impl AssetLoader for TiffLoader {
             },
             TextureDimension::D2,
             data,
-            TextureFormat::R16Unorm,
+            format,
             RenderAssetUsages::default(),
         ))
     }
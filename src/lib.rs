@@ -67,19 +67,37 @@ pub mod prelude {
     pub use crate::{
         debug::{
             camera::{DebugCameraBundle, DebugCameraController},
+            diagnostics::TerrainDiagnosticsPlugin,
             DebugTerrainMaterial, LoadingImages, TerrainDebugPlugin,
         },
         math::TerrainModel,
         plugin::TerrainPlugin,
         preprocess::{
+            job::{JobDataset, JobModel, PreprocessJob},
             preprocessor::Preprocessor,
-            preprocessor::{PreprocessDataset, SphericalDataset},
+            preprocessor::{
+                DatasetSource, DerivedAttachment, DerivedDataset, NodataConfig, NoiseConfig,
+                NoiseKind, PreprocessDataset, SphericalDataset,
+            },
+            validator::{validate_terrain, ValidationConfig, ValidationIssue, ValidationReport},
             TerrainPreprocessPlugin,
         },
         render::terrain_material::TerrainMaterialPlugin,
         terrain::{TerrainBundle, TerrainConfig},
         terrain_data::{
-            tile_atlas::TileAtlas, tile_tree::TileTree, AttachmentConfig, AttachmentFormat,
+            attachment_source::{
+                AttachmentSource, GeneratedAttachment, GeneratedTile, GpuGeneratedAttachment,
+            },
+            tile_atlas::{
+                AdaptiveStreamingConfig, LoadLatencyHistogram, SharedTileAtlas, StreamingBudget,
+                TileAtlas, TileAtlasExhausted, TileAtlasStats, TileAtlasStreamingConfig,
+                TileLoaded, TilePin, TileRequested, TileUnloaded,
+            },
+            tile_content::{
+                ContentTile, TileContent, TileContentRoot, TileSceneSpawner, TileSpawner,
+            },
+            tile_tree::TileTree,
+            AttachmentConfig, AttachmentData, AttachmentFormat, TileStatistics,
         },
         terrain_view::{TerrainViewComponents, TerrainViewConfig},
     };
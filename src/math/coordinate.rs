@@ -4,6 +4,8 @@ use bevy::{
     render::render_resource::ShaderType,
 };
 use bincode::{Decode, Encode};
+use itertools::iproduct;
+use serde::Serialize;
 use std::fmt;
 
 const NEIGHBOURING_SIDES: [[u32; 5]; 6] = [
@@ -44,7 +46,7 @@ impl SideInfo {
     fn project_to_side(side: u32, other_side: u32) -> [SideInfo; 2] {
         let index = ((6 + other_side - side) % 6) as usize;
 
-        if side % 2 == 0 {
+        if side.is_multiple_of(2) {
             SideInfo::EVEN_LIST[index]
         } else {
             SideInfo::ODD_LIST[index]
@@ -99,7 +101,7 @@ impl Coordinate {
             (side, uv)
         } else {
             let uv = DVec2::new(local_position.x + 0.5, local_position.z + 0.5)
-                .clamp(DVec2::ZERO, DVec2::ONE);
+                .clamp(DVec2::ZERO, model.root_count().as_dvec2());
 
             (0, uv)
         };
@@ -108,7 +110,12 @@ impl Coordinate {
     }
 
     pub(crate) fn world_position(self, model: &TerrainModel, height: f32) -> DVec3 {
-        let local_position = if model.is_spherical() {
+        model.position_local_to_world(self.local_position(model), height as f64)
+    }
+
+    /// The position on the unit sphere or the unit plane of the model.
+    pub(crate) fn local_position(self, model: &TerrainModel) -> DVec3 {
+        if model.is_spherical() {
             let w = (self.uv - 0.5) / 0.5;
             let uv = w / (1.0 + C_SQR - C_SQR * w * w).powf(0.5);
 
@@ -124,9 +131,7 @@ impl Coordinate {
             .normalize()
         } else {
             DVec3::new(self.uv.x - 0.5, 0.0, self.uv.y - 0.5)
-        };
-
-        model.position_local_to_world(local_position, height as f64)
+        }
     }
 
     /// Projects the coordinate onto one of the six cube faces.
@@ -151,8 +156,17 @@ impl Coordinate {
     }
 }
 
+/// The world space bounding box of a tile.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TileBounds {
+    pub min: DVec3,
+    pub max: DVec3,
+}
+
 /// The global coordinate and identifier of a tile.
-#[derive(Copy, Clone, Default, Debug, Hash, Eq, PartialEq, ShaderType, Encode, Decode)]
+#[derive(
+    Copy, Clone, Default, Debug, Hash, Eq, PartialEq, ShaderType, Encode, Decode, Serialize,
+)]
 pub struct TileCoordinate {
     /// The side of the cube sphere the tile is located on.
     pub side: u32,
@@ -185,6 +199,21 @@ impl TileCoordinate {
         format!("{path}/{self}.{extension}")
     }
 
+    /// The position of the tile along the Z-order curve of its side, which keeps nearby tiles close together.
+    pub fn z_order(self) -> u64 {
+        fn spread(value: u32) -> u64 {
+            let mut value = value as u64;
+            value = (value | (value << 16)) & 0x0000_FFFF_0000_FFFF;
+            value = (value | (value << 8)) & 0x00FF_00FF_00FF_00FF;
+            value = (value | (value << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+            value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+            value = (value | (value << 1)) & 0x5555_5555_5555_5555;
+            value
+        }
+
+        spread(self.x) | (spread(self.y) << 1)
+    }
+
     pub fn parent(self) -> Self {
         Self {
             side: self.side,
@@ -194,6 +223,39 @@ impl TileCoordinate {
         }
     }
 
+    /// The world space bounds of the tile between the minimum and maximum height.
+    ///
+    /// On spherical terrains the bounds enclose a grid of samples on the tile,
+    /// which approximates the curvature of its surface.
+    pub(crate) fn world_bounds(
+        self,
+        model: &TerrainModel,
+        min_height: f32,
+        max_height: f32,
+    ) -> TileBounds {
+        let sample_count = if model.is_spherical() { 5 } else { 2 };
+        let tile_count = Self::count(self.lod) as f64;
+        let tile_xy = DVec2::new(self.x as f64, self.y as f64);
+
+        iproduct!(0..sample_count, 0..sample_count, [min_height, max_height])
+            .map(|(x, y, height)| {
+                let sample_offset = DVec2::new(x as f64, y as f64) / (sample_count - 1) as f64;
+
+                Coordinate::new(self.side, (tile_xy + sample_offset) / tile_count)
+                    .world_position(model, height)
+            })
+            .fold(
+                TileBounds {
+                    min: DVec3::splat(f64::INFINITY),
+                    max: DVec3::splat(f64::NEG_INFINITY),
+                },
+                |bounds, position| TileBounds {
+                    min: bounds.min.min(position),
+                    max: bounds.max.max(position),
+                },
+            )
+    }
+
     pub fn children(self) -> impl Iterator<Item = Self> {
         (0..4).map(move |index| {
             TileCoordinate::new(
@@ -205,7 +267,7 @@ impl TileCoordinate {
         })
     }
 
-    pub fn neighbours(self, spherical: bool) -> impl Iterator<Item = Self> {
+    pub fn neighbours(self, model: &TerrainModel) -> impl Iterator<Item = Self> + '_ {
         const OFFSETS: [IVec2; 8] = [
             IVec2::new(0, -1),
             IVec2::new(1, 0),
@@ -220,21 +282,16 @@ impl TileCoordinate {
         OFFSETS.iter().map(move |&offset| {
             let neighbour_position = IVec2::new(self.x as i32, self.y as i32) + offset;
 
-            self.neighbour_coordinate(neighbour_position, spherical)
+            self.neighbour_coordinate(neighbour_position, model)
         })
     }
 
-    fn neighbour_coordinate(self, neighbour_position: IVec2, spherical: bool) -> Self {
+    fn neighbour_coordinate(self, neighbour_position: IVec2, model: &TerrainModel) -> Self {
         let tile_count = Self::count(self.lod) as i32;
 
-        if spherical {
+        if model.is_spherical() {
             let edge_index = match neighbour_position {
-                IVec2 { x, y }
-                    if x < 0 && y < 0
-                        || x < 0 && y >= tile_count
-                        || x >= tile_count && y < 0
-                        || x >= tile_count && y >= tile_count =>
-                {
+                IVec2 { x, y } if (x < 0 || x >= tile_count) && (y < 0 || y >= tile_count) => {
                     return Self::INVALID;
                 }
                 IVec2 { x, .. } if x < 0 => 1,
@@ -261,11 +318,9 @@ impl TileCoordinate {
 
             Self::new(neighbour_side, self.lod, x, y)
         } else {
-            if neighbour_position.x < 0
-                || neighbour_position.y < 0
-                || neighbour_position.x >= tile_count
-                || neighbour_position.y >= tile_count
-            {
+            let side_count = model.root_count().as_ivec2() * tile_count;
+
+            if neighbour_position.min_element() < 0 || neighbour_position.cmpge(side_count).any() {
                 Self::INVALID
             } else {
                 Self::new(
@@ -3,7 +3,7 @@ mod ellipsoid;
 mod terrain_model;
 
 pub use crate::math::{
-    coordinate::{Coordinate, TileCoordinate},
+    coordinate::{Coordinate, TileBounds, TileCoordinate},
     terrain_model::{
         generate_terrain_model_approximation, TerrainModel, TerrainModelApproximation,
     },
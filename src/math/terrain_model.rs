@@ -5,10 +5,11 @@ use crate::{
     terrain_view::TerrainViewComponents,
 };
 use bevy::{
-    math::{DMat3, DMat4, DQuat, DVec2, DVec3, IVec2},
+    math::{DMat3, DMat4, DQuat, DVec2, DVec3, IVec2, UVec2},
     prelude::*,
     render::render_resource::ShaderType,
 };
+use itertools::iproduct;
 
 /// One matrix per side, which shuffles the a, b, and c component to their corresponding position.
 const SIDE_MATRICES: [DMat3; 6] = [
@@ -22,13 +23,15 @@ const SIDE_MATRICES: [DMat3; 6] = [
 
 #[derive(Clone)]
 pub enum TerrainKind {
-    PLANAR {
+    Planar {
         side_length: f64,
+        /// The count of tiles at lod zero in x and z direction.
+        root_count: UVec2,
     },
-    SPHERICAL {
+    Spherical {
         radius: f64,
     },
-    ELLIPSOIDAL {
+    Ellipsoidal {
         ellipsoid_from_world: DMat4,
         major_axis: f64,
         minor_axis: f64,
@@ -52,9 +55,9 @@ pub struct TerrainModel {
 impl TerrainModel {
     pub(crate) fn is_spherical(&self) -> bool {
         match self.kind {
-            TerrainKind::PLANAR { .. } => false,
-            TerrainKind::SPHERICAL { .. } => true,
-            TerrainKind::ELLIPSOIDAL { .. } => true,
+            TerrainKind::Planar { .. } => false,
+            TerrainKind::Spherical { .. } => true,
+            TerrainKind::Ellipsoidal { .. } => true,
         }
     }
 
@@ -82,13 +85,41 @@ impl TerrainModel {
     }
 
     pub fn planar(position: DVec3, side_length: f64, min_height: f32, max_height: f32) -> Self {
+        Self::planar_rectangle(position, side_length, UVec2::ONE, min_height, max_height)
+    }
+
+    /// A rectangular planar terrain centered at the position, which consists of a grid of square
+    /// root tiles and extends `side_length * root_count` in x and z direction.
+    ///
+    /// The uv coordinates of the terrain range from zero to the root count,
+    /// so a dataset covering the entire terrain spans from `(0, 0)` to the root count.
+    /// The tree size of the views has to be at least as large as the root count,
+    /// and their geometry tile count at least the amount of root tiles.
+    pub fn planar_rectangle(
+        position: DVec3,
+        side_length: f64,
+        root_count: UVec2,
+        min_height: f32,
+        max_height: f32,
+    ) -> Self {
+        assert!(
+            root_count.min_element() > 0,
+            "A planar terrain requires at least one root tile."
+        );
+
+        // the local origin lies at the center of the first root tile
+        let offset = 0.5 * side_length * (root_count.as_dvec2() - 1.0);
+
         Self::from_scale_rotation_translation(
             DVec3::splat(side_length),
             DQuat::IDENTITY,
-            position,
+            position - DVec3::new(offset.x, 0.0, offset.y),
             min_height,
             max_height,
-            TerrainKind::PLANAR { side_length },
+            TerrainKind::Planar {
+                side_length,
+                root_count,
+            },
         )
     }
 
@@ -99,7 +130,7 @@ impl TerrainModel {
             position,
             min_height,
             max_height,
-            TerrainKind::SPHERICAL { radius },
+            TerrainKind::Spherical { radius },
         )
     }
 
@@ -119,7 +150,7 @@ impl TerrainModel {
             position,
             min_height,
             max_height,
-            TerrainKind::ELLIPSOIDAL {
+            TerrainKind::Ellipsoidal {
                 ellipsoid_from_world,
                 major_axis,
                 minor_axis,
@@ -127,31 +158,38 @@ impl TerrainModel {
         )
     }
 
+    pub(crate) fn world_from_local(&self) -> DMat4 {
+        self.world_from_local
+    }
+
     pub(crate) fn position_local_to_world(&self, local_position: DVec3, height: f64) -> DVec3 {
         let world_position = self.world_from_local.transform_point3(local_position);
-        let world_normal = self
-            .world_from_local
+
+        world_position + height * self.surface_normal(local_position)
+    }
+
+    /// The world space direction, along which heights are measured at the local position.
+    pub(crate) fn surface_normal(&self, local_position: DVec3) -> DVec3 {
+        self.world_from_local
             .transform_vector3(if self.is_spherical() {
                 local_position
             } else {
                 DVec3::Y
             })
-            .normalize();
-
-        world_position + height * world_normal
+            .normalize()
     }
 
     pub(crate) fn position_world_to_local(&self, world_position: DVec3) -> DVec3 {
         match self.kind {
-            TerrainKind::PLANAR { .. } => {
+            TerrainKind::Planar { .. } => {
                 DVec3::new(1.0, 0.0, 1.0) * self.local_from_world.transform_point3(world_position)
             }
 
-            TerrainKind::SPHERICAL { .. } => self
+            TerrainKind::Spherical { .. } => self
                 .local_from_world
                 .transform_point3(world_position)
                 .normalize(),
-            TerrainKind::ELLIPSOIDAL {
+            TerrainKind::Ellipsoidal {
                 ellipsoid_from_world,
                 major_axis,
                 minor_axis,
@@ -180,11 +218,27 @@ impl TerrainModel {
         }
     }
 
+    /// The count of tiles at lod zero in x and y direction of each side.
+    pub(crate) fn root_count(&self) -> UVec2 {
+        match self.kind {
+            TerrainKind::Planar { root_count, .. } => root_count,
+            _ => UVec2::ONE,
+        }
+    }
+
+    /// The tiles at lod zero of all sides.
+    pub(crate) fn root_tiles(&self) -> impl Iterator<Item = TileCoordinate> {
+        let root_count = self.root_count();
+
+        iproduct!(0..self.side_count(), 0..root_count.y, 0..root_count.x)
+            .map(|(side, y, x)| TileCoordinate::new(side, 0, x, y))
+    }
+
     pub(crate) fn scale(&self) -> f64 {
         match self.kind {
-            TerrainKind::PLANAR { side_length } => side_length / 2.0,
-            TerrainKind::SPHERICAL { radius } => radius,
-            TerrainKind::ELLIPSOIDAL {
+            TerrainKind::Planar { side_length, .. } => side_length / 2.0,
+            TerrainKind::Spherical { radius } => radius,
+            TerrainKind::Ellipsoidal {
                 major_axis,
                 minor_axis,
                 ..
@@ -1,6 +1,11 @@
 use crate::{
     math::{generate_terrain_model_approximation, TerrainModelApproximation},
     render::{
+        attachment_bindings::AttachmentLayouts,
+        attachment_generation::{
+            prepare_attachment_generation, queue_attachment_generation, AttachmentGenerationLabel,
+            AttachmentGenerationNode, AttachmentGenerationPipelines,
+        },
         culling_bind_group::CullingBindGroup,
         terrain_bind_group::TerrainData,
         terrain_view_bind_group::TerrainViewData,
@@ -12,7 +17,10 @@ use crate::{
     shaders::{load_terrain_shaders, InternalShaders},
     terrain::TerrainComponents,
     terrain_data::{
-        gpu_tile_atlas::GpuTileAtlas, gpu_tile_tree::GpuTileTree, tile_atlas::TileAtlas,
+        gpu_tile_atlas::{GpuTileAtlas, SharedAtlasTextures},
+        gpu_tile_tree::GpuTileTree,
+        tile_atlas::{TileAtlas, TileAtlasExhausted, TileLoaded, TileRequested, TileUnloaded},
+        tile_content::TileContent,
         tile_tree::TileTree,
     },
     terrain_view::TerrainViewComponents,
@@ -38,6 +46,10 @@ impl Plugin for TerrainPlugin {
 
         app.init_resource::<InternalShaders>()
             .init_resource::<TerrainViewComponents<TileTree>>()
+            .add_event::<TileAtlasExhausted>()
+            .add_event::<TileRequested>()
+            .add_event::<TileLoaded>()
+            .add_event::<TileUnloaded>()
             .init_resource::<TerrainViewComponents<TerrainModelApproximation>>()
             .add_systems(
                 PostUpdate,
@@ -53,10 +65,19 @@ impl Plugin for TerrainPlugin {
                     generate_terrain_model_approximation,
                 )
                     .chain(),
+            )
+            .add_systems(Last, AttachmentLayouts::update.after(TileAtlas::update))
+            .add_systems(
+                Last,
+                (
+                    TileContent::update.after(TileAtlas::update),
+                    TileContent::cleanup,
+                ),
             );
 
         app.sub_app_mut(RenderApp)
             .init_resource::<TerrainComponents<GpuTileAtlas>>()
+            .init_resource::<SharedAtlasTextures>()
             .init_resource::<TerrainComponents<TerrainData>>()
             .init_resource::<TerrainViewComponents<GpuTileTree>>()
             .init_resource::<TerrainViewComponents<TerrainViewData>>()
@@ -67,9 +88,10 @@ impl Plugin for TerrainPlugin {
                 (
                     GpuTileAtlas::initialize,
                     GpuTileAtlas::extract.after(GpuTileAtlas::initialize),
+                    GpuTileAtlas::release_shared_textures.after(GpuTileAtlas::initialize),
                     GpuTileTree::initialize,
                     GpuTileTree::extract.after(GpuTileTree::initialize),
-                    TerrainData::initialize.after(GpuTileAtlas::initialize),
+                    TerrainData::initialize.after(GpuTileAtlas::extract),
                     TerrainData::extract.after(TerrainData::initialize),
                     TerrainViewData::initialize.after(GpuTileTree::initialize),
                     TerrainViewData::extract.after(TerrainViewData::initialize),
@@ -86,7 +108,11 @@ impl Plugin for TerrainPlugin {
                         CullingBindGroup::prepare,
                     )
                         .in_set(RenderSet::Prepare),
+                    prepare_attachment_generation
+                        .in_set(RenderSet::Prepare)
+                        .after(GpuTileAtlas::prepare),
                     queue_tiling_prepass.in_set(RenderSet::Queue),
+                    queue_attachment_generation.in_set(RenderSet::Queue),
                     GpuTileAtlas::cleanup
                         .before(World::clear_entities)
                         .in_set(RenderSet::Cleanup),
@@ -96,14 +122,19 @@ impl Plugin for TerrainPlugin {
 
     fn finish(&self, app: &mut App) {
         load_terrain_shaders(app);
+        app.init_resource::<AttachmentLayouts>();
 
         let render_app = app
             .sub_app_mut(RenderApp)
             .init_resource::<TilingPrepassPipelines>()
-            .init_resource::<SpecializedComputePipelines<TilingPrepassPipelines>>();
+            .init_resource::<SpecializedComputePipelines<TilingPrepassPipelines>>()
+            .init_resource::<AttachmentGenerationPipelines>()
+            .init_resource::<SpecializedComputePipelines<AttachmentGenerationPipelines>>();
 
         let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
         render_graph.add_node(TilingPrepassLabel, TilingPrepassNode);
         render_graph.add_node_edge(TilingPrepassLabel, CameraDriverLabel);
+        render_graph.add_node(AttachmentGenerationLabel, AttachmentGenerationNode);
+        render_graph.add_node_edge(AttachmentGenerationLabel, CameraDriverLabel);
     }
 }
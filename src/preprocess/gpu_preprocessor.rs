@@ -1,6 +1,7 @@
 use crate::{
+    math::TerrainModel,
     preprocess::{
-        preprocessor::{PreprocessTask, PreprocessTaskType, Preprocessor},
+        preprocessor::{NoiseKind, PreprocessTask, PreprocessTaskType, Preprocessor},
         TerrainPreprocessItem,
     },
     terrain::TerrainComponents,
@@ -32,6 +33,25 @@ pub(crate) struct SplitData {
     tile: AtlasTile,
     top_left: Vec2,
     bottom_right: Vec2,
+    nodata_value: f32,
+    tile_index: u32,
+}
+
+#[derive(Clone, Debug, ShaderType)]
+struct GenerateData {
+    tile: AtlasTile,
+    top_left: Vec2,
+    bottom_right: Vec2,
+    kind: u32,
+    seed: u32,
+    octaves: u32,
+    frequency: f32,
+    lacunarity: f32,
+    gain: f32,
+    amplitude: f32,
+    offset: f32,
+    warp_strength: f32,
+    is_spherical: u32,
     tile_index: u32,
 }
 
@@ -49,6 +69,27 @@ struct DownsampleData {
     tile_index: u32,
 }
 
+#[derive(Clone, Debug, ShaderType)]
+struct FillData {
+    tile: AtlasTile,
+    parent_tile: AtlasTile,
+    fill_radius: u32,
+    fill_from_parent: u32,
+    tile_index: u32,
+}
+
+#[derive(Clone, Debug, ShaderType)]
+struct DeriveData {
+    tile: AtlasTile,
+    world_from_local: Mat3,
+    kind: u32,
+    parameter: f32,
+    is_spherical: u32,
+    min_height: f32,
+    max_height: f32,
+    tile_index: u32,
+}
+
 pub(crate) fn create_split_layout(device: &RenderDevice) -> BindGroupLayout {
     device.create_bind_group_layout(
         None,
@@ -63,6 +104,16 @@ pub(crate) fn create_split_layout(device: &RenderDevice) -> BindGroupLayout {
     )
 }
 
+pub(crate) fn create_generate_layout(device: &RenderDevice) -> BindGroupLayout {
+    device.create_bind_group_layout(
+        None,
+        &BindGroupLayoutEntries::single(
+            ShaderStages::COMPUTE,
+            uniform_buffer::<GenerateData>(false),
+        ),
+    )
+}
+
 pub(crate) fn create_stitch_layout(device: &RenderDevice) -> BindGroupLayout {
     device.create_bind_group_layout(
         None,
@@ -80,9 +131,31 @@ pub(crate) fn create_downsample_layout(device: &RenderDevice) -> BindGroupLayout
     )
 }
 
+pub(crate) fn create_fill_layout(device: &RenderDevice) -> BindGroupLayout {
+    device.create_bind_group_layout(
+        None,
+        &BindGroupLayoutEntries::single(ShaderStages::COMPUTE, uniform_buffer::<FillData>(false)),
+    )
+}
+
+pub(crate) fn create_derive_layout(device: &RenderDevice) -> BindGroupLayout {
+    device.create_bind_group_layout(
+        None,
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::COMPUTE,
+            (
+                uniform_buffer::<DeriveData>(false), // derive_data
+                texture_2d_array(TextureSampleType::Float { filterable: true }), // source_atlas
+            ),
+        ),
+    )
+}
+
 pub(crate) struct GpuPreprocessor {
     pub(crate) ready_tasks: VecDeque<PreprocessTask>,
     pub(crate) processing_tasks: Vec<ProcessingTask>,
+    /// The model is extracted every frame, since its height range may be calibrated from the source data.
+    pub(crate) model: Option<TerrainModel>,
 }
 
 impl GpuPreprocessor {
@@ -90,6 +163,7 @@ impl GpuPreprocessor {
         Self {
             ready_tasks: default(),
             processing_tasks: vec![],
+            model: None,
         }
     }
 
@@ -104,15 +178,17 @@ impl GpuPreprocessor {
 
     pub(crate) fn extract(
         mut gpu_preprocessors: ResMut<TerrainComponents<GpuPreprocessor>>,
-        preprocessors: Extract<Query<(Entity, &Preprocessor)>>,
+        preprocessors: Extract<Query<(Entity, &Preprocessor, &TileAtlas)>>,
     ) {
-        for (terrain, preprocessor) in preprocessors.iter() {
+        for (terrain, preprocessor, tile_atlas) in preprocessors.iter() {
             let gpu_preprocessor = gpu_preprocessors.get_mut(&terrain).unwrap();
 
+            gpu_preprocessor.model = Some(tile_atlas.model.clone());
+
             // Todo: mem take using &mut world?
             gpu_preprocessor
                 .ready_tasks
-                .extend(preprocessor.ready_tasks.clone().into_iter());
+                .extend(preprocessor.ready_tasks.clone());
         }
     }
 
@@ -137,8 +213,24 @@ impl GpuPreprocessor {
 
             while !gpu_preprocessor.ready_tasks.is_empty() {
                 let task = gpu_preprocessor.ready_tasks.back().unwrap();
-                let attachment =
-                    &mut gpu_tile_atlas.attachments[task.tile.attachment_index as usize];
+
+                let source_view = match &task.task_type {
+                    PreprocessTaskType::Derive {
+                        source_attachment_index,
+                        ..
+                    } => {
+                        let source = gpu_tile_atlas.attachment(*source_attachment_index);
+
+                        Some(source.atlas_texture.create_view(&TextureViewDescriptor {
+                            format: Some(source.buffer_info.format.processing_format()),
+                            dimension: Some(TextureViewDimension::D2Array),
+                            ..default()
+                        }))
+                    }
+                    _ => None,
+                };
+
+                let attachment = gpu_tile_atlas.attachment_mut(task.tile.attachment_index);
 
                 if let Some(section_index) = attachment.reserve_write_slot(task.tile) {
                     let task = gpu_preprocessor.ready_tasks.pop_back().unwrap();
@@ -148,6 +240,7 @@ impl GpuPreprocessor {
                             tile_data,
                             top_left,
                             bottom_right,
+                            nodata_value,
                         } => {
                             let tile_data = images.get(tile_data).unwrap();
 
@@ -158,6 +251,7 @@ impl GpuPreprocessor {
                                     tile: task.tile.into(),
                                     top_left: *top_left,
                                     bottom_right: *bottom_right,
+                                    nodata_value: *nodata_value,
                                     tile_index: section_index,
                                 },
                                 BufferUsages::UNIFORM,
@@ -173,6 +267,46 @@ impl GpuPreprocessor {
                                 )),
                             ))
                         }
+                        PreprocessTaskType::Generate {
+                            noise,
+                            top_left,
+                            bottom_right,
+                            is_spherical,
+                        } => {
+                            let (kind, warp_strength) = match noise.kind {
+                                NoiseKind::Fbm => (0, 0.0),
+                                NoiseKind::Ridged => (1, 0.0),
+                                NoiseKind::Warped { strength } => (2, strength),
+                            };
+
+                            let generate_buffer = StaticBuffer::create(
+                                format!("{}_generate_buffer", attachment.name).as_str(),
+                                &device,
+                                &GenerateData {
+                                    tile: task.tile.into(),
+                                    top_left: *top_left,
+                                    bottom_right: *bottom_right,
+                                    kind,
+                                    seed: noise.seed,
+                                    octaves: noise.octaves,
+                                    frequency: noise.frequency,
+                                    lacunarity: noise.lacunarity,
+                                    gain: noise.gain,
+                                    amplitude: noise.amplitude,
+                                    offset: noise.offset,
+                                    warp_strength,
+                                    is_spherical: *is_spherical as u32,
+                                    tile_index: section_index,
+                                },
+                                BufferUsages::UNIFORM,
+                            );
+
+                            Some(device.create_bind_group(
+                                format!("{}_generate_bind_group", attachment.name).as_str(),
+                                &create_generate_layout(&device),
+                                &BindGroupEntries::single(&generate_buffer),
+                            ))
+                        }
                         PreprocessTaskType::Stitch { neighbour_tiles } => {
                             let stitch_buffer = StaticBuffer::create(
                                 format!("{}_stitch_buffer", attachment.name).as_str(),
@@ -209,6 +343,60 @@ impl GpuPreprocessor {
                                 &BindGroupEntries::single(&downsample_buffer),
                             ))
                         }
+                        PreprocessTaskType::Fill {
+                            parent_tile,
+                            fill_radius,
+                            fill_from_parent,
+                        } => {
+                            let fill_buffer = StaticBuffer::create(
+                                format!("{}_fill_buffer", attachment.name).as_str(),
+                                &device,
+                                &FillData {
+                                    tile: task.tile.into(),
+                                    parent_tile: *parent_tile,
+                                    fill_radius: *fill_radius,
+                                    fill_from_parent: *fill_from_parent as u32,
+                                    tile_index: section_index,
+                                },
+                                BufferUsages::UNIFORM,
+                            );
+
+                            Some(device.create_bind_group(
+                                format!("{}_fill_bind_group", attachment.name).as_str(),
+                                &create_fill_layout(&device),
+                                &BindGroupEntries::single(&fill_buffer),
+                            ))
+                        }
+                        PreprocessTaskType::Derive { derived, .. } => {
+                            let model = gpu_preprocessor.model.as_ref().unwrap();
+
+                            let derive_buffer = StaticBuffer::create(
+                                format!("{}_derive_buffer", attachment.name).as_str(),
+                                &device,
+                                &DeriveData {
+                                    tile: task.tile.into(),
+                                    world_from_local: Mat3::from_mat4(
+                                        model.world_from_local().as_mat4(),
+                                    ),
+                                    kind: derived.id(),
+                                    parameter: derived.parameter(),
+                                    is_spherical: model.is_spherical() as u32,
+                                    min_height: model.min_height,
+                                    max_height: model.max_height,
+                                    tile_index: section_index,
+                                },
+                                BufferUsages::UNIFORM,
+                            );
+
+                            Some(device.create_bind_group(
+                                format!("{}_derive_bind_group", attachment.name).as_str(),
+                                &create_derive_layout(&device),
+                                &BindGroupEntries::sequential((
+                                    &derive_buffer,
+                                    source_view.as_ref().unwrap(),
+                                )),
+                            ))
+                        }
                         _ => break,
                     };
 
@@ -0,0 +1,248 @@
+use crate::{
+    math::TerrainModel,
+    preprocess::preprocessor::{DerivedDataset, PreprocessDataset, Preprocessor, SphericalDataset},
+    terrain::TerrainConfig,
+    terrain_data::{tile_atlas::TileAtlas, AttachmentConfig},
+};
+use anyhow::Result;
+use bevy::{math::DVec3, prelude::*};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// The shape of the terrain model of a [`PreprocessJob`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum JobModel {
+    Planar {
+        position: DVec3,
+        side_length: f64,
+        /// The count of root tiles in x and z direction, see [`TerrainModel::planar_rectangle`].
+        #[serde(default = "single_root")]
+        root_count: UVec2,
+        min_height: f32,
+        max_height: f32,
+    },
+    Sphere {
+        position: DVec3,
+        radius: f64,
+        min_height: f32,
+        max_height: f32,
+    },
+    Ellipsoid {
+        position: DVec3,
+        major_axis: f64,
+        minor_axis: f64,
+        min_height: f32,
+        max_height: f32,
+    },
+}
+
+fn single_root() -> UVec2 {
+    UVec2::ONE
+}
+
+impl JobModel {
+    pub fn model(&self) -> TerrainModel {
+        match *self {
+            JobModel::Planar {
+                position,
+                side_length,
+                root_count,
+                min_height,
+                max_height,
+            } => TerrainModel::planar_rectangle(
+                position,
+                side_length,
+                root_count,
+                min_height,
+                max_height,
+            ),
+            JobModel::Sphere {
+                position,
+                radius,
+                min_height,
+                max_height,
+            } => TerrainModel::sphere(position, radius, min_height, max_height),
+            JobModel::Ellipsoid {
+                position,
+                major_axis,
+                minor_axis,
+                min_height,
+                max_height,
+            } => TerrainModel::ellipsoid(position, major_axis, minor_axis, min_height, max_height),
+        }
+    }
+}
+
+/// A step of a [`PreprocessJob`], which writes the data of one attachment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum JobDataset {
+    /// See [`Preprocessor::preprocess_tile`].
+    Tile(PreprocessDataset),
+    /// See [`Preprocessor::preprocess_spherical`].
+    Spherical(SphericalDataset),
+    /// See [`Preprocessor::derive_attachment`].
+    Derived(DerivedDataset),
+}
+
+/// A declarative description of the preprocessing of a terrain.
+///
+/// Jobs are stored as RON files, so that preprocessing pipelines can be versioned as data
+/// and run repeatedly with [`Preprocessor::from_job`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PreprocessJob {
+    /// The path to the terrain folder inside the assets directory.
+    pub path: String,
+    pub lod_count: u32,
+    #[serde(default = "default_atlas_size")]
+    pub atlas_size: u32,
+    pub model: JobModel,
+    pub attachments: Vec<AttachmentConfig>,
+    /// The attachments, whose previous data is removed before preprocessing.
+    #[serde(default)]
+    pub clear_attachments: Vec<u32>,
+    /// The datasets, which are processed in order.
+    pub datasets: Vec<JobDataset>,
+}
+
+fn default_atlas_size() -> u32 {
+    TerrainConfig::default().atlas_size
+}
+
+impl PreprocessJob {
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let job = fs::read_to_string(path)?;
+        Ok(ron::from_str(&job)?)
+    }
+
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let job = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        fs::write(path, job)?;
+        Ok(())
+    }
+
+    /// The config of the terrain, which is used to create its [`TileAtlas`].
+    pub fn terrain_config(&self) -> TerrainConfig {
+        TerrainConfig {
+            lod_count: self.lod_count,
+            model: self.model.model(),
+            atlas_size: self.atlas_size,
+            path: self.path.clone(),
+            attachments: self.attachments.clone(),
+            ..default()
+        }
+    }
+}
+
+impl Preprocessor {
+    /// Creates a preprocessor, which runs all steps of the job.
+    ///
+    /// The tile atlas has to be created from the [`PreprocessJob::terrain_config`] of the same job.
+    pub fn from_job(
+        job: &PreprocessJob,
+        asset_server: &AssetServer,
+        tile_atlas: &mut TileAtlas,
+    ) -> Self {
+        let mut preprocessor = Preprocessor::new();
+
+        for &attachment_index in &job.clear_attachments {
+            preprocessor = preprocessor.clear_attachment(attachment_index, tile_atlas);
+        }
+
+        for dataset in job.datasets.iter().cloned() {
+            preprocessor = match dataset {
+                JobDataset::Tile(dataset) => {
+                    preprocessor.preprocess_tile(dataset, asset_server, tile_atlas)
+                }
+                JobDataset::Spherical(dataset) => {
+                    preprocessor.preprocess_spherical(dataset, asset_server, tile_atlas)
+                }
+                JobDataset::Derived(dataset) => preprocessor.derive_attachment(dataset, tile_atlas),
+            };
+        }
+
+        preprocessor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::preprocessor::PreprocessTaskType;
+
+    #[test]
+    fn planar_job_describes_the_terrain_of_the_examples() {
+        let job = PreprocessJob::load_file("assets/jobs/preprocess_planar.ron").unwrap();
+        let config = job.terrain_config();
+
+        assert_eq!(config.path, "terrains/planar");
+        assert_eq!(config.lod_count, 4);
+        assert_eq!(config.atlas_size, TerrainConfig::default().atlas_size);
+        assert_eq!(config.model.root_count(), UVec2::ONE);
+        assert_eq!(
+            config
+                .attachments
+                .iter()
+                .map(|attachment| attachment.name.as_str())
+                .collect::<Vec<_>>(),
+            ["height", "albedo"]
+        );
+        assert_eq!(job.datasets.len(), 2);
+    }
+
+    #[test]
+    fn job_runs_its_datasets_in_order() {
+        let job: PreprocessJob = ron::from_str(
+            r#"(
+                path: "../target/job_test",
+                lod_count: 2,
+                model: Planar(
+                    position: (0.0, 0.0, 0.0),
+                    side_length: 1.0,
+                    root_count: (2, 1),
+                    min_height: 0.0,
+                    max_height: 1.0,
+                ),
+                attachments: [
+                    (name: "height", format: R16),
+                    (name: "slope", format: R16),
+                ],
+                datasets: [
+                    Tile((source: Noise(()), lod_range: (start: 0, end: 2))),
+                    Derived((
+                        attachment_index: 1,
+                        source_attachment_index: 0,
+                        derived: Slope,
+                        lod_range: (start: 0, end: 2),
+                    )),
+                ],
+            )"#,
+        )
+        .unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()));
+        let asset_server = app.world().resource::<AssetServer>().clone();
+
+        let mut tile_atlas = TileAtlas::new(&job.terrain_config());
+        assert_eq!(tile_atlas.model.root_count(), UVec2::new(2, 1));
+
+        let preprocessor = Preprocessor::from_job(&job, &asset_server, &mut tile_atlas);
+
+        // the noise covers the first root tile, from which the slope is derived afterwards
+        let tasks = &preprocessor.task_queue;
+        assert!(matches!(
+            tasks.front().unwrap().task_type,
+            PreprocessTaskType::Generate { .. }
+        ));
+        let first_derive = tasks
+            .iter()
+            .position(|task| matches!(task.task_type, PreprocessTaskType::Derive { .. }))
+            .unwrap();
+        assert!(tasks
+            .iter()
+            .take(first_derive)
+            .all(|task| task.tile.attachment_index == 0));
+        assert_eq!(tile_atlas.state.existing_tiles.len(), 5);
+    }
+}
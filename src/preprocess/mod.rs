@@ -2,11 +2,15 @@ use crate::{
     formats::tiff::TiffLoader,
     preprocess::{
         gpu_preprocessor::{
-            create_downsample_layout, create_split_layout, create_stitch_layout, GpuPreprocessor,
+            create_derive_layout, create_downsample_layout, create_fill_layout,
+            create_generate_layout, create_split_layout, create_stitch_layout, GpuPreprocessor,
         },
         preprocessor::{preprocessor_load_tile, select_ready_tasks, PreprocessTaskType},
     },
-    shaders::{load_preprocess_shaders, DOWNSAMPLE_SHADER, SPLIT_SHADER, STITCH_SHADER},
+    shaders::{
+        load_preprocess_shaders, DERIVE_SHADER, DOWNSAMPLE_SHADER, FILL_SHADER, GENERATE_SHADER,
+        SPLIT_SHADER, STITCH_SHADER,
+    },
     terrain::TerrainComponents,
     terrain_data::gpu_tile_atlas::{create_attachment_layout, GpuTileAtlas},
 };
@@ -22,7 +26,9 @@ use bevy::{
 };
 
 pub mod gpu_preprocessor;
+pub mod job;
 pub mod preprocessor;
+pub mod validator;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
 pub struct TerrainPreprocessLabel;
@@ -35,6 +41,9 @@ bitflags::bitflags! {
         const SPLIT      = 1 << 1;
         const STITCH     = 1 << 2;
         const DOWNSAMPLE = 1 << 3;
+        const DERIVE     = 1 << 4;
+        const FILL       = 1 << 5;
+        const GENERATE   = 1 << 6;
     }
 }
 
@@ -42,17 +51,30 @@ pub(crate) struct TerrainPreprocessItem {
     split_pipeline: CachedComputePipelineId,
     stitch_pipeline: CachedComputePipelineId,
     downsample_pipeline: CachedComputePipelineId,
+    derive_pipeline: CachedComputePipelineId,
+    fill_pipeline: CachedComputePipelineId,
+    generate_pipeline: CachedComputePipelineId,
 }
 
 impl TerrainPreprocessItem {
     fn pipelines<'a>(
         &'a self,
         pipeline_cache: &'a PipelineCache,
-    ) -> Option<(&ComputePipeline, &ComputePipeline, &ComputePipeline)> {
+    ) -> Option<(
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+    )> {
         Some((
             pipeline_cache.get_compute_pipeline(self.split_pipeline)?,
             pipeline_cache.get_compute_pipeline(self.stitch_pipeline)?,
             pipeline_cache.get_compute_pipeline(self.downsample_pipeline)?,
+            pipeline_cache.get_compute_pipeline(self.derive_pipeline)?,
+            pipeline_cache.get_compute_pipeline(self.fill_pipeline)?,
+            pipeline_cache.get_compute_pipeline(self.generate_pipeline)?,
         ))
     }
 
@@ -67,9 +89,15 @@ pub struct TerrainPreprocessPipelines {
     split_layout: BindGroupLayout,
     stitch_layout: BindGroupLayout,
     downsample_layout: BindGroupLayout,
+    derive_layout: BindGroupLayout,
+    fill_layout: BindGroupLayout,
+    generate_layout: BindGroupLayout,
     split_shader: Handle<Shader>,
     stitch_shader: Handle<Shader>,
     downsample_shader: Handle<Shader>,
+    derive_shader: Handle<Shader>,
+    fill_shader: Handle<Shader>,
+    generate_shader: Handle<Shader>,
 }
 
 impl FromWorld for TerrainPreprocessPipelines {
@@ -81,19 +109,31 @@ impl FromWorld for TerrainPreprocessPipelines {
         let split_layout = create_split_layout(device);
         let stitch_layout = create_stitch_layout(device);
         let downsample_layout = create_downsample_layout(device);
+        let derive_layout = create_derive_layout(device);
+        let fill_layout = create_fill_layout(device);
+        let generate_layout = create_generate_layout(device);
 
         let split_shader = asset_server.load(SPLIT_SHADER);
         let stitch_shader = asset_server.load(STITCH_SHADER);
         let downsample_shader = asset_server.load(DOWNSAMPLE_SHADER);
+        let derive_shader = asset_server.load(DERIVE_SHADER);
+        let fill_shader = asset_server.load(FILL_SHADER);
+        let generate_shader = asset_server.load(GENERATE_SHADER);
 
         Self {
             attachment_layout,
             split_layout,
             stitch_layout,
             downsample_layout,
+            derive_layout,
+            fill_layout,
+            generate_layout,
             split_shader,
             stitch_shader,
             downsample_shader,
+            derive_shader,
+            fill_shader,
+            generate_shader,
         }
     }
 }
@@ -126,6 +166,21 @@ impl SpecializedComputePipeline for TerrainPreprocessPipelines {
             shader = self.downsample_shader.clone();
             entry_point = "downsample".into();
         }
+        if key.contains(TerrainPreprocessPipelineKey::DERIVE) {
+            layout = vec![self.attachment_layout.clone(), self.derive_layout.clone()];
+            shader = self.derive_shader.clone();
+            entry_point = "derive".into();
+        }
+        if key.contains(TerrainPreprocessPipelineKey::FILL) {
+            layout = vec![self.attachment_layout.clone(), self.fill_layout.clone()];
+            shader = self.fill_shader.clone();
+            entry_point = "fill".into();
+        }
+        if key.contains(TerrainPreprocessPipelineKey::GENERATE) {
+            layout = vec![self.attachment_layout.clone(), self.generate_layout.clone()];
+            shader = self.generate_shader.clone();
+            entry_point = "generate".into();
+        }
 
         ComputePipelineDescriptor {
             label: Some("terrain_preprocess_pipeline".into()),
@@ -157,8 +212,14 @@ impl render_graph::Node for TerrainPreprocessNode {
                 device.create_command_encoder(&CommandEncoderDescriptor::default());
 
             for (&terrain, preprocess_item) in preprocess_items.iter() {
-                let Some((split_pipeline, stitch_pipeline, downsample_pipeline)) =
-                    preprocess_item.pipelines(pipeline_cache)
+                let Some((
+                    split_pipeline,
+                    stitch_pipeline,
+                    downsample_pipeline,
+                    derive_pipeline,
+                    fill_pipeline,
+                    generate_pipeline,
+                )) = preprocess_item.pipelines(pipeline_cache)
                 else {
                     continue;
                 };
@@ -166,7 +227,7 @@ impl render_graph::Node for TerrainPreprocessNode {
                 let preprocess_data = preprocess_data.get(&terrain).unwrap();
                 let gpu_tile_atlas = gpu_tile_atlases.get(&terrain).unwrap();
 
-                for attachment in &gpu_tile_atlas.attachments {
+                for attachment in gpu_tile_atlas.attachments.iter().flatten() {
                     attachment.copy_tiles_to_write_section(&mut command_encoder);
                 }
 
@@ -175,13 +236,15 @@ impl render_graph::Node for TerrainPreprocessNode {
                         command_encoder.begin_compute_pass(&ComputePassDescriptor::default());
 
                     for task in &preprocess_data.processing_tasks {
-                        let attachment =
-                            &gpu_tile_atlas.attachments[task.task.tile.attachment_index as usize];
+                        let attachment = gpu_tile_atlas.attachment(task.task.tile.attachment_index);
 
                         let pipeline = match task.task.task_type {
                             PreprocessTaskType::Split { .. } => split_pipeline,
                             PreprocessTaskType::Stitch { .. } => stitch_pipeline,
                             PreprocessTaskType::Downsample { .. } => downsample_pipeline,
+                            PreprocessTaskType::Derive { .. } => derive_pipeline,
+                            PreprocessTaskType::Fill { .. } => fill_pipeline,
+                            PreprocessTaskType::Generate { .. } => generate_pipeline,
                             _ => continue,
                         };
 
@@ -196,7 +259,7 @@ impl render_graph::Node for TerrainPreprocessNode {
                     }
                 }
 
-                for attachment in &gpu_tile_atlas.attachments {
+                for attachment in gpu_tile_atlas.attachments.iter().flatten() {
                     attachment.copy_tiles_from_write_section(&mut command_encoder);
 
                     attachment.download_tiles(&mut command_encoder);
@@ -240,6 +303,21 @@ pub(crate) fn queue_terrain_preprocess(
             &preprocess_pipelines,
             TerrainPreprocessPipelineKey::DOWNSAMPLE,
         );
+        let derive_pipeline = pipelines.specialize(
+            &pipeline_cache,
+            &preprocess_pipelines,
+            TerrainPreprocessPipelineKey::DERIVE,
+        );
+        let fill_pipeline = pipelines.specialize(
+            &pipeline_cache,
+            &preprocess_pipelines,
+            TerrainPreprocessPipelineKey::FILL,
+        );
+        let generate_pipeline = pipelines.specialize(
+            &pipeline_cache,
+            &preprocess_pipelines,
+            TerrainPreprocessPipelineKey::GENERATE,
+        );
 
         preprocess_items.insert(
             terrain,
@@ -247,6 +325,9 @@ pub(crate) fn queue_terrain_preprocess(
                 split_pipeline,
                 stitch_pipeline,
                 downsample_pipeline,
+                derive_pipeline,
+                fill_pipeline,
+                generate_pipeline,
             },
         );
     }
@@ -2,18 +2,23 @@ use crate::{
     math::TileCoordinate,
     terrain_data::{
         tile_atlas::{AtlasTile, AtlasTileAttachment, TileAtlas},
-        AttachmentFormat,
+        AttachmentFormat, INVALID_ATLAS_INDEX,
     },
     util::CollectArray,
 };
-use bevy::{prelude::*, render::texture::ImageSampler};
-use itertools::{iproduct, Itertools};
-use std::{
-    collections::VecDeque,
-    fs,
-    ops::{DerefMut, Range},
-    time::Instant,
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+    utils::{HashMap, HashSet},
 };
+use itertools::{iproduct, Itertools};
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fs, iter, mem, ops::Range, slice, time::Instant};
 
 pub fn reset_directory(directory: &str) {
     let _ = fs::remove_file(format!("{directory}/../../config.tc"));
@@ -23,38 +28,291 @@ pub fn reset_directory(directory: &str) {
 
 pub(crate) struct LoadingTile {
     id: AssetId<Image>,
+    /// Whether the image is shared with the caller and has to be copied, before it is modified.
+    shared: bool,
+    attachment_index: u32,
     format: AttachmentFormat,
+    nodata_value: Option<f32>,
+}
+
+/// The kind of fractal noise evaluated by a [`NoiseConfig`].
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum NoiseKind {
+    /// Fractal brownian motion, which sums octaves of gradient noise.
+    Fbm,
+    /// Sharp ridges created by folding each octave of the noise.
+    Ridged,
+    /// Fractal brownian motion, whose domain is distorted by another noise.
+    Warped { strength: f32 },
+}
+
+/// Configures the procedural noise, which is evaluated in the local space of the terrain model.
+///
+/// Spherical models sample the noise on the unit sphere and planar ones on the unit square,
+/// so the result is seamless across tile and cube face boundaries.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    pub kind: NoiseKind,
+    /// Deterministically selects one of the possible noise patterns.
+    pub seed: u32,
+    /// The frequency of the first octave in cycles per unit of the local space.
+    pub frequency: f32,
+    pub octaves: u32,
+    /// The frequency multiplier between consecutive octaves.
+    pub lacunarity: f32,
+    /// The amplitude multiplier between consecutive octaves.
+    pub gain: f32,
+    /// The value is computed as `offset + amplitude * noise` and clamped to `0..1`.
+    pub amplitude: f32,
+    pub offset: f32,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Fbm,
+            seed: 0,
+            frequency: 4.0,
+            octaves: 8,
+            lacunarity: 2.0,
+            gain: 0.5,
+            amplitude: 0.5,
+            offset: 0.5,
+        }
+    }
+}
+
+/// The source of the data of a dataset.
+///
+/// The in-memory sources can not be stored in a [`PreprocessJob`](super::job::PreprocessJob).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DatasetSource {
+    /// An image asset, which is split into tiles.
+    Path(String),
+    /// Procedural noise, which is evaluated at each lod.
+    Noise(NoiseConfig),
+    /// An existing image, which is split into tiles.
+    /// The image is copied before it is converted to the processing format of the attachment,
+    /// so the image itself is not modified.
+    #[serde(skip)]
+    Image(Handle<Image>),
+    /// A grid of heights indexed by `[y, x]`, which is only supported for the height attachment.
+    /// The values are normalized to the measured value range like float images.
+    #[serde(skip)]
+    Heights(Array2<f32>),
+    /// A grid of RGBA colours indexed by `[y, x]`.
+    #[serde(skip)]
+    Colors(Array2<[u8; 4]>),
+}
+
+impl DatasetSource {
+    /// Returns the image to split into tiles or `None` for procedural sources.
+    fn image(&self, asset_server: &AssetServer) -> Option<Handle<Image>> {
+        match self {
+            DatasetSource::Path(path) => Some(asset_server.load(path)),
+            DatasetSource::Noise(_) => None,
+            DatasetSource::Image(image) => Some(image.clone()),
+            DatasetSource::Heights(heights) => Some(asset_server.add(array_image(
+                heights,
+                TextureFormat::R32Float,
+                |&value| f32::to_le_bytes(value),
+            ))),
+            DatasetSource::Colors(colors) => Some(asset_server.add(array_image(
+                colors,
+                TextureFormat::Rgba8UnormSrgb,
+                |&color| color,
+            ))),
+        }
+    }
+}
+
+fn array_image<T, const N: usize>(
+    array: &Array2<T>,
+    format: TextureFormat,
+    bytes: impl Fn(&T) -> [u8; N],
+) -> Image {
+    let (height, width) = array.dim();
+
+    Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        array.iter().flat_map(bytes).collect(),
+        format,
+        RenderAssetUsages::default(),
+    )
+}
+
+impl Default for DatasetSource {
+    fn default() -> Self {
+        Self::Path("".to_string())
+    }
+}
+
+impl From<String> for DatasetSource {
+    fn from(path: String) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&str> for DatasetSource {
+    fn from(path: &str) -> Self {
+        Self::Path(path.to_string())
+    }
+}
+
+impl From<NoiseConfig> for DatasetSource {
+    fn from(noise: NoiseConfig) -> Self {
+        Self::Noise(noise)
+    }
+}
+
+impl From<Handle<Image>> for DatasetSource {
+    fn from(image: Handle<Image>) -> Self {
+        Self::Image(image)
+    }
+}
+
+impl From<Array2<f32>> for DatasetSource {
+    fn from(heights: Array2<f32>) -> Self {
+        Self::Heights(heights)
+    }
+}
+
+impl From<Array2<[u8; 4]>> for DatasetSource {
+    fn from(colors: Array2<[u8; 4]>) -> Self {
+        Self::Colors(colors)
+    }
+}
+
+/// Describes how missing data (voids) in a dataset is detected and filled.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodataConfig {
+    /// The source value, which marks missing data.
+    /// Float sources use physical units, all other sources the normalized range `0..1`.
+    pub value: f32,
+    /// Voids are inpainted from the valid pixels within this radius (in pixels).
+    pub fill_radius: u32,
+    /// Voids, which can not be inpainted, are filled with the data of the parent tile.
+    pub fill_from_parent: bool,
+    /// Writes a mask (1 for real data, 0 for voids) into this attachment, before filling.
+    pub mask_attachment_index: Option<u32>,
 }
 
+impl Default for NodataConfig {
+    fn default() -> Self {
+        Self {
+            value: 0.0,
+            fill_radius: 4,
+            fill_from_parent: true,
+            mask_attachment_index: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SphericalDataset {
     pub attachment_index: u32,
-    pub paths: Vec<String>,
+    /// The source of each of the six sides.
+    pub sources: Vec<DatasetSource>,
     pub lod_range: Range<u32>,
+    #[serde(default)]
+    pub nodata: Option<NodataConfig>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PreprocessDataset {
     pub attachment_index: u32,
-    pub path: String,
+    pub source: DatasetSource,
     pub side: u32,
+    /// The uv coordinates of the dataset on the side, which range up to the root count
+    /// on rectangular planar terrains.
     pub top_left: Vec2,
     pub bottom_right: Vec2,
     pub lod_range: Range<u32>,
+    pub nodata: Option<NodataConfig>,
+}
+
+/// The kind of data [`Preprocessor::derive_attachment`] computes from the height attachment.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum DerivedAttachment {
+    /// Normals in the tangent frame used by `sample_normal` (Rgba8).
+    TangentNormal,
+    /// Normals in world space, including the rotation of the terrain model (Rgba8).
+    WorldNormal,
+    /// Angle between the surface and the base shape, where 0 is flat and 1 is vertical (R16).
+    Slope,
+    /// Laplacian of the height, mapped from `-1/scale..1/scale` to `0..1` (R16).
+    Curvature { scale: f32 },
+    /// Horizon based ambient occlusion, traced in eight directions up to `radius` pixels (R16).
+    /// Directions leaving the tile are clamped to its border.
+    AmbientOcclusion { radius: u32 },
+    /// 1 where the source contains data and 0 for voids (any format).
+    Mask,
+}
+
+impl DerivedAttachment {
+    pub(crate) fn id(&self) -> u32 {
+        match self {
+            DerivedAttachment::TangentNormal => 0,
+            DerivedAttachment::WorldNormal => 1,
+            DerivedAttachment::Slope => 2,
+            DerivedAttachment::Curvature { .. } => 3,
+            DerivedAttachment::AmbientOcclusion { .. } => 4,
+            DerivedAttachment::Mask => 5,
+        }
+    }
+
+    pub(crate) fn parameter(&self) -> f32 {
+        match *self {
+            DerivedAttachment::Curvature { scale } => scale,
+            DerivedAttachment::AmbientOcclusion { radius } => radius as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn supports_format(&self, format: AttachmentFormat) -> bool {
+        match self {
+            DerivedAttachment::TangentNormal | DerivedAttachment::WorldNormal => {
+                format == AttachmentFormat::Rgba8
+            }
+            DerivedAttachment::Mask => true,
+            _ => format == AttachmentFormat::R16,
+        }
+    }
+}
+
+/// Derives the data of one attachment from the height stored in another one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DerivedDataset {
+    pub attachment_index: u32,
+    pub source_attachment_index: u32,
+    pub derived: DerivedAttachment,
+    pub lod_range: Range<u32>,
 }
 
 impl Default for PreprocessDataset {
     fn default() -> Self {
         Self {
             attachment_index: 0,
-            path: "".to_string(),
+            source: default(),
             side: 0,
             top_left: Vec2::splat(0.0),
             bottom_right: Vec2::splat(1.0),
             lod_range: 0..1,
+            nodata: None,
         }
     }
 }
 
 impl PreprocessDataset {
+    /// The tiles of the lod covered by the dataset in Z-order, so that consecutive tasks share most of their tiles.
     fn overlapping_tiles(&self, lod: u32) -> impl Iterator<Item = TileCoordinate> + '_ {
         let tile_count = TileCoordinate::count(lod);
 
@@ -63,6 +321,7 @@ impl PreprocessDataset {
 
         iproduct!(lower.x..upper.x, lower.y..upper.y)
             .map(move |(x, y)| TileCoordinate::new(self.side, lod, x, y))
+            .sorted_by_key(|tile_coordinate| tile_coordinate.z_order())
     }
 }
 
@@ -72,6 +331,13 @@ pub(crate) enum PreprocessTaskType {
         tile_data: Handle<Image>,
         top_left: Vec2,
         bottom_right: Vec2,
+        nodata_value: f32,
+    },
+    Generate {
+        noise: NoiseConfig,
+        top_left: Vec2,
+        bottom_right: Vec2,
+        is_spherical: bool,
     },
     Stitch {
         neighbour_tiles: [AtlasTile; 8],
@@ -79,6 +345,15 @@ pub(crate) enum PreprocessTaskType {
     Downsample {
         child_tiles: [AtlasTile; 4],
     },
+    Fill {
+        parent_tile: AtlasTile,
+        fill_radius: u32,
+        fill_from_parent: bool,
+    },
+    Derive {
+        source_attachment_index: u32,
+        derived: DerivedAttachment,
+    },
     Save,
     Barrier,
 }
@@ -95,10 +370,15 @@ impl PreprocessTask {
     fn is_ready(&self, asset_server: &AssetServer, tile_atlas: &TileAtlas) -> bool {
         match &self.task_type {
             PreprocessTaskType::Split { tile_data, .. } => {
-                asset_server.is_loaded_with_dependencies(tile_data)
+                // images created in memory are not tracked by the asset server
+                asset_server.get_load_state(tile_data).is_none()
+                    || asset_server.is_loaded_with_dependencies(tile_data)
             }
+            PreprocessTaskType::Generate { .. } => true,
             PreprocessTaskType::Stitch { .. } => true,
             PreprocessTaskType::Downsample { .. } => true,
+            PreprocessTaskType::Fill { .. } => true,
+            PreprocessTaskType::Derive { .. } => true,
             PreprocessTaskType::Barrier => {
                 tile_atlas.state.download_slots == tile_atlas.state.max_download_slots
             }
@@ -112,12 +392,21 @@ impl PreprocessTask {
             PreprocessTaskType::Split { .. } => {
                 println!("Splitting tile: {}", self.tile.coordinate)
             }
+            PreprocessTaskType::Generate { .. } => {
+                println!("Generating tile: {}", self.tile.coordinate)
+            }
             PreprocessTaskType::Stitch { .. } => {
                 println!("Stitching tile: {}", self.tile.coordinate)
             }
             PreprocessTaskType::Downsample { .. } => {
                 println!("Downsampling tile: {}", self.tile.coordinate)
             }
+            PreprocessTaskType::Fill { .. } => {
+                println!("Filling tile: {}", self.tile.coordinate)
+            }
+            PreprocessTaskType::Derive { .. } => {
+                println!("Deriving tile: {}", self.tile.coordinate)
+            }
             PreprocessTaskType::Save => {
                 println!("Started saving tile: {}", self.tile.coordinate)
             }
@@ -127,22 +416,30 @@ impl PreprocessTask {
 
     fn barrier() -> Self {
         Self {
-            tile: default(),
+            tile: AtlasTile::new(TileCoordinate::INVALID, INVALID_ATLAS_INDEX).attachment(0),
             task_type: PreprocessTaskType::Barrier,
         }
     }
 
+    /// The tile is only resolved to an atlas index, once the task is ready,
+    /// so that preprocessing is not limited by the size of the atlas.
+    fn tile(
+        tile_coordinate: TileCoordinate,
+        tile_atlas: &mut TileAtlas,
+        attachment_index: u32,
+    ) -> AtlasTileAttachment {
+        tile_atlas.state.existing_tiles.insert(tile_coordinate);
+
+        AtlasTile::new(tile_coordinate, INVALID_ATLAS_INDEX).attachment(attachment_index)
+    }
+
     fn save(
         tile_coordinate: TileCoordinate,
         tile_atlas: &mut TileAtlas,
-        dataset: &PreprocessDataset,
+        attachment_index: u32,
     ) -> Self {
-        let tile = tile_atlas
-            .get_or_allocate_tile(tile_coordinate)
-            .attachment(dataset.attachment_index);
-
         Self {
-            tile,
+            tile: Self::tile(tile_coordinate, tile_atlas, attachment_index),
             task_type: PreprocessTaskType::Save,
         }
     }
@@ -153,37 +450,46 @@ impl PreprocessTask {
         dataset: &PreprocessDataset,
         tile_data: Handle<Image>,
     ) -> Self {
-        let tile = tile_atlas
-            .get_or_allocate_tile(tile_coordinate)
-            .attachment(dataset.attachment_index);
-
         Self {
-            tile,
+            tile: Self::tile(tile_coordinate, tile_atlas, dataset.attachment_index),
             task_type: PreprocessTaskType::Split {
                 tile_data,
                 top_left: dataset.top_left,
                 bottom_right: dataset.bottom_right,
+                nodata_value: dataset.nodata.as_ref().map_or(0.0, |nodata| nodata.value),
             },
         }
     }
 
-    fn stitch(
+    fn generate(
         tile_coordinate: TileCoordinate,
         tile_atlas: &mut TileAtlas,
         dataset: &PreprocessDataset,
+        noise: NoiseConfig,
+    ) -> Self {
+        Self {
+            tile: Self::tile(tile_coordinate, tile_atlas, dataset.attachment_index),
+            task_type: PreprocessTaskType::Generate {
+                noise,
+                top_left: dataset.top_left,
+                bottom_right: dataset.bottom_right,
+                is_spherical: tile_atlas.model.is_spherical(),
+            },
+        }
+    }
+
+    fn stitch(
+        tile_coordinate: TileCoordinate,
+        tile_atlas: &mut TileAtlas,
+        attachment_index: u32,
     ) -> Self {
-        let tile = tile_atlas
-            .get_or_allocate_tile(tile_coordinate)
-            .attachment(dataset.attachment_index);
-
-        let neighbour_tiles = tile
-            .coordinate
-            .neighbours(tile_atlas.model.is_spherical())
-            .map(|coordinate| tile_atlas.get_tile(coordinate))
+        let neighbour_tiles = tile_coordinate
+            .neighbours(&tile_atlas.model)
+            .map(|coordinate| AtlasTile::new(coordinate, INVALID_ATLAS_INDEX))
             .collect_array();
 
         Self {
-            tile,
+            tile: Self::tile(tile_coordinate, tile_atlas, attachment_index),
             task_type: PreprocessTaskType::Stitch { neighbour_tiles },
         }
     }
@@ -193,21 +499,101 @@ impl PreprocessTask {
         tile_atlas: &mut TileAtlas,
         dataset: &PreprocessDataset,
     ) -> Self {
-        let tile = tile_atlas
-            .get_or_allocate_tile(tile_coordinate)
-            .attachment(dataset.attachment_index);
-
-        let child_tiles = tile
-            .coordinate
+        let child_tiles = tile_coordinate
             .children()
-            .map(|coordinate| tile_atlas.get_tile(coordinate))
+            .map(|coordinate| AtlasTile::new(coordinate, INVALID_ATLAS_INDEX))
             .collect_array();
 
         Self {
-            tile,
+            tile: Self::tile(tile_coordinate, tile_atlas, dataset.attachment_index),
             task_type: PreprocessTaskType::Downsample { child_tiles },
         }
     }
+
+    fn fill(
+        tile_coordinate: TileCoordinate,
+        tile_atlas: &mut TileAtlas,
+        dataset: &PreprocessDataset,
+        nodata: &NodataConfig,
+    ) -> Self {
+        // the coarsest tiles of the dataset have no parent to fill from
+        let parent_coordinate = if tile_coordinate.lod > dataset.lod_range.start {
+            tile_coordinate.parent()
+        } else {
+            TileCoordinate::INVALID
+        };
+
+        Self {
+            tile: Self::tile(tile_coordinate, tile_atlas, dataset.attachment_index),
+            task_type: PreprocessTaskType::Fill {
+                parent_tile: AtlasTile::new(parent_coordinate, INVALID_ATLAS_INDEX),
+                fill_radius: nodata.fill_radius,
+                fill_from_parent: nodata.fill_from_parent,
+            },
+        }
+    }
+
+    fn derive(
+        tile_coordinate: TileCoordinate,
+        tile_atlas: &mut TileAtlas,
+        dataset: &DerivedDataset,
+    ) -> Self {
+        Self {
+            tile: Self::tile(tile_coordinate, tile_atlas, dataset.attachment_index),
+            task_type: PreprocessTaskType::Derive {
+                source_attachment_index: dataset.source_attachment_index,
+                derived: dataset.derived,
+            },
+        }
+    }
+
+    fn input_tiles(&self) -> &[AtlasTile] {
+        match &self.task_type {
+            PreprocessTaskType::Stitch { neighbour_tiles } => neighbour_tiles,
+            PreprocessTaskType::Downsample { child_tiles } => child_tiles,
+            PreprocessTaskType::Fill { parent_tile, .. } => slice::from_ref(parent_tile),
+            _ => &[],
+        }
+    }
+
+    fn input_tiles_mut(&mut self) -> &mut [AtlasTile] {
+        match &mut self.task_type {
+            PreprocessTaskType::Stitch { neighbour_tiles } => neighbour_tiles,
+            PreprocessTaskType::Downsample { child_tiles } => child_tiles,
+            PreprocessTaskType::Fill { parent_tile, .. } => slice::from_mut(parent_tile),
+            _ => &mut [],
+        }
+    }
+
+    /// The coordinates of all tiles the task reads from or writes to.
+    fn tile_coordinates(&self) -> impl Iterator<Item = TileCoordinate> + '_ {
+        iter::once(self.tile.coordinate)
+            .chain(self.input_tiles().iter().map(|tile| tile.coordinate))
+            .filter(|&tile_coordinate| tile_coordinate != TileCoordinate::INVALID)
+    }
+
+    /// The coordinates of all existing tiles, which have to be resident to process the task.
+    fn required_tiles<'a>(
+        &'a self,
+        tile_atlas: &'a TileAtlas,
+    ) -> impl Iterator<Item = TileCoordinate> + 'a {
+        self.tile_coordinates()
+            .filter(|tile_coordinate| tile_atlas.state.existing_tiles.contains(tile_coordinate))
+    }
+
+    fn is_resident(&self, tile_atlas: &TileAtlas) -> bool {
+        self.required_tiles(tile_atlas)
+            .all(|tile_coordinate| tile_atlas.state.is_loaded(tile_coordinate))
+    }
+
+    /// Replaces the coordinates of the task with the atlas indices of the resident tiles.
+    fn resolve(&mut self, tile_atlas: &mut TileAtlas) {
+        self.tile.atlas_index = tile_atlas.get_tile(self.tile.coordinate).atlas_index;
+
+        for tile in self.input_tiles_mut() {
+            *tile = tile_atlas.get_tile(tile.coordinate);
+        }
+    }
 }
 
 #[derive(Component)]
@@ -215,39 +601,122 @@ pub struct Preprocessor {
     pub(crate) loading_tiles: Vec<LoadingTile>,
     pub(crate) task_queue: VecDeque<PreprocessTask>,
     pub(crate) ready_tasks: Vec<PreprocessTask>,
+    /// The number of queued tasks, which use each tile.
+    pending_uses: HashMap<TileCoordinate, u32>,
+    /// The tiles used by the tasks in flight, which can not be evicted yet.
+    pinned_tiles: Vec<TileCoordinate>,
 
     pub(crate) start_time: Option<Instant>,
     loaded: bool,
 }
 
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Preprocessor {
     pub fn new() -> Self {
         Self {
             loading_tiles: default(),
             task_queue: default(),
             ready_tasks: default(),
+            pending_uses: default(),
+            pinned_tiles: default(),
             start_time: None,
             loaded: false,
         }
     }
 
+    fn push_task(&mut self, task: PreprocessTask) {
+        for tile_coordinate in task.tile_coordinates() {
+            *self.pending_uses.entry(tile_coordinate).or_default() += 1;
+        }
+
+        self.task_queue.push_back(task);
+    }
+
+    fn pop_task(&mut self) -> PreprocessTask {
+        let task = self.task_queue.pop_front().unwrap();
+
+        for tile_coordinate in task.tile_coordinates() {
+            let uses = self.pending_uses.get_mut(&tile_coordinate).unwrap();
+            *uses -= 1;
+
+            if *uses == 0 {
+                self.pending_uses.remove(&tile_coordinate);
+            }
+        }
+
+        task
+    }
+
+    /// Makes the tiles of the upcoming tasks (up to the next barrier) resident, so that they are loaded in parallel.
+    ///
+    /// Earlier tasks take precedence, thus the tiles of a task are never evicted for the ones of a later task.
+    fn prefetch_tiles(&self, tile_atlas: &mut TileAtlas) {
+        let mut excluded_tiles = HashSet::new();
+
+        let tasks = self
+            .task_queue
+            .iter()
+            .take_while(|task| !matches!(task.task_type, PreprocessTaskType::Barrier))
+            .filter(|task| !matches!(task.task_type, PreprocessTaskType::Save))
+            .take(tile_atlas.state.max_download_slots as usize);
+
+        for task in tasks {
+            let tiles = task.required_tiles(tile_atlas).collect_vec();
+            excluded_tiles.extend(tiles.iter().copied());
+
+            for tile_coordinate in tiles {
+                if !tile_atlas.state.make_resident(
+                    tile_coordinate,
+                    &excluded_tiles,
+                    &self.pending_uses,
+                ) {
+                    return;
+                }
+            }
+        }
+    }
+
     fn split_and_downsample(
         &mut self,
         dataset: &PreprocessDataset,
         asset_server: &AssetServer,
         tile_atlas: &mut TileAtlas,
     ) {
-        let tile_handle = asset_server.load(&dataset.path);
+        let Some(tile_handle) = dataset.source.image(asset_server) else {
+            if let DatasetSource::Noise(noise) = dataset.source {
+                // noise is evaluated directly at each lod instead of downsampling
+                for lod in dataset.lod_range.clone() {
+                    for tile_coordinate in dataset.overlapping_tiles(lod) {
+                        self.push_task(PreprocessTask::generate(
+                            tile_coordinate,
+                            tile_atlas,
+                            dataset,
+                            noise,
+                        ));
+                    }
+                }
+            }
+
+            return;
+        };
 
         self.loading_tiles.push(LoadingTile {
             id: tile_handle.id(),
-            format: tile_atlas.attachments[dataset.attachment_index as usize].format,
+            shared: matches!(dataset.source, DatasetSource::Image(_)),
+            attachment_index: dataset.attachment_index,
+            format: tile_atlas.attachment(dataset.attachment_index).format,
+            nodata_value: dataset.nodata.as_ref().map(|nodata| nodata.value),
         });
 
         let mut lods = dataset.lod_range.clone().rev();
 
         for tile_coordinate in dataset.overlapping_tiles(lods.next().unwrap()) {
-            self.task_queue.push_back(PreprocessTask::split(
+            self.push_task(PreprocessTask::split(
                 tile_coordinate,
                 tile_atlas,
                 dataset,
@@ -256,10 +725,10 @@ impl Preprocessor {
         }
 
         for lod in lods {
-            self.task_queue.push_back(PreprocessTask::barrier());
+            self.push_task(PreprocessTask::barrier());
 
             for tile_coordinate in dataset.overlapping_tiles(lod) {
-                self.task_queue.push_back(PreprocessTask::downsample(
+                self.push_task(PreprocessTask::downsample(
                     tile_coordinate,
                     tile_atlas,
                     dataset,
@@ -268,29 +737,89 @@ impl Preprocessor {
         }
     }
 
+    /// Writes the nodata mask and fills the voids of the datasets from coarse to fine,
+    /// so that the parent tiles are already filled.
+    fn fill_voids(&mut self, datasets: &[PreprocessDataset], tile_atlas: &mut TileAtlas) {
+        let Some(nodata) = datasets[0].nodata.clone() else {
+            return;
+        };
+
+        if let Some(mask_attachment_index) = nodata.mask_attachment_index {
+            for dataset in datasets {
+                let mask_dataset = DerivedDataset {
+                    attachment_index: mask_attachment_index,
+                    source_attachment_index: dataset.attachment_index,
+                    derived: DerivedAttachment::Mask,
+                    lod_range: dataset.lod_range.clone(),
+                };
+
+                for lod in dataset.lod_range.clone() {
+                    for tile_coordinate in dataset.overlapping_tiles(lod) {
+                        self.push_task(PreprocessTask::derive(
+                            tile_coordinate,
+                            tile_atlas,
+                            &mask_dataset,
+                        ));
+                    }
+                }
+            }
+
+            self.push_task(PreprocessTask::barrier());
+        }
+
+        if nodata.fill_radius == 0 && !nodata.fill_from_parent {
+            return;
+        }
+
+        for lod in datasets[0].lod_range.clone() {
+            for dataset in datasets {
+                for tile_coordinate in dataset.overlapping_tiles(lod) {
+                    self.push_task(PreprocessTask::fill(
+                        tile_coordinate,
+                        tile_atlas,
+                        dataset,
+                        &nodata,
+                    ));
+                }
+            }
+
+            self.push_task(PreprocessTask::barrier());
+        }
+    }
+
     fn stitch_and_save_layer(
         &mut self,
-        dataset: &PreprocessDataset,
+        tiles: &[TileCoordinate],
+        attachment_index: u32,
         tile_atlas: &mut TileAtlas,
-        lod: u32,
     ) {
-        for tile_coordinate in dataset.overlapping_tiles(lod) {
-            self.task_queue
-                .push_back(PreprocessTask::stitch(tile_coordinate, tile_atlas, dataset));
+        for &tile_coordinate in tiles {
+            self.push_task(PreprocessTask::stitch(
+                tile_coordinate,
+                tile_atlas,
+                attachment_index,
+            ));
         }
 
-        self.task_queue.push_back(PreprocessTask::barrier());
+        self.push_task(PreprocessTask::barrier());
 
-        for tile_coordinate in dataset.overlapping_tiles(lod) {
-            self.task_queue
-                .push_back(PreprocessTask::save(tile_coordinate, tile_atlas, dataset));
+        for &tile_coordinate in tiles {
+            self.push_task(PreprocessTask::save(
+                tile_coordinate,
+                tile_atlas,
+                attachment_index,
+            ));
         }
     }
 
     pub fn clear_attachment(self, attachment_index: u32, tile_atlas: &mut TileAtlas) -> Self {
-        let attachment = &mut tile_atlas.attachments[attachment_index as usize];
         tile_atlas.state.existing_tiles.clear();
-        reset_directory(&attachment.path);
+        reset_directory(&tile_atlas.attachment(attachment_index).path);
+
+        if attachment_index == 0 {
+            tile_atlas.state.tile_statistics.clear();
+            tile_atlas.state.statistics_generation += 1;
+        }
 
         self
     }
@@ -302,10 +831,22 @@ impl Preprocessor {
         tile_atlas: &mut TileAtlas,
     ) -> Self {
         self.split_and_downsample(&dataset, asset_server, tile_atlas);
-        self.task_queue.push_back(PreprocessTask::barrier());
+        self.push_task(PreprocessTask::barrier());
+
+        self.fill_voids(slice::from_ref(&dataset), tile_atlas);
+
+        let mask_attachment_index = dataset
+            .nodata
+            .as_ref()
+            .and_then(|nodata| nodata.mask_attachment_index);
 
         for lod in dataset.lod_range.clone() {
-            self.stitch_and_save_layer(&dataset, tile_atlas, lod);
+            let tiles = dataset.overlapping_tiles(lod).collect_vec();
+            self.stitch_and_save_layer(&tiles, dataset.attachment_index, tile_atlas);
+
+            if let Some(mask_attachment_index) = mask_attachment_index {
+                self.stitch_and_save_layer(&tiles, mask_attachment_index, tile_atlas);
+            }
         }
 
         self
@@ -320,9 +861,10 @@ impl Preprocessor {
         let side_datasets = (0..6)
             .map(|side| PreprocessDataset {
                 attachment_index: dataset.attachment_index,
-                path: dataset.paths[side as usize].clone(),
+                source: dataset.sources[side as usize].clone(),
                 side,
                 lod_range: dataset.lod_range.clone(),
+                nodata: dataset.nodata.clone(),
                 ..default()
             })
             .collect_vec();
@@ -331,16 +873,85 @@ impl Preprocessor {
             self.split_and_downsample(dataset, asset_server, tile_atlas);
         }
 
-        self.task_queue.push_back(PreprocessTask::barrier());
+        self.push_task(PreprocessTask::barrier());
+
+        self.fill_voids(&side_datasets, tile_atlas);
+
+        let mask_attachment_index = dataset
+            .nodata
+            .as_ref()
+            .and_then(|nodata| nodata.mask_attachment_index);
 
         for lod in dataset.lod_range {
             for dataset in &side_datasets {
-                self.stitch_and_save_layer(dataset, tile_atlas, lod);
+                let tiles = dataset.overlapping_tiles(lod).collect_vec();
+                self.stitch_and_save_layer(&tiles, dataset.attachment_index, tile_atlas);
+
+                if let Some(mask_attachment_index) = mask_attachment_index {
+                    self.stitch_and_save_layer(&tiles, mask_attachment_index, tile_atlas);
+                }
             }
         }
 
         self
     }
+
+    /// Computes a derived attachment from the stitched height of the source attachment.
+    ///
+    /// The source has to be preprocessed by this preprocessor beforehand, so that its tiles
+    /// are known to the atlas. Both attachments need the same texture and border size.
+    pub fn derive_attachment(
+        mut self,
+        dataset: DerivedDataset,
+        tile_atlas: &mut TileAtlas,
+    ) -> Self {
+        let source = tile_atlas.attachment(dataset.source_attachment_index);
+        let target = tile_atlas.attachment(dataset.attachment_index);
+
+        assert_eq!(
+            (source.texture_size, source.border_size),
+            (target.texture_size, target.border_size),
+            "The derived attachment has to match the texture and border size of its source."
+        );
+        assert!(
+            dataset.derived.supports_format(target.format),
+            "The derived attachment has an incompatible format."
+        );
+
+        let layers = dataset
+            .lod_range
+            .clone()
+            .map(|lod| {
+                tile_atlas
+                    .state
+                    .existing_tiles
+                    .iter()
+                    .filter(|tile_coordinate| tile_coordinate.lod == lod)
+                    .copied()
+                    .sorted_by_key(|tile| (tile.side, tile.z_order()))
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        // wait until the source attachment is stitched
+        self.push_task(PreprocessTask::barrier());
+
+        for &tile_coordinate in layers.iter().flatten() {
+            self.push_task(PreprocessTask::derive(
+                tile_coordinate,
+                tile_atlas,
+                &dataset,
+            ));
+        }
+
+        self.push_task(PreprocessTask::barrier());
+
+        for tiles in &layers {
+            self.stitch_and_save_layer(tiles, dataset.attachment_index, tile_atlas);
+        }
+
+        self
+    }
 }
 
 pub(crate) fn select_ready_tasks(
@@ -348,15 +959,8 @@ pub(crate) fn select_ready_tasks(
     mut terrains: Query<(&mut Preprocessor, &mut TileAtlas)>,
 ) {
     for (mut preprocessor, mut tile_atlas) in terrains.iter_mut() {
-        let Preprocessor {
-            task_queue,
-            ready_tasks,
-            start_time,
-            ..
-        } = preprocessor.deref_mut();
-
-        if let Some(time) = start_time {
-            if task_queue.is_empty()
+        if let Some(time) = preprocessor.start_time {
+            if preprocessor.task_queue.is_empty()
                 && tile_atlas.state.download_slots == tile_atlas.state.max_download_slots
                 && tile_atlas.state.save_slots == tile_atlas.state.max_save_slots
             {
@@ -367,56 +971,639 @@ pub(crate) fn select_ready_tasks(
                 //     println!("{tile}");
                 // });
 
-                *start_time = None;
+                preprocessor.start_time = None;
             }
         } else {
             break;
         }
 
-        ready_tasks.clear();
+        // the tiles of the previous tasks may be evicted, once their results are downloaded
+        if tile_atlas.state.download_slots == tile_atlas.state.max_download_slots {
+            for tile_coordinate in mem::take(&mut preprocessor.pinned_tiles) {
+                tile_atlas.state.release_tile(tile_coordinate);
+            }
+        }
+
+        preprocessor.prefetch_tiles(&mut tile_atlas);
 
-        loop {
-            if (tile_atlas.state.download_slots > 0)
-                && task_queue
-                    .front()
-                    .map_or(false, |task| task.is_ready(&asset_server, &tile_atlas))
-            {
-                let task = task_queue.pop_front().unwrap();
+        preprocessor.ready_tasks.clear();
 
-                // task.debug();
+        while let Some(task) = preprocessor.task_queue.front() {
+            match task.task_type {
+                PreprocessTaskType::Save => {
+                    let mut task = preprocessor.pop_task();
 
-                if matches!(task.task_type, PreprocessTaskType::Save) {
-                    tile_atlas.save(task.tile);
-                } else {
-                    ready_tasks.push(task);
-                    tile_atlas.state.download_slots -= 1;
+                    // tiles, which have been evicted, were already written back to disk
+                    if tile_atlas.state.is_dirty(task.tile) {
+                        task.resolve(&mut tile_atlas);
+                        tile_atlas.save(task.tile);
+                    }
                 }
-            } else {
-                break;
+                _ if tile_atlas.state.can_download()
+                    && task.is_ready(&asset_server, &tile_atlas)
+                    && task.is_resident(&tile_atlas) =>
+                {
+                    let mut task = preprocessor.pop_task();
+
+                    // task.debug();
+
+                    if matches!(task.task_type, PreprocessTaskType::Barrier) {
+                        continue;
+                    }
+
+                    task.resolve(&mut tile_atlas);
+
+                    for tile_coordinate in task.required_tiles(&tile_atlas).collect_vec() {
+                        tile_atlas.state.pin_tile(tile_coordinate);
+                        preprocessor.pinned_tiles.push(tile_coordinate);
+                    }
+
+                    tile_atlas.state.modified_tile_attachment(task.tile);
+                    tile_atlas.start_download(task.tile);
+                    preprocessor.ready_tasks.push(task);
+                }
+                _ => break,
             }
         }
     }
 }
 
+/// Measures the value range of a float image, ignoring nodata and non-finite values.
+fn measure_range(image: &Image, nodata_value: Option<f32>) -> Option<(f32, f32)> {
+    image
+        .data
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .filter(|&value| value.is_finite() && Some(value) != nodata_value)
+        .fold(None, |range, value| match range {
+            None => Some((value, value)),
+            Some((min, max)) => Some((f32::min(min, value), f32::max(max, value))),
+        })
+}
+
+/// Converts a float image to R16, by mapping the value range to `1..=u16::MAX`.
+/// Nodata is stored as zero, which the preprocessing shaders treat as missing data.
+fn normalize_image(image: &mut Image, range: (f32, f32), nodata_value: Option<f32>) {
+    let (min, max) = range;
+    let extent = f32::max(max - min, f32::EPSILON);
+
+    image.data = image
+        .data
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .map(|value| {
+            if value.is_finite() && Some(value) != nodata_value {
+                let value = (value - min) / extent * u16::MAX as f32;
+                value.round().clamp(1.0, u16::MAX as f32) as u16
+            } else {
+                0
+            }
+        })
+        .flat_map(u16::to_le_bytes)
+        .collect();
+}
+
 pub(crate) fn preprocessor_load_tile(
-    mut preprocessors: Query<&mut Preprocessor>,
+    mut preprocessors: Query<(&mut Preprocessor, &mut TileAtlas)>,
     mut images: ResMut<Assets<Image>>,
 ) {
-    for mut preprocessor in preprocessors.iter_mut() {
-        preprocessor.loading_tiles.retain_mut(|tile| {
-            if let Some(image) = images.get_mut(tile.id) {
-                image.texture_descriptor.format = tile.format.processing_format();
-                image.sampler = ImageSampler::linear();
-                false
-            } else {
-                true
+    for (mut preprocessor, mut tile_atlas) in preprocessors.iter_mut() {
+        if preprocessor.loaded
+            || !preprocessor
+                .loading_tiles
+                .iter()
+                .all(|tile| images.contains(tile.id))
+        {
+            continue;
+        }
+
+        let mut loading_tiles = mem::take(&mut preprocessor.loading_tiles);
+
+        // the images of the caller are copied, so that they are not modified below
+        let mut copies = HashMap::new();
+
+        for tile in loading_tiles.iter_mut().filter(|tile| tile.shared) {
+            let copy = copies.entry(tile.id).or_insert_with(|| {
+                let image = images.get(tile.id).unwrap().clone();
+                images.add(image)
+            });
+            tile.id = copy.id();
+        }
+
+        for task in &mut preprocessor.task_queue {
+            if let PreprocessTaskType::Split { tile_data, .. } = &mut task.task_type {
+                if let Some(copy) = copies.get(&tile_data.id()) {
+                    *tile_data = copy.clone();
+                }
             }
+        }
+
+        // The value range of float sources is measured across all tiles of the height attachment,
+        // so that they are normalized consistently, and used to calibrate the terrain model.
+        let float_tiles = loading_tiles
+            .iter()
+            .filter(|tile| {
+                tile.format == AttachmentFormat::R16
+                    && images.get(tile.id).unwrap().texture_descriptor.format
+                        == TextureFormat::R32Float
+            })
+            .into_group_map_by(|tile| tile.attachment_index);
+
+        for (attachment_index, tiles) in float_tiles {
+            assert_eq!(
+                attachment_index, 0,
+                "Float sources are only supported for the height attachment, \
+                since the measured value range is only stored for the heights."
+            );
+
+            let Some(range) = tiles
+                .iter()
+                .filter_map(|tile| measure_range(images.get(tile.id).unwrap(), tile.nodata_value))
+                .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+            else {
+                continue;
+            };
+
+            // datasets may share an image, which must only be normalized once
+            for tile in tiles.iter().unique_by(|tile| tile.id) {
+                normalize_image(images.get_mut(tile.id).unwrap(), range, tile.nodata_value);
+            }
+
+            info!("Measured a height range of {range:?}.");
+
+            tile_atlas.calibrate_height_range(range.0, range.1);
+        }
+
+        for tile in loading_tiles {
+            let image = images.get_mut(tile.id).unwrap();
+            image.texture_descriptor.format = tile.format.processing_format();
+            image.sampler = ImageSampler::linear();
+        }
+
+        info!("Finished loading all tiles.");
+        preprocessor.loaded = true;
+        preprocessor.start_time = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::TerrainModel,
+        terrain::TerrainConfig,
+        terrain_data::{
+            tile_atlas::{
+                AtlasTileAttachmentWithData, TileAtlasExhausted, TileLoaded, TileRequested,
+                TileUnloaded,
+            },
+            tile_tree::TileTree,
+            AttachmentConfig, AttachmentData,
+        },
+        terrain_view::TerrainViewComponents,
+    };
+    use bevy::{math::DVec3, tasks::AsyncComputeTaskPool};
+    use ndarray::array;
+
+    const TEXTURE_SIZE: u32 = 8;
+
+    /// The value written to every texel of the tile, so that its stored data can be identified.
+    fn tile_value(tile_coordinate: TileCoordinate) -> u16 {
+        (tile_coordinate.lod * 100 + tile_coordinate.x * 10 + tile_coordinate.y) as u16
+    }
+
+    /// Replaces the GPU by completing the download of each ready task right away.
+    fn process_ready_tasks(mut terrains: Query<(&mut Preprocessor, &mut TileAtlas)>) {
+        for (mut preprocessor, mut tile_atlas) in &mut terrains {
+            for task in mem::take(&mut preprocessor.ready_tasks) {
+                let tile = task.tile;
+                let data = AttachmentData::R16(vec![
+                    tile_value(tile.coordinate);
+                    (TEXTURE_SIZE * TEXTURE_SIZE) as usize
+                ]);
+
+                tile_atlas.attachments[tile.attachment_index as usize]
+                    .as_mut()
+                    .unwrap()
+                    .downloading_tiles
+                    .push(AsyncComputeTaskPool::get().spawn(async move {
+                        AtlasTileAttachmentWithData {
+                            tile,
+                            data,
+                            texture_size: TEXTURE_SIZE,
+                        }
+                    }));
+            }
+        }
+    }
+
+    fn attachment_config(name: &str, format: AttachmentFormat) -> AttachmentConfig {
+        AttachmentConfig {
+            name: name.to_string(),
+            texture_size: TEXTURE_SIZE,
+            format,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn derived_attachment_is_computed_for_every_existing_tile_before_it_is_stitched() {
+        let config = TerrainConfig {
+            lod_count: 2,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            ..default()
+        }
+        .add_attachment(attachment_config("height", AttachmentFormat::R16))
+        .add_attachment(attachment_config("slope", AttachmentFormat::R16));
+
+        let mut tile_atlas = TileAtlas::new(&config);
+        tile_atlas.state.existing_tiles = (0..2)
+            .flat_map(|lod| {
+                let count = TileCoordinate::count(lod);
+                iproduct!(0..count, 0..count).map(move |(x, y)| TileCoordinate::new(0, lod, x, y))
+            })
+            .collect();
+
+        let dataset = DerivedDataset {
+            attachment_index: 1,
+            source_attachment_index: 0,
+            derived: DerivedAttachment::Slope,
+            lod_range: 1..2,
+        };
+
+        let preprocessor = Preprocessor::new().derive_attachment(dataset, &mut tile_atlas);
+        let tasks = preprocessor.task_queue.iter().collect_vec();
+
+        // barrier, four derive tasks, barrier, four stitch tasks, barrier, four save tasks
+        assert_eq!(tasks.len(), 15);
+        assert!(matches!(tasks[0].task_type, PreprocessTaskType::Barrier));
+
+        for task in &tasks[1..5] {
+            assert!(matches!(
+                task.task_type,
+                PreprocessTaskType::Derive {
+                    source_attachment_index: 0,
+                    derived: DerivedAttachment::Slope,
+                }
+            ));
+            assert_eq!(task.tile.attachment_index, 1);
+            assert_eq!(task.tile.coordinate.lod, 1);
+        }
+
+        assert!(matches!(tasks[5].task_type, PreprocessTaskType::Barrier));
+        assert!(tasks[6..10]
+            .iter()
+            .all(|task| matches!(task.task_type, PreprocessTaskType::Stitch { .. })));
+    }
+
+    #[test]
+    #[should_panic(expected = "incompatible format")]
+    fn derived_normals_require_a_color_attachment() {
+        let config = TerrainConfig::default()
+            .add_attachment(attachment_config("height", AttachmentFormat::R16))
+            .add_attachment(attachment_config("normal", AttachmentFormat::R16));
+
+        let dataset = DerivedDataset {
+            attachment_index: 1,
+            source_attachment_index: 0,
+            derived: DerivedAttachment::TangentNormal,
+            lod_range: 0..1,
+        };
+
+        Preprocessor::new().derive_attachment(dataset, &mut TileAtlas::new(&config));
+    }
+
+    #[test]
+    fn float_heights_are_normalized_to_their_measured_range() {
+        let config = TerrainConfig::default()
+            .add_attachment(attachment_config("height", AttachmentFormat::R16));
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .add_systems(Update, preprocessor_load_tile);
+
+        let asset_server = app.world().resource::<AssetServer>().clone();
+        let mut tile_atlas = TileAtlas::new(&config);
+
+        let dataset = PreprocessDataset {
+            source: DatasetSource::Heights(array![[-9999.0, 10.0], [30.0, 20.0]]),
+            nodata: Some(NodataConfig {
+                value: -9999.0,
+                ..default()
+            }),
+            ..default()
+        };
+
+        let preprocessor =
+            Preprocessor::new().preprocess_tile(dataset, &asset_server, &mut tile_atlas);
+        let PreprocessTaskType::Split { tile_data, .. } = &preprocessor.task_queue[0].task_type
+        else {
+            panic!("The heights are split into tiles.");
+        };
+        let tile_data = tile_data.clone();
+
+        let terrain = app.world_mut().spawn((preprocessor, tile_atlas)).id();
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        assert!(app.world().get::<Preprocessor>(terrain).unwrap().loaded);
+
+        // the terrain model is calibrated with the measured range, which excludes the nodata value
+        let tile_atlas = app.world().get::<TileAtlas>(terrain).unwrap();
+        assert_eq!(tile_atlas.height_range, Some((10.0, 30.0)));
+        assert_eq!(
+            (tile_atlas.model.min_height, tile_atlas.model.max_height),
+            (10.0, 30.0)
+        );
+
+        // valid heights are mapped to 1..=u16::MAX, so that zero marks the voids
+        let image = app
+            .world()
+            .resource::<Assets<Image>>()
+            .get(&tile_data)
+            .unwrap();
+        assert_eq!(image.texture_descriptor.format, TextureFormat::R16Unorm);
+        assert_eq!(
+            bytemuck::cast_slice::<u8, u16>(&image.data),
+            [0, 1, u16::MAX, 32768]
+        );
+    }
+
+    #[test]
+    fn in_memory_images_are_copied_before_they_are_converted() {
+        let config = TerrainConfig::default()
+            .add_attachment(attachment_config("height", AttachmentFormat::R16));
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .add_systems(Update, preprocessor_load_tile);
+
+        let heights = array_image(
+            &array![[10.0, 20.0], [30.0, 40.0]],
+            TextureFormat::R32Float,
+            |&value: &f32| f32::to_le_bytes(value),
+        );
+        let source_data = heights.data.clone();
+        let source = app.world_mut().resource_mut::<Assets<Image>>().add(heights);
+
+        let asset_server = app.world().resource::<AssetServer>().clone();
+        let mut tile_atlas = TileAtlas::new(&config);
+
+        let dataset = PreprocessDataset {
+            source: DatasetSource::Image(source.clone()),
+            ..default()
+        };
+
+        let preprocessor =
+            Preprocessor::new().preprocess_tile(dataset, &asset_server, &mut tile_atlas);
+        let terrain = app.world_mut().spawn((preprocessor, tile_atlas)).id();
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        // the tasks split the normalized copy, while the image of the caller is left untouched
+        let preprocessor = app.world().get::<Preprocessor>(terrain).unwrap();
+        let PreprocessTaskType::Split { tile_data, .. } = &preprocessor.task_queue[0].task_type
+        else {
+            panic!("The image is split into tiles.");
+        };
+        assert_ne!(tile_data.id(), source.id());
+
+        let images = app.world().resource::<Assets<Image>>();
+        let source = images.get(&source).unwrap();
+        assert_eq!(source.texture_descriptor.format, TextureFormat::R32Float);
+        assert_eq!(source.data, source_data);
+
+        let copy = images.get(tile_data).unwrap();
+        assert_eq!(copy.texture_descriptor.format, TextureFormat::R16Unorm);
+        assert_eq!(
+            bytemuck::cast_slice::<u8, u16>(&copy.data),
+            [1, 21845, 43690, u16::MAX]
+        );
+    }
+
+    #[test]
+    fn arrays_are_indexed_by_row_and_column() {
+        let colors = Array2::from_shape_fn((2, 3), |(y, x)| [(3 * y + x) as u8, 0, 0, 255]);
+        let image = array_image(&colors, TextureFormat::Rgba8UnormSrgb, |&color| color);
+
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(
+            image
+                .data
+                .chunks_exact(4)
+                .map(|color| color[0])
+                .collect_vec(),
+            [0, 1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn noise_is_generated_at_every_lod_instead_of_downsampled() {
+        let config = TerrainConfig {
+            lod_count: 3,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            ..default()
+        }
+        .add_attachment(attachment_config("height", AttachmentFormat::R16));
+
+        let mut tile_atlas = TileAtlas::new(&config);
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()));
+        let asset_server = app.world().resource::<AssetServer>().clone();
+
+        let dataset = PreprocessDataset {
+            source: DatasetSource::Noise(default()),
+            top_left: Vec2::new(0.5, 0.0),
+            lod_range: 0..3,
+            ..default()
+        };
+
+        let preprocessor =
+            Preprocessor::new().preprocess_tile(dataset, &asset_server, &mut tile_atlas);
+        assert!(preprocessor.loading_tiles.is_empty());
+
+        let generated_tiles = preprocessor
+            .task_queue
+            .iter()
+            .take_while(|task| !matches!(task.task_type, PreprocessTaskType::Barrier))
+            .map(|task| {
+                assert!(matches!(
+                    task.task_type,
+                    PreprocessTaskType::Generate {
+                        is_spherical: false,
+                        ..
+                    }
+                ));
+                task.tile.coordinate
+            })
+            .collect_vec();
+
+        // only the tiles overlapping the right half of the terrain are generated
+        let tile_counts = (0..3)
+            .map(|lod| {
+                generated_tiles
+                    .iter()
+                    .filter(|tile_coordinate| tile_coordinate.lod == lod)
+                    .count()
+            })
+            .collect_vec();
+        assert_eq!(tile_counts, [1, 2, 8]);
+    }
+
+    #[test]
+    fn voids_are_masked_and_filled_from_coarse_to_fine() {
+        let config = TerrainConfig {
+            lod_count: 2,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            ..default()
+        }
+        .add_attachment(attachment_config("height", AttachmentFormat::R16))
+        .add_attachment(attachment_config("mask", AttachmentFormat::R16));
+
+        let mut tile_atlas = TileAtlas::new(&config);
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()));
+        let asset_server = app.world().resource::<AssetServer>().clone();
+
+        let dataset = PreprocessDataset {
+            source: DatasetSource::Noise(default()),
+            lod_range: 0..2,
+            nodata: Some(NodataConfig {
+                mask_attachment_index: Some(1),
+                ..default()
+            }),
+            ..default()
+        };
+
+        let preprocessor =
+            Preprocessor::new().preprocess_tile(dataset, &asset_server, &mut tile_atlas);
+        let tasks = preprocessor
+            .task_queue
+            .iter()
+            .skip_while(|task| !matches!(task.task_type, PreprocessTaskType::Barrier))
+            .skip(1)
+            .collect_vec();
+
+        // the mask is derived from the unfilled data of all five tiles
+        for task in &tasks[0..5] {
+            assert!(matches!(
+                task.task_type,
+                PreprocessTaskType::Derive {
+                    source_attachment_index: 0,
+                    derived: DerivedAttachment::Mask,
+                }
+            ));
+            assert_eq!(task.tile.attachment_index, 1);
+        }
+
+        assert!(matches!(tasks[5].task_type, PreprocessTaskType::Barrier));
+
+        // the root tile has no parent, while the children are filled from the filled root
+        let PreprocessTaskType::Fill { parent_tile, .. } = &tasks[6].task_type else {
+            panic!("The voids of the root tile are filled first.");
+        };
+        assert_eq!(parent_tile.coordinate, TileCoordinate::INVALID);
+        assert!(matches!(tasks[7].task_type, PreprocessTaskType::Barrier));
+
+        for task in &tasks[8..12] {
+            let PreprocessTaskType::Fill { parent_tile, .. } = &task.task_type else {
+                panic!("The voids of the children are filled after the root tile.");
+            };
+            assert_eq!(parent_tile.coordinate, TileCoordinate::new(0, 0, 0, 0));
+        }
+
+        assert!(matches!(tasks[12].task_type, PreprocessTaskType::Barrier));
+    }
+
+    #[test]
+    fn preprocess_with_atlas_smaller_than_dataset() {
+        let path = "../target/preprocess_test";
+        let lod_count = 3;
+        let atlas_size = 12;
+
+        let config = TerrainConfig {
+            lod_count,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            atlas_size,
+            path: path.to_string(),
+            ..default()
+        }
+        .add_attachment(AttachmentConfig {
+            name: "height".to_string(),
+            texture_size: TEXTURE_SIZE,
+            ..default()
         });
 
-        if !preprocessor.loaded && preprocessor.loading_tiles.is_empty() {
-            println!("finished loading all tiles");
-            preprocessor.loaded = true;
-            preprocessor.start_time = Some(Instant::now());
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .init_resource::<TerrainViewComponents<TileTree>>()
+            .add_event::<TileAtlasExhausted>()
+            .add_event::<TileRequested>()
+            .add_event::<TileLoaded>()
+            .add_event::<TileUnloaded>()
+            .add_systems(
+                Update,
+                (
+                    preprocessor_load_tile,
+                    select_ready_tasks,
+                    process_ready_tasks,
+                    TileAtlas::update,
+                )
+                    .chain(),
+            );
+
+        let asset_server = app.world().resource::<AssetServer>().clone();
+        let mut tile_atlas = TileAtlas::new(&config);
+
+        let dataset = PreprocessDataset {
+            source: DatasetSource::Noise(default()),
+            lod_range: 0..lod_count,
+            ..default()
+        };
+
+        let preprocessor = Preprocessor::new()
+            .clear_attachment(0, &mut tile_atlas)
+            .preprocess_tile(dataset, &asset_server, &mut tile_atlas);
+
+        let tile_count = tile_atlas.state.existing_tiles.len() as u32;
+        assert!(tile_count > atlas_size);
+
+        let terrain = app.world_mut().spawn((preprocessor, tile_atlas)).id();
+
+        for _ in 0..1000 {
+            app.update();
+
+            let preprocessor = app.world().get::<Preprocessor>(terrain).unwrap();
+
+            if preprocessor.loaded && preprocessor.start_time.is_none() {
+                break;
+            }
         }
+
+        let preprocessor = app.world().get::<Preprocessor>(terrain).unwrap();
+        assert!(preprocessor.task_queue.is_empty(), "Preprocessing stalled.");
+        assert!(preprocessor.start_time.is_none(), "Preprocessing stalled.");
+
+        let tile_atlas = app.world().get::<TileAtlas>(terrain).unwrap();
+        let attachment_path = &tile_atlas.attachment(0).path;
+        assert_eq!(tile_atlas.state.existing_tiles.len() as u32, tile_count);
+
+        // each tile is written back with the data of its last task, even if it was evicted in between
+        for &tile_coordinate in &tile_atlas.state.existing_tiles {
+            let bytes = fs::read(tile_coordinate.path(attachment_path, "bin")).unwrap();
+
+            assert!(bytemuck::cast_slice::<u8, u16>(&bytes)
+                .iter()
+                .all(|&value| value == tile_value(tile_coordinate)));
+        }
+
+        let _ = fs::remove_dir_all(format!("assets/{path}"));
     }
 }
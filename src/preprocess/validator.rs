@@ -0,0 +1,496 @@
+use crate::{
+    formats::TC,
+    math::{TerrainModel, TileCoordinate},
+    terrain::TerrainConfig,
+    terrain_data::{AttachmentConfig, AttachmentData},
+};
+use anyhow::Result;
+use bevy::{math::IVec2, prelude::*, utils::HashSet};
+use itertools::{iproduct, Itertools};
+use lru::LruCache;
+use ron::ser::PrettyConfig;
+use serde::Serialize;
+use std::{fs, num::NonZeroUsize};
+
+const CACHE_SIZE: usize = 256;
+
+/// Configures the tolerances of the checks performed by [`validate_terrain`].
+///
+/// All tolerances are specified in normalized units (`0..1`) of the attachment values.
+#[derive(Clone, Debug)]
+pub struct ValidationConfig {
+    /// The maximum difference between a border pixel and the pixel of the neighbour it is stitched from.
+    pub border_tolerance: f32,
+    /// The maximum difference between a parent pixel and the average of its valid child pixels.
+    /// Set this to `None` for terrains with generated or derived attachments, which are not downsampled.
+    pub downsample_tolerance: Option<f32>,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            border_tolerance: 1.0 / 255.0,
+            downsample_tolerance: Some(1.0 / 255.0),
+        }
+    }
+}
+
+/// An inconsistency found by [`validate_terrain`].
+#[derive(Clone, Debug, Serialize)]
+pub enum ValidationIssue {
+    /// The tile is listed in the tile config, but its parent is not.
+    MissingParent { tile: TileCoordinate },
+    /// The data file of the tile attachment does not exist.
+    MissingData {
+        tile: TileCoordinate,
+        attachment: String,
+    },
+    /// The data file of the tile attachment does not match the size of the attachment.
+    InvalidData {
+        tile: TileCoordinate,
+        attachment: String,
+        expected_size: usize,
+        size: usize,
+    },
+    /// The border towards a neighbour does not match the data of that neighbour.
+    /// Borders without a neighbour have to repeat the outermost pixels of the tile.
+    BorderMismatch {
+        tile: TileCoordinate,
+        neighbour: Option<TileCoordinate>,
+        attachment: String,
+        pixel_count: u32,
+        max_difference: f32,
+    },
+    /// The tile does not agree with the downsampled data of its children.
+    DownsampleMismatch {
+        tile: TileCoordinate,
+        attachment: String,
+        pixel_count: u32,
+        max_difference: f32,
+    },
+}
+
+/// The machine-readable result of [`validate_terrain`].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub path: String,
+    pub tile_count: usize,
+    pub attachments: Vec<String>,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Writes the report in the RON format.
+    pub fn save_file(&self, path: &str) -> Result<()> {
+        let report = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        fs::write(path, report)?;
+        Ok(())
+    }
+}
+
+/// Counts the pixels of a tile, which differ by more than the tolerance.
+#[derive(Default)]
+struct Mismatch {
+    pixel_count: u32,
+    max_difference: f32,
+}
+
+impl Mismatch {
+    fn compare(&mut self, value: Vec4, expected: Vec4, tolerance: f32) {
+        let difference = (value - expected).abs().max_element();
+
+        if difference > tolerance {
+            self.pixel_count += 1;
+            self.max_difference = self.max_difference.max(difference);
+        }
+    }
+}
+
+/// Projects the pixel coordinates into the coordinate system of the neighbouring side,
+/// the same way the stitch shader does.
+fn project_to_side(coords: IVec2, side: u32, neighbour_side: u32, texture_size: i32) -> IVec2 {
+    const PS: u32 = 0;
+    const PT: u32 = 1;
+    const NS: u32 = 2;
+    const NT: u32 = 3;
+
+    const EVEN_LIST: [[u32; 2]; 6] = [[PS, PT], [PS, PT], [NT, PS], [NT, NS], [PT, NS], [PS, PT]];
+    const ODD_LIST: [[u32; 2]; 6] = [[PS, PT], [PS, PT], [PT, NS], [PT, PS], [NT, PS], [PS, PT]];
+
+    let index = ((6 + neighbour_side - side) % 6) as usize;
+    let info = if side.is_multiple_of(2) {
+        EVEN_LIST[index]
+    } else {
+        ODD_LIST[index]
+    };
+
+    let [x, y] = info.map(|info| match info {
+        PS => coords.x,
+        PT => coords.y,
+        NS => texture_size - 1 - coords.x,
+        _ => texture_size - 1 - coords.y,
+    });
+
+    IVec2::new(x, y)
+}
+
+/// Validates one attachment of all tiles, by loading the tiles and their neighbours on demand.
+struct AttachmentValidator<'a> {
+    attachment: &'a AttachmentConfig,
+    path: String,
+    tiles: &'a HashSet<TileCoordinate>,
+    model: &'a TerrainModel,
+    cache: LruCache<TileCoordinate, Option<AttachmentData>>,
+    /// The tiles with missing or invalid data, which have already been reported.
+    invalid_tiles: HashSet<TileCoordinate>,
+    issues: Vec<ValidationIssue>,
+}
+
+impl<'a> AttachmentValidator<'a> {
+    fn new(
+        attachment: &'a AttachmentConfig,
+        terrain_path: &str,
+        tiles: &'a HashSet<TileCoordinate>,
+        model: &'a TerrainModel,
+    ) -> Self {
+        Self {
+            attachment,
+            path: format!("assets/{terrain_path}/data/{}", attachment.name),
+            tiles,
+            model,
+            cache: LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap()),
+            invalid_tiles: default(),
+            issues: default(),
+        }
+    }
+
+    fn read_tile(&mut self, tile: TileCoordinate) -> Option<AttachmentData> {
+        if self.invalid_tiles.contains(&tile) {
+            return None;
+        }
+
+        let texture_size = self.attachment.texture_size as usize;
+        let expected_size =
+            texture_size * texture_size * self.attachment.format.pixel_size() as usize;
+
+        let Ok(bytes) = fs::read(tile.path(&self.path, "bin")) else {
+            self.invalid_tiles.insert(tile);
+            self.issues.push(ValidationIssue::MissingData {
+                tile,
+                attachment: self.attachment.name.clone(),
+            });
+            return None;
+        };
+
+        if bytes.len() != expected_size {
+            self.invalid_tiles.insert(tile);
+            self.issues.push(ValidationIssue::InvalidData {
+                tile,
+                attachment: self.attachment.name.clone(),
+                expected_size,
+                size: bytes.len(),
+            });
+            return None;
+        }
+
+        Some(AttachmentData::from_bytes(&bytes, self.attachment.format))
+    }
+
+    fn tile(&mut self, tile: TileCoordinate) -> Option<AttachmentData> {
+        if let Some(data) = self.cache.get(&tile) {
+            return data.clone();
+        }
+
+        let data = self.read_tile(tile);
+        self.cache.put(tile, data.clone());
+        data
+    }
+
+    fn pixel(&self, data: &AttachmentData, coords: IVec2) -> Vec4 {
+        data.pixel((coords.y * self.attachment.texture_size as i32 + coords.x) as usize)
+    }
+
+    fn validate_borders(&mut self, tile: TileCoordinate, data: &AttachmentData, tolerance: f32) {
+        const OFFSETS: [IVec2; 8] = [
+            IVec2::new(0, 1),
+            IVec2::new(-1, 0),
+            IVec2::new(0, -1),
+            IVec2::new(1, 0),
+            IVec2::new(1, 1),
+            IVec2::new(-1, 1),
+            IVec2::new(-1, -1),
+            IVec2::new(1, -1),
+        ];
+
+        let texture_size = self.attachment.texture_size as i32;
+        let border_size = self.attachment.border_size as i32;
+        let center_size = texture_size - 2 * border_size;
+        let offset_size = border_size + center_size;
+
+        // the border regions in the same order as the neighbours (x, y, width, height)
+        let bounds = [
+            [border_size, 0, center_size, border_size],
+            [offset_size, border_size, border_size, center_size],
+            [border_size, offset_size, center_size, border_size],
+            [0, border_size, border_size, center_size],
+            [0, 0, border_size, border_size],
+            [offset_size, 0, border_size, border_size],
+            [offset_size, offset_size, border_size, border_size],
+            [0, offset_size, border_size, border_size],
+        ];
+
+        let neighbours = tile.neighbours(self.model).collect_vec();
+
+        for (index, neighbour) in neighbours.into_iter().enumerate() {
+            let neighbour = self.tiles.contains(&neighbour).then_some(neighbour);
+
+            let neighbour_data = match neighbour {
+                Some(neighbour) => match self.tile(neighbour) {
+                    Some(neighbour_data) => Some(neighbour_data),
+                    None => continue, // already reported as missing
+                },
+                None => None,
+            };
+
+            let [x, y, width, height] = bounds[index];
+            let mut mismatch = Mismatch::default();
+
+            for (y, x) in iproduct!(y..y + height, x..x + width) {
+                let coords = IVec2::new(x, y);
+
+                let expected = match (neighbour, &neighbour_data) {
+                    (Some(neighbour), Some(neighbour_data)) => {
+                        let neighbour_coords = project_to_side(
+                            coords + OFFSETS[index] * center_size,
+                            tile.side,
+                            neighbour.side,
+                            texture_size,
+                        );
+
+                        self.pixel(neighbour_data, neighbour_coords)
+                    }
+                    _ => {
+                        let repeat_coords =
+                            coords.clamp(IVec2::splat(border_size), IVec2::splat(offset_size - 1));
+
+                        self.pixel(data, repeat_coords)
+                    }
+                };
+
+                mismatch.compare(self.pixel(data, coords), expected, tolerance);
+            }
+
+            if mismatch.pixel_count > 0 {
+                self.issues.push(ValidationIssue::BorderMismatch {
+                    tile,
+                    neighbour,
+                    attachment: self.attachment.name.clone(),
+                    pixel_count: mismatch.pixel_count,
+                    max_difference: mismatch.max_difference,
+                });
+            }
+        }
+    }
+
+    fn validate_downsampling(
+        &mut self,
+        tile: TileCoordinate,
+        data: &AttachmentData,
+        tolerance: f32,
+    ) {
+        let children = tile
+            .children()
+            .map(|child| self.tiles.contains(&child).then(|| self.tile(child)))
+            .collect_vec();
+
+        // the children of tiles at the finest lod of the dataset do not exist
+        if children.iter().all(Option::is_none) {
+            return;
+        }
+
+        let border_size = self.attachment.border_size as i32;
+        let center_size = self.attachment.texture_size as i32 - 2 * border_size;
+        let child_size = center_size / 2;
+
+        let mut mismatch = Mismatch::default();
+
+        for (y, x) in iproduct!(0..center_size, 0..center_size) {
+            let tile_coords = IVec2::new(x, y);
+            let child_index = (x / child_size + 2 * (y / child_size)) as usize;
+
+            let Some(Some(child_data)) = &children[child_index] else {
+                continue;
+            };
+
+            let child_coords = 2 * (tile_coords % child_size) + border_size;
+
+            let (sum, count) = iproduct!(0..2, 0..2)
+                .map(|(x, y)| self.pixel(child_data, child_coords + IVec2::new(x, y)))
+                .filter(|value| value.xyz() != Vec3::ZERO)
+                .fold((Vec4::ZERO, 0.0), |(sum, count), value| {
+                    (sum + value, count + 1.0)
+                });
+
+            let expected = if count > 0.0 { sum / count } else { Vec4::ZERO };
+
+            mismatch.compare(
+                self.pixel(data, tile_coords + border_size),
+                expected,
+                tolerance,
+            );
+        }
+
+        if mismatch.pixel_count > 0 {
+            self.issues.push(ValidationIssue::DownsampleMismatch {
+                tile,
+                attachment: self.attachment.name.clone(),
+                pixel_count: mismatch.pixel_count,
+                max_difference: mismatch.max_difference,
+            });
+        }
+    }
+}
+
+/// Validates the preprocessed data of a terrain.
+///
+/// Walks all tiles listed in the tile config and checks that
+/// - the parent of every tile exists,
+/// - the data of every tile attachment exists and has the expected size,
+/// - the borders of adjacent tiles (including the ones across the sides of spherical terrains) match,
+/// - parent tiles agree with the downsampled data of their children.
+pub fn validate_terrain(
+    config: &TerrainConfig,
+    validation: &ValidationConfig,
+) -> Result<ValidationReport> {
+    let tc = TC::load_file(format!("assets/{}/config.tc", config.path))?;
+    let tiles: HashSet<TileCoordinate> = tc.tiles.into_iter().collect();
+
+    // process nearby tiles consecutively, so that their neighbours are cached
+    let sorted_tiles = tiles
+        .iter()
+        .copied()
+        .sorted_by_key(|tile| (tile.side, tile.lod, tile.z_order()))
+        .collect_vec();
+
+    let mut report = ValidationReport {
+        path: config.path.clone(),
+        tile_count: tiles.len(),
+        attachments: config
+            .attachments
+            .iter()
+            .map(|attachment| attachment.name.clone())
+            .collect(),
+        issues: default(),
+    };
+
+    for &tile in &sorted_tiles {
+        if tile.lod > 0 && !tiles.contains(&tile.parent()) {
+            report.issues.push(ValidationIssue::MissingParent { tile });
+        }
+    }
+
+    for attachment in &config.attachments {
+        let mut validator =
+            AttachmentValidator::new(attachment, &config.path, &tiles, &config.model);
+
+        for &tile in &sorted_tiles {
+            let Some(data) = validator.tile(tile) else {
+                continue;
+            };
+
+            validator.validate_borders(tile, &data, validation.border_tolerance);
+
+            if let Some(tolerance) = validation.downsample_tolerance {
+                validator.validate_downsampling(tile, &data, tolerance);
+            }
+        }
+
+        report.issues.extend(validator.issues);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::AttachmentFormat;
+    use bevy::math::DVec3;
+
+    const VALUE: u16 = 1000;
+
+    fn write_tile(path: &str, tile: TileCoordinate, data: &[u16]) {
+        fs::write(tile.path(path, "bin"), bytemuck::cast_slice(data)).unwrap();
+    }
+
+    #[test]
+    fn validation_reports_missing_tiles_and_mismatching_borders() {
+        let path = "../target/validator_test";
+        let data_path = format!("assets/{path}/data/height");
+        let _ = fs::remove_dir_all(format!("assets/{path}"));
+        fs::create_dir_all(&data_path).unwrap();
+
+        let config = TerrainConfig {
+            lod_count: 3,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            path: path.to_string(),
+            ..default()
+        }
+        .add_attachment(AttachmentConfig {
+            name: "height".to_string(),
+            texture_size: 4,
+            border_size: 1,
+            format: AttachmentFormat::R16,
+            ..default()
+        });
+
+        let root = TileCoordinate::new(0, 0, 0, 0);
+        let child = TileCoordinate::new(0, 1, 0, 0);
+        let orphan = TileCoordinate::new(0, 2, 3, 3);
+
+        TC {
+            tiles: vec![root, child, orphan],
+            height_range: None,
+            tile_statistics: default(),
+        }
+        .save_file(format!("assets/{path}/config.tc"))
+        .unwrap();
+
+        // the borders of tiles without neighbours repeat their outermost pixels
+        write_tile(&data_path, root, &[VALUE; 16]);
+
+        // the top border of the child differs from the pixels it should repeat
+        let mut child_data = [VALUE; 16];
+        child_data[1] = 2 * VALUE;
+        write_tile(&data_path, child, &child_data);
+
+        let report = validate_terrain(&config, &ValidationConfig::default()).unwrap();
+        let _ = fs::remove_dir_all(format!("assets/{path}"));
+
+        assert_eq!(report.tile_count, 3);
+        assert!(!report.is_valid());
+        assert_eq!(report.issues.len(), 3, "{:?}", report.issues);
+
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            ValidationIssue::MissingParent { tile } if *tile == orphan
+        )));
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            ValidationIssue::MissingData { tile, .. } if *tile == orphan
+        )));
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            ValidationIssue::BorderMismatch {
+                tile,
+                neighbour: None,
+                pixel_count: 1,
+                ..
+            } if *tile == child
+        )));
+    }
+}
@@ -0,0 +1,318 @@
+//! The attachment bindings of the terrain shaders, which are generated for the attachments of
+//! all terrains.
+//!
+//! Each distinct set of attachments is assigned an attachment layout, which is selected in the
+//! shaders by the `ATTACHMENT_LAYOUT` shader def. Depending on the device, the attachments are
+//! either bound as a binding array or as separate bindings.
+
+use crate::terrain_data::tile_atlas::TileAtlas;
+use bevy::{
+    prelude::*,
+    render::{render_resource::*, renderer::RenderDevice},
+};
+use itertools::Itertools;
+use std::fmt::Write;
+
+pub const ATTACHMENT_BINDINGS_SHADER: Handle<Shader> =
+    Handle::weak_from_u128(203476190348571209374519283745619028);
+
+/// The binding of the first attachment in the terrain bind group.
+pub(crate) const FIRST_ATTACHMENT_BINDING: u32 = 5;
+
+/// Whether the attachments can be bound as a binding array and indexed dynamically.
+pub(crate) fn supports_binding_arrays(device: &RenderDevice) -> bool {
+    device.features().contains(
+        WgpuFeatures::TEXTURE_BINDING_ARRAY
+            | WgpuFeatures::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+    )
+}
+
+/// The maximum amount of attachment slots, which can be bound to the terrain shaders.
+///
+/// Each slot is a sampled texture, either as a separate binding or as an element of the binding array.
+pub(crate) fn max_attachment_slots(device: &RenderDevice) -> u32 {
+    device.limits().max_sampled_textures_per_shader_stage
+}
+
+/// Panics, if the attachment slots of a terrain exceed the sampled textures the device can bind.
+pub(crate) fn validate_slot_count(slot_count: u32, max_slot_count: u32) {
+    assert!(
+        slot_count <= max_slot_count,
+        "The terrain uses {slot_count} attachment slots, but the device only supports \
+        {max_slot_count} sampled textures per shader stage. Remove some of its attachments."
+    );
+}
+
+/// The identifier of the attachment in the generated shader code.
+///
+/// Attachment names are validated by the [`TileAtlas`], so that their identifiers are unique.
+pub(crate) fn attachment_identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Identifies the attachment layout of a terrain in the pipeline keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AttachmentLayoutKey {
+    /// The index of the attachment layout in the generated shader.
+    pub(crate) id: u32,
+    /// The amount of bound attachments, including vacant ones.
+    pub(crate) slot_count: u32,
+}
+
+impl AttachmentLayoutKey {
+    pub fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        vec![ShaderDefVal::UInt("ATTACHMENT_LAYOUT".into(), self.id)]
+    }
+}
+
+/// The attachment layouts of all terrains, from which the attachment bindings are generated.
+///
+/// A layout is the list of attachment names by their index, where removed attachments are vacant.
+/// Layouts are never removed, so that their ids stay valid.
+#[derive(Resource)]
+pub(crate) struct AttachmentLayouts {
+    layouts: Vec<Vec<Option<String>>>,
+    binding_array: bool,
+    max_slot_count: u32,
+}
+
+impl FromWorld for AttachmentLayouts {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+
+        let layouts = Self {
+            layouts: default(),
+            binding_array: supports_binding_arrays(device),
+            max_slot_count: max_attachment_slots(device),
+        };
+
+        world
+            .resource_mut::<Assets<Shader>>()
+            .insert(&ATTACHMENT_BINDINGS_SHADER, layouts.shader());
+
+        layouts
+    }
+}
+
+impl AttachmentLayouts {
+    /// Assigns the attachment layout to each terrain and regenerates the attachment bindings,
+    /// once a new layout is used.
+    pub(crate) fn update(
+        mut attachment_layouts: ResMut<AttachmentLayouts>,
+        mut shaders: ResMut<Assets<Shader>>,
+        mut tile_atlases: Query<&mut TileAtlas>,
+    ) {
+        let mut changed = false;
+
+        for mut tile_atlas in &mut tile_atlases {
+            let layout = tile_atlas
+                .attachments
+                .iter()
+                .map(|attachment| {
+                    attachment
+                        .as_ref()
+                        .map(|attachment| attachment.name.clone())
+                })
+                .collect_vec();
+
+            let id = match attachment_layouts
+                .layouts
+                .iter()
+                .position(|other| *other == layout)
+            {
+                Some(id) => id,
+                None => {
+                    // the bindings of the layout are only generated, if the device can bind them
+                    validate_slot_count(layout.len() as u32, attachment_layouts.max_slot_count);
+                    attachment_layouts.layouts.push(layout);
+                    changed = true;
+                    attachment_layouts.layouts.len() - 1
+                }
+            } as u32;
+
+            if tile_atlas.attachment_layout != id {
+                tile_atlas.attachment_layout = id;
+            }
+        }
+
+        if changed {
+            shaders.insert(&ATTACHMENT_BINDINGS_SHADER, attachment_layouts.shader());
+        }
+    }
+
+    fn shader(&self) -> Shader {
+        Shader::from_wgsl(
+            self.generate(),
+            "bevy_terrain/shaders/attachment_bindings.wgsl",
+        )
+    }
+
+    /// Generates the bindings and sampling functions of all attachment layouts.
+    fn generate(&self) -> String {
+        let mut source = String::from(
+            "#define_import_path bevy_terrain::attachment_bindings
+
+#import bevy_terrain::types::AtlasTile
+#import bevy_terrain::bindings::{atlas_sampler, attachments}
+#import bevy_terrain::functions::lookup_attachment_tile
+
+fn attachment_uv(uv: vec2<f32>, attachment_index: u32) -> vec2<f32> {
+    let attachment = attachments[attachment_index];
+    return uv * attachment.scale + attachment.offset;
+}
+
+fn sample_attachment(lookup_tile: AtlasTile, attachment_index: u32) -> vec4<f32> {
+    let tile = lookup_attachment_tile(lookup_tile, attachment_index);
+
+    // coarser tiles do not contain the attachment
+    if (tile.coordinate.lod < attachments[attachment_index].min_lod) { return vec4<f32>(0.0); }
+
+    let uv = attachment_uv(tile.coordinate.uv, attachment_index);
+
+#ifdef FRAGMENT
+#ifdef SAMPLE_GRAD
+    return sample_attachment_grad(attachment_index, uv, tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy);
+#else
+    return sample_attachment_level(attachment_index, uv, tile.index, 0.0);
+#endif
+#else
+    return sample_attachment_level(attachment_index, uv, tile.index, 0.0);
+#endif
+}
+",
+        );
+
+        for (id, layout) in self.layouts.iter().enumerate() {
+            let slot_count = layout.len().max(1);
+
+            writeln!(source, "\n#if ATTACHMENT_LAYOUT == {id}").unwrap();
+
+            if self.binding_array {
+                Self::write_binding_array(&mut source, slot_count);
+            } else {
+                Self::write_bindings(&mut source, slot_count);
+            }
+
+            for (attachment_index, name) in layout.iter().enumerate() {
+                let Some(name) = name else {
+                    continue;
+                };
+
+                let name = attachment_identifier(name);
+
+                write!(
+                    source,
+                    "
+const {constant}_ATTACHMENT: u32 = {attachment_index}u;
+
+fn sample_{name}_attachment(tile: AtlasTile) -> vec4<f32> {{
+    return sample_attachment(tile, {constant}_ATTACHMENT);
+}}
+",
+                    constant = name.to_uppercase(),
+                )
+                .unwrap();
+            }
+
+            writeln!(source, "#endif").unwrap();
+        }
+
+        source
+    }
+
+    fn write_binding_array(source: &mut String, slot_count: usize) {
+        write!(
+            source,
+            "
+@group(1) @binding({FIRST_ATTACHMENT_BINDING})
+var attachment_atlases: binding_array<texture_2d_array<f32>, {slot_count}u>;
+
+fn sample_attachment_level(attachment_index: u32, uv: vec2<f32>, atlas_index: u32, level: f32) -> vec4<f32> {{
+    return textureSampleLevel(attachment_atlases[attachment_index], atlas_sampler, uv, atlas_index, level);
+}}
+
+fn sample_attachment_grad(attachment_index: u32, uv: vec2<f32>, atlas_index: u32, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {{
+    return textureSampleGrad(attachment_atlases[attachment_index], atlas_sampler, uv, atlas_index, ddx, ddy);
+}}
+
+fn gather_attachment(attachment_index: u32, uv: vec2<f32>, atlas_index: u32) -> vec4<f32> {{
+    return textureGather(0, attachment_atlases[attachment_index], atlas_sampler, uv, atlas_index);
+}}
+"
+        )
+        .unwrap();
+    }
+
+    fn write_bindings(source: &mut String, slot_count: usize) {
+        for attachment_index in 0..slot_count {
+            write!(
+                source,
+                "
+@group(1) @binding({binding})
+var attachment{attachment_index}_atlas: texture_2d_array<f32>;",
+                binding = FIRST_ATTACHMENT_BINDING as usize + attachment_index,
+            )
+            .unwrap();
+        }
+
+        let cases = |sample: &str| {
+            (0..slot_count)
+                .map(|attachment_index| {
+                    format!(
+                        "        case {attachment_index}u: {{ return {}; }}",
+                        sample.replace("ATLAS", &format!("attachment{attachment_index}_atlas"))
+                    )
+                })
+                .join("\n")
+        };
+
+        write!(
+            source,
+            "
+
+fn sample_attachment_level(attachment_index: u32, uv: vec2<f32>, atlas_index: u32, level: f32) -> vec4<f32> {{
+    switch (attachment_index) {{
+{}
+        default: {{ return vec4<f32>(0.0); }}
+    }}
+}}
+
+fn sample_attachment_grad(attachment_index: u32, uv: vec2<f32>, atlas_index: u32, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {{
+    switch (attachment_index) {{
+{}
+        default: {{ return vec4<f32>(0.0); }}
+    }}
+}}
+
+fn gather_attachment(attachment_index: u32, uv: vec2<f32>, atlas_index: u32) -> vec4<f32> {{
+    switch (attachment_index) {{
+{}
+        default: {{ return vec4<f32>(0.0); }}
+    }}
+}}
+",
+            cases("textureSampleLevel(ATLAS, atlas_sampler, uv, atlas_index, level)"),
+            cases("textureSampleGrad(ATLAS, atlas_sampler, uv, atlas_index, ddx, ddy)"),
+            cases("textureGather(0, ATLAS, atlas_sampler, uv, atlas_index)"),
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_count_within_the_device_limit_is_valid() {
+        validate_slot_count(16, 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "only supports 16 sampled textures per shader stage")]
+    fn slot_count_beyond_the_device_limit_is_rejected() {
+        validate_slot_count(17, 16);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{
+    terrain::TerrainComponents,
+    terrain_data::{
+        gpu_tile_atlas::{create_attachment_layout, GpuTileAtlas},
+        tile_atlas::{AtlasTile, AtlasTileAttachment},
+    },
+    util::StaticBuffer,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        render_graph::{self, RenderLabel},
+        render_resource::{binding_types::*, *},
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+use itertools::Itertools;
+use std::mem;
+
+/// The maximum amount of attachments, which can be accessed by a generation shader.
+pub(crate) const MAX_SOURCE_ATTACHMENTS: usize = 4;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct AttachmentGenerationLabel;
+
+#[derive(Clone, Debug, ShaderType)]
+struct GenerationData {
+    tile: AtlasTile,
+    world_from_local: Mat3,
+    is_spherical: u32,
+    min_height: f32,
+    max_height: f32,
+    tile_index: u32,
+}
+
+pub(crate) fn create_generation_layout(device: &RenderDevice) -> BindGroupLayout {
+    let source_atlas = texture_2d_array(TextureSampleType::Float { filterable: true });
+
+    device.create_bind_group_layout(
+        None,
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::COMPUTE,
+            (
+                uniform_buffer::<GenerationData>(false), // generation_data
+                source_atlas,                            // source_atlas_0
+                source_atlas,                            // source_atlas_1
+                source_atlas,                            // source_atlas_2
+                source_atlas,                            // source_atlas_3
+            ),
+        ),
+    )
+}
+
+/// The GPU state of an attachment, which is generated by a compute shader.
+pub(crate) struct GpuAttachmentGeneration {
+    shader: Handle<Shader>,
+    source_attachments: Vec<u32>,
+    pipeline: Option<CachedComputePipelineId>,
+    /// The tiles extracted from the tile atlas, which should be generated in this frame.
+    pub(crate) pending_tiles: Vec<AtlasTileAttachment>,
+    /// The tiles, which could not be generated in this frame and are returned to the tile atlas.
+    pub(crate) deferred_tiles: Vec<AtlasTileAttachment>,
+    /// The tiles generated in this frame and their bind groups, indexed by their write section slot.
+    slots: Vec<(AtlasTileAttachment, BindGroup)>,
+    /// The tiles, which have been generated and are reported back to the tile atlas.
+    pub(crate) generated_tiles: Vec<AtlasTileAttachment>,
+}
+
+impl GpuAttachmentGeneration {
+    pub(crate) fn new(shader: Handle<Shader>, source_attachments: Vec<u32>) -> Self {
+        Self {
+            shader,
+            source_attachments,
+            pipeline: None,
+            pending_tiles: default(),
+            deferred_tiles: default(),
+            slots: default(),
+            generated_tiles: default(),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct AttachmentGenerationPipelines {
+    attachment_layout: BindGroupLayout,
+    generation_layout: BindGroupLayout,
+}
+
+impl FromWorld for AttachmentGenerationPipelines {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+
+        Self {
+            attachment_layout: create_attachment_layout(device),
+            generation_layout: create_generation_layout(device),
+        }
+    }
+}
+
+impl SpecializedComputePipeline for AttachmentGenerationPipelines {
+    type Key = Handle<Shader>;
+
+    fn specialize(&self, key: Self::Key) -> ComputePipelineDescriptor {
+        ComputePipelineDescriptor {
+            label: Some("attachment_generation_pipeline".into()),
+            layout: vec![
+                self.attachment_layout.clone(),
+                self.generation_layout.clone(),
+            ],
+            push_constant_ranges: default(),
+            shader: key,
+            shader_defs: vec![],
+            entry_point: "generate".into(),
+        }
+    }
+}
+
+pub(crate) fn queue_attachment_generation(
+    pipeline_cache: Res<PipelineCache>,
+    generation_pipelines: Res<AttachmentGenerationPipelines>,
+    mut pipelines: ResMut<SpecializedComputePipelines<AttachmentGenerationPipelines>>,
+    mut gpu_tile_atlases: ResMut<TerrainComponents<GpuTileAtlas>>,
+) {
+    for gpu_tile_atlas in gpu_tile_atlases.values_mut() {
+        for attachment in gpu_tile_atlas.attachments.iter_mut().flatten() {
+            if let Some(generation) = &mut attachment.generation {
+                generation.pipeline = Some(pipelines.specialize(
+                    &pipeline_cache,
+                    &generation_pipelines,
+                    generation.shader.clone(),
+                ));
+            }
+        }
+    }
+}
+
+/// Assigns the pending tiles of all generated attachments to write section slots.
+pub(crate) fn prepare_attachment_generation(
+    device: Res<RenderDevice>,
+    pipeline_cache: Res<PipelineCache>,
+    mut gpu_tile_atlases: ResMut<TerrainComponents<GpuTileAtlas>>,
+) {
+    for gpu_tile_atlas in gpu_tile_atlases.values_mut() {
+        let model = &gpu_tile_atlas.model;
+        let attachments = &mut gpu_tile_atlas.attachments;
+
+        for attachment_index in 0..attachments.len() {
+            let Some(generation) = attachments[attachment_index]
+                .as_ref()
+                .and_then(|attachment| attachment.generation.as_ref())
+            else {
+                continue;
+            };
+
+            let is_ready = generation
+                .pipeline
+                .is_some_and(|id| pipeline_cache.get_compute_pipeline(id).is_some());
+
+            // unused source bindings are filled with the generated attachment itself
+            let source_views = (0..MAX_SOURCE_ATTACHMENTS)
+                .map(|source_index| {
+                    let source_attachment_index = generation
+                        .source_attachments
+                        .get(source_index)
+                        .map_or(attachment_index, |&index| index as usize);
+                    let source = attachments[source_attachment_index].as_ref().unwrap();
+
+                    source.atlas_texture.create_view(&TextureViewDescriptor {
+                        format: Some(source.buffer_info.format.processing_format()),
+                        dimension: Some(TextureViewDimension::D2Array),
+                        ..default()
+                    })
+                })
+                .collect_vec();
+
+            let attachment = attachments[attachment_index].as_mut().unwrap();
+            let generation = attachment.generation.as_mut().unwrap();
+
+            generation.slots.clear();
+
+            for tile in mem::take(&mut generation.pending_tiles) {
+                if !is_ready || generation.slots.len() == attachment.max_atlas_write_slots as usize
+                {
+                    generation.deferred_tiles.push(tile);
+                    continue;
+                }
+
+                let generation_buffer = StaticBuffer::create(
+                    format!("{}_generation_buffer", attachment.name).as_str(),
+                    &device,
+                    &GenerationData {
+                        tile: tile.into(),
+                        world_from_local: Mat3::from_mat4(model.world_from_local().as_mat4()),
+                        is_spherical: model.is_spherical() as u32,
+                        min_height: model.min_height,
+                        max_height: model.max_height,
+                        tile_index: generation.slots.len() as u32,
+                    },
+                    BufferUsages::UNIFORM,
+                );
+
+                let bind_group = device.create_bind_group(
+                    format!("{}_generation_bind_group", attachment.name).as_str(),
+                    &create_generation_layout(&device),
+                    &BindGroupEntries::sequential((
+                        &generation_buffer,
+                        &source_views[0],
+                        &source_views[1],
+                        &source_views[2],
+                        &source_views[3],
+                    )),
+                );
+
+                // the tile is generated by the node in this frame
+                generation.slots.push((tile, bind_group));
+                generation.generated_tiles.push(tile);
+            }
+        }
+    }
+}
+
+pub struct AttachmentGenerationNode;
+
+impl render_graph::Node for AttachmentGenerationNode {
+    fn run<'w>(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        context: &mut RenderContext<'w>,
+        world: &'w World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let gpu_tile_atlases = world.resource::<TerrainComponents<GpuTileAtlas>>();
+
+        context.add_command_buffer_generation_task(move |device| {
+            let mut command_encoder =
+                device.create_command_encoder(&CommandEncoderDescriptor::default());
+
+            for gpu_tile_atlas in gpu_tile_atlases.values() {
+                for attachment in gpu_tile_atlas.attachments.iter().flatten() {
+                    let Some(generation) = &attachment.generation else {
+                        continue;
+                    };
+
+                    let Some(pipeline) = generation
+                        .pipeline
+                        .and_then(|id| pipeline_cache.get_compute_pipeline(id))
+                    else {
+                        continue;
+                    };
+
+                    if generation.slots.is_empty() {
+                        continue;
+                    }
+
+                    let mut compute_pass =
+                        command_encoder.begin_compute_pass(&ComputePassDescriptor::default());
+
+                    compute_pass.set_pipeline(pipeline);
+                    compute_pass.set_bind_group(0, &attachment.bind_group, &[]);
+
+                    for (_, bind_group) in &generation.slots {
+                        compute_pass.set_bind_group(1, bind_group, &[]);
+                        compute_pass.dispatch_workgroups(
+                            attachment.buffer_info.workgroup_count.x,
+                            attachment.buffer_info.workgroup_count.y,
+                            attachment.buffer_info.workgroup_count.z,
+                        );
+                    }
+
+                    drop(compute_pass);
+
+                    attachment.copy_to_atlas(
+                        generation.slots.iter().map(|&(tile, _)| tile),
+                        &mut command_encoder,
+                    );
+                }
+            }
+
+            command_encoder.finish()
+        });
+
+        Ok(())
+    }
+}
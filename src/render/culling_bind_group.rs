@@ -46,10 +46,14 @@ pub struct CullingUniform {
 
 impl From<&ExtractedView> for CullingUniform {
     fn from(view: &ExtractedView) -> Self {
+        let view_proj = view.clip_from_world.unwrap_or_else(|| {
+            view.clip_from_view * view.world_from_view.compute_matrix().inverse()
+        });
+
         Self {
             world_position: view.world_from_view.translation(),
-            view_proj: view.world_from_view.compute_matrix().inverse(),
-            planes: default(),
+            view_proj,
+            planes: planes(&view_proj),
         }
     }
 }
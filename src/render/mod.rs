@@ -5,6 +5,8 @@
 //! each view. Then they are drawn using a single draw indirect call and morphed together to form
 //! one continuous surface.
 
+pub mod attachment_bindings;
+pub mod attachment_generation;
 pub mod culling_bind_group;
 pub mod terrain_bind_group;
 pub mod terrain_material;
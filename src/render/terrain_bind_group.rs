@@ -1,5 +1,11 @@
 use crate::{
-    prelude::TileAtlas, terrain::TerrainComponents, terrain_data::gpu_tile_atlas::GpuTileAtlas,
+    prelude::TileAtlas,
+    render::attachment_bindings::{
+        max_attachment_slots, supports_binding_arrays, validate_slot_count,
+        FIRST_ATTACHMENT_BINDING,
+    },
+    terrain::TerrainComponents,
+    terrain_data::{gpu_tile_atlas::GpuTileAtlas, TileStatistics},
     util::StaticBuffer,
 };
 use bevy::{
@@ -18,29 +24,46 @@ use bevy::{
     },
 };
 use itertools::Itertools;
-use std::iter;
-
-pub(crate) fn create_terrain_layout(device: &RenderDevice) -> BindGroupLayout {
-    device.create_bind_group_layout(
-        None,
-        &BindGroupLayoutEntries::sequential(
-            ShaderStages::all(),
-            (
-                storage_buffer_read_only::<MeshUniform>(false), // mesh
-                uniform_buffer::<TerrainConfigUniform>(false),  // terrain config
-                uniform_buffer::<AttachmentUniform>(false),
-                sampler(SamplerBindingType::Filtering), // atlas sampler
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 1
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 2
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 3
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 4
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 5
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 6
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 7
-                texture_2d_array(TextureSampleType::Float { filterable: true }), // attachment 8
-            ),
+use std::{iter, num::NonZeroU32};
+
+/// Creates the layout of the terrain bind group with the amount of attachment slots.
+///
+/// The attachments are bound as a binding array, if the device supports it, and as separate
+/// bindings otherwise. See [`attachment_bindings`](crate::render::attachment_bindings).
+/// Panics, if the device can not bind that many attachments.
+pub(crate) fn create_terrain_layout(device: &RenderDevice, slot_count: u32) -> BindGroupLayout {
+    validate_slot_count(slot_count, max_attachment_slots(device));
+
+    let mut entries = BindGroupLayoutEntries::sequential(
+        ShaderStages::all(),
+        (
+            storage_buffer_read_only::<MeshUniform>(false), // mesh
+            uniform_buffer::<TerrainConfigUniform>(false),  // terrain config
+            storage_buffer_read_only::<Vec<AttachmentConfig>>(false), // attachments
+            sampler(SamplerBindingType::Filtering),         // atlas sampler
+            storage_buffer_read_only::<Vec<TileStatistics>>(false), // tile statistics
         ),
     )
+    .to_vec();
+
+    let attachment = texture_2d_array(TextureSampleType::Float { filterable: true });
+
+    if supports_binding_arrays(device) {
+        entries.push(
+            attachment
+                .count(NonZeroU32::new(slot_count).unwrap())
+                .build(FIRST_ATTACHMENT_BINDING, ShaderStages::all()),
+        );
+    } else {
+        entries.extend((0..slot_count).map(|attachment_index| {
+            attachment.build(
+                FIRST_ATTACHMENT_BINDING + attachment_index,
+                ShaderStages::all(),
+            )
+        }));
+    }
+
+    device.create_bind_group_layout(None, &entries)
 }
 
 #[derive(Default, ShaderType)]
@@ -48,27 +71,32 @@ struct AttachmentConfig {
     size: f32,
     scale: f32,
     offset: f32,
-    _padding: u32,
+    min_lod: u32,
+    max_lod: u32,
 }
 
-#[derive(Default, ShaderType)]
-struct AttachmentUniform {
-    data: [AttachmentConfig; 8],
-}
+impl AttachmentConfig {
+    /// The configs of all attachment slots, vacant attachments keep the default config.
+    fn from_tile_atlas(tile_atlas: &GpuTileAtlas) -> Vec<Self> {
+        let mut configs = (0..tile_atlas.attachment_layout_key().slot_count)
+            .map(|_| Self::default())
+            .collect_vec();
 
-impl AttachmentUniform {
-    fn new(tile_atlas: &GpuTileAtlas) -> Self {
-        let mut uniform = Self::default();
+        for (config, attachment) in iter::zip(&mut configs, &tile_atlas.attachments) {
+            let Some(attachment) = attachment else {
+                continue;
+            };
 
-        for (config, attachment) in iter::zip(&mut uniform.data, &tile_atlas.attachments) {
             config.size = attachment.buffer_info.center_size as f32;
             config.scale = attachment.buffer_info.center_size as f32
                 / attachment.buffer_info.texture_size as f32;
             config.offset = attachment.buffer_info.border_size as f32
                 / attachment.buffer_info.texture_size as f32;
+            config.min_lod = attachment.lod_range.start;
+            config.max_lod = attachment.lod_range.end - 1;
         }
 
-        uniform
+        configs
     }
 }
 
@@ -79,6 +107,7 @@ struct TerrainConfigUniform {
     min_height: f32,
     max_height: f32,
     scale: f32,
+    root_count: UVec2,
 }
 
 impl TerrainConfigUniform {
@@ -88,12 +117,20 @@ impl TerrainConfigUniform {
             min_height: tile_atlas.model.min_height,
             max_height: tile_atlas.model.max_height,
             scale: tile_atlas.model.scale() as f32,
+            root_count: tile_atlas.model.root_count(),
         }
     }
 }
 
 pub struct TerrainData {
     mesh_buffer: StaticBuffer<MeshUniform>,
+    tile_statistics: Vec<TileStatistics>,
+    tile_statistics_buffer: StaticBuffer<Vec<TileStatistics>>,
+    tile_statistics_changed: bool,
+    /// The statistics generation of the tile atlas, which the tile statistics were computed at.
+    statistics_generation: u32,
+    /// The generation of the [`GpuTileAtlas`], whose textures are bound.
+    atlas_generation: u32,
     pub(crate) terrain_bind_group: BindGroup,
 }
 
@@ -117,6 +154,14 @@ impl TerrainData {
             BufferUsages::UNIFORM,
         );
 
+        let tile_statistics = tile_atlas.atlas_tile_statistics();
+        let tile_statistics_buffer = StaticBuffer::create(
+            None,
+            device,
+            &tile_statistics,
+            BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        );
+
         let atlas_sampler = device.create_sampler(&SamplerDescriptor {
             mag_filter: FilterMode::Linear,
             min_filter: FilterMode::Linear,
@@ -125,42 +170,69 @@ impl TerrainData {
             ..default()
         });
 
-        let attachments = (0..8)
-            .map(|i| {
+        let slot_count = gpu_tile_atlas.attachment_layout_key().slot_count;
+
+        // vacant attachment slots are bound to the fallback image
+        let attachments = (0..slot_count as usize)
+            .map(|attachment_index| {
                 gpu_tile_atlas
                     .attachments
-                    .get(i)
+                    .get(attachment_index)
+                    .and_then(Option::as_ref)
                     .map_or(fallback_image.d2_array.texture_view.clone(), |attachment| {
                         attachment.atlas_texture.create_view(&default())
                     })
             })
             .collect_vec();
 
-        let attachment_uniform = AttachmentUniform::new(gpu_tile_atlas);
-        let attachment_buffer =
-            StaticBuffer::create(None, device, &attachment_uniform, BufferUsages::UNIFORM);
+        let attachment_buffer = StaticBuffer::create(
+            None,
+            device,
+            &AttachmentConfig::from_tile_atlas(gpu_tile_atlas),
+            BufferUsages::STORAGE,
+        );
+
+        let mut entries = BindGroupEntries::sequential((
+            &mesh_buffer,
+            &terrain_config_buffer,
+            &attachment_buffer,
+            &atlas_sampler,
+            &tile_statistics_buffer,
+        ))
+        .to_vec();
+
+        let attachment_views = attachments.iter().map(|view| &**view).collect_vec();
+
+        if supports_binding_arrays(device) {
+            entries.push(BindGroupEntry {
+                binding: FIRST_ATTACHMENT_BINDING,
+                resource: BindingResource::TextureViewArray(&attachment_views),
+            });
+        } else {
+            entries.extend(
+                attachment_views
+                    .iter()
+                    .enumerate()
+                    .map(|(attachment_index, &view)| BindGroupEntry {
+                        binding: FIRST_ATTACHMENT_BINDING + attachment_index as u32,
+                        resource: BindingResource::TextureView(view),
+                    }),
+            );
+        }
 
         let terrain_bind_group = device.create_bind_group(
             "terrain_bind_group",
-            &create_terrain_layout(device),
-            &BindGroupEntries::sequential((
-                &mesh_buffer,
-                &terrain_config_buffer,
-                &attachment_buffer,
-                &atlas_sampler,
-                &attachments[0],
-                &attachments[1],
-                &attachments[2],
-                &attachments[3],
-                &attachments[4],
-                &attachments[5],
-                &attachments[6],
-                &attachments[7],
-            )),
+            &create_terrain_layout(device, slot_count),
+            &entries,
         );
 
         Self {
             mesh_buffer,
+            tile_statistics,
+            tile_statistics_buffer,
+            tile_statistics_changed: false,
+            statistics_generation: tile_atlas.state.statistics_generation,
+            atlas_generation: gpu_tile_atlas.generation,
             terrain_bind_group,
         }
     }
@@ -170,25 +242,38 @@ impl TerrainData {
         fallback_image: Res<FallbackImage>,
         mut terrain_data: ResMut<TerrainComponents<TerrainData>>,
         gpu_tile_atlases: Res<TerrainComponents<GpuTileAtlas>>,
-        tile_atlases: Extract<Query<(Entity, &TileAtlas), Added<TileAtlas>>>,
+        tile_atlases: Extract<Query<(Entity, &TileAtlas)>>,
     ) {
         for (terrain, tile_atlas) in &tile_atlases {
             let gpu_tile_atlas = gpu_tile_atlases.get(&terrain).unwrap();
 
+            // the bind group is recreated, once the atlas textures have been resized
+            if terrain_data.get(&terrain).is_some_and(|terrain_data| {
+                terrain_data.atlas_generation == gpu_tile_atlas.generation
+            }) {
+                continue;
+            }
+
             terrain_data.insert(
                 terrain,
-                TerrainData::new(&device, &fallback_image, tile_atlas.into(), gpu_tile_atlas),
+                TerrainData::new(&device, &fallback_image, tile_atlas, gpu_tile_atlas),
             );
         }
     }
 
+    #[allow(clippy::type_complexity)]
     pub(crate) fn extract(
         mut terrain_data: ResMut<TerrainComponents<TerrainData>>,
         terrains: Extract<
-            Query<(Entity, &GlobalTransform, Option<&PreviousGlobalTransform>), With<TileAtlas>>,
+            Query<(
+                Entity,
+                &TileAtlas,
+                &GlobalTransform,
+                Option<&PreviousGlobalTransform>,
+            )>,
         >,
     ) {
-        for (terrain, transform, previous_transform) in terrains.iter() {
+        for (terrain, tile_atlas, transform, previous_transform) in terrains.iter() {
             let mesh_transforms = MeshTransforms {
                 world_from_local: (&transform.affine()).into(),
                 flags: 0,
@@ -201,6 +286,18 @@ impl TerrainData {
 
             let terrain_data = terrain_data.get_mut(&terrain).unwrap();
             terrain_data.mesh_buffer.set_value(mesh_uniform);
+
+            // the statistics are only rebuilt, once tiles have been loaded, evicted or saved
+            if tile_atlas.state.statistics_generation != terrain_data.statistics_generation {
+                terrain_data.statistics_generation = tile_atlas.state.statistics_generation;
+
+                let tile_statistics = tile_atlas.atlas_tile_statistics();
+
+                if tile_statistics != terrain_data.tile_statistics {
+                    terrain_data.tile_statistics = tile_statistics;
+                    terrain_data.tile_statistics_changed = true;
+                }
+            }
         }
     }
 
@@ -210,6 +307,15 @@ impl TerrainData {
     ) {
         for terrain_data in &mut terrain_data.values_mut() {
             terrain_data.mesh_buffer.update(&queue);
+
+            if terrain_data.tile_statistics_changed {
+                let tile_statistics = terrain_data.tile_statistics.clone();
+                terrain_data
+                    .tile_statistics_buffer
+                    .set_value(tile_statistics);
+                terrain_data.tile_statistics_buffer.update(&queue);
+                terrain_data.tile_statistics_changed = false;
+            }
         }
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
     debug::DebugTerrain,
     render::{
+        attachment_bindings::AttachmentLayoutKey,
         terrain_bind_group::{create_terrain_layout, SetTerrainBindGroup},
         terrain_view_bind_group::{
             create_terrain_view_layout, DrawTerrainCommand, SetTerrainViewBindGroup,
@@ -34,6 +35,7 @@ use std::{hash::Hash, marker::PhantomData};
 
 pub struct TerrainPipelineKey<M: Material> {
     pub flags: TerrainPipelineFlags,
+    pub attachment_layout: AttachmentLayoutKey,
     pub bind_group_data: M::Data,
 }
 
@@ -44,7 +46,9 @@ where
     M::Data: PartialEq,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.flags == other.flags && self.bind_group_data == other.bind_group_data
+        self.flags == other.flags
+            && self.attachment_layout == other.attachment_layout
+            && self.bind_group_data == other.bind_group_data
     }
 }
 
@@ -55,6 +59,7 @@ where
     fn clone(&self) -> Self {
         Self {
             flags: self.flags,
+            attachment_layout: self.attachment_layout,
             bind_group_data: self.bind_group_data.clone(),
         }
     }
@@ -66,6 +71,7 @@ where
 {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.flags.hash(state);
+        self.attachment_layout.hash(state);
         self.bind_group_data.hash(state);
     }
 }
@@ -232,7 +238,8 @@ impl TerrainPipelineFlags {
 pub struct TerrainRenderPipeline<M: Material> {
     pub(crate) view_layout: BindGroupLayout,
     pub(crate) view_layout_multisampled: BindGroupLayout,
-    pub(crate) terrain_layout: BindGroupLayout,
+    /// The terrain layout depends on the attachments, so it is created for each pipeline.
+    device: RenderDevice,
     pub(crate) terrain_view_layout: BindGroupLayout,
     pub(crate) material_layout: BindGroupLayout,
     pub vertex_shader: Handle<Shader>,
@@ -252,7 +259,6 @@ impl<M: Material> FromWorld for TerrainRenderPipeline<M> {
         let view_layout_multisampled = mesh_pipeline
             .get_view_layout(MeshPipelineViewLayoutKey::MULTISAMPLED)
             .clone();
-        let terrain_layout = create_terrain_layout(device);
         let terrain_view_layout = create_terrain_view_layout(device);
         let material_layout = M::bind_group_layout(device);
 
@@ -271,7 +277,7 @@ impl<M: Material> FromWorld for TerrainRenderPipeline<M> {
         Self {
             view_layout,
             view_layout_multisampled,
-            terrain_layout,
+            device: device.clone(),
             terrain_view_layout,
             material_layout,
             vertex_shader,
@@ -289,6 +295,7 @@ where
 
     fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
         let mut shader_defs = key.flags.shader_defs();
+        shader_defs.extend(key.attachment_layout.shader_defs());
 
         let mut bind_group_layout = match key.flags.msaa_samples() {
             1 => vec![self.view_layout.clone()],
@@ -298,7 +305,10 @@ where
             }
         };
 
-        bind_group_layout.push(self.terrain_layout.clone());
+        bind_group_layout.push(create_terrain_layout(
+            &self.device,
+            key.attachment_layout.slot_count,
+        ));
         bind_group_layout.push(self.terrain_view_layout.clone());
         bind_group_layout.push(self.material_layout.clone());
 
@@ -410,6 +420,7 @@ pub(crate) fn queue_terrain<M: Material>(
 
                 let key = TerrainPipelineKey {
                     flags,
+                    attachment_layout: gpu_tile_atlas.attachment_layout_key(),
                     bind_group_data: material.key.clone(),
                 };
 
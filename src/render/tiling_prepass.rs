@@ -38,10 +38,25 @@ bitflags::bitflags! {
         const TEST1          = 1 << 5;
         const TEST2          = 1 << 6;
         const TEST3          = 1 << 7;
+        const ATTACHMENT_SLOT_RESERVED_BITS = TilingPrepassPipelineKey::ATTACHMENT_SLOT_MASK_BITS << TilingPrepassPipelineKey::ATTACHMENT_SLOT_SHIFT_BITS;
     }
 }
 
 impl TilingPrepassPipelineKey {
+    const ATTACHMENT_SLOT_MASK_BITS: u32 = 0xffff;
+    const ATTACHMENT_SLOT_SHIFT_BITS: u32 = 32 - 16;
+
+    /// The terrain bind group layout depends on the amount of attachment slots.
+    pub fn from_attachment_slot_count(slot_count: u32) -> Self {
+        let slot_bits =
+            (slot_count & Self::ATTACHMENT_SLOT_MASK_BITS) << Self::ATTACHMENT_SLOT_SHIFT_BITS;
+        TilingPrepassPipelineKey::from_bits(slot_bits).unwrap()
+    }
+
+    pub fn attachment_slot_count(&self) -> u32 {
+        (self.bits() >> Self::ATTACHMENT_SLOT_SHIFT_BITS) & Self::ATTACHMENT_SLOT_MASK_BITS
+    }
+
     pub fn from_debug(debug: &DebugTerrain) -> Self {
         let mut key = TilingPrepassPipelineKey::NONE;
 
@@ -90,10 +105,10 @@ impl TilingPrepassItem {
         &'a self,
         pipeline_cache: &'a PipelineCache,
     ) -> Option<(
-        &ComputePipeline,
-        &ComputePipeline,
-        &ComputePipeline,
-        &ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
+        &'a ComputePipeline,
     )> {
         Some((
             pipeline_cache.get_compute_pipeline(self.refine_tiles_pipeline)?,
@@ -109,7 +124,8 @@ pub struct TilingPrepassPipelines {
     pub(crate) prepare_indirect_layout: BindGroupLayout,
     pub(crate) refine_tiles_layout: BindGroupLayout,
     culling_data_layout: BindGroupLayout,
-    terrain_layout: BindGroupLayout,
+    /// The terrain layout depends on the attachments, so it is created for each pipeline.
+    device: RenderDevice,
     prepare_prepass_shader: Handle<Shader>,
     refine_tiles_shader: Handle<Shader>,
 }
@@ -122,7 +138,6 @@ impl FromWorld for TilingPrepassPipelines {
         let prepare_indirect_layout = create_prepare_indirect_layout(device);
         let refine_tiles_layout = create_refine_tiles_layout(device);
         let culling_data_layout = create_culling_layout(device);
-        let terrain_layout = create_terrain_layout(device);
 
         let prepare_prepass_shader = asset_server.load(PREPARE_PREPASS_SHADER);
         let refine_tiles_shader = asset_server.load(REFINE_TILES_SHADER);
@@ -131,7 +146,7 @@ impl FromWorld for TilingPrepassPipelines {
             prepare_indirect_layout,
             refine_tiles_layout,
             culling_data_layout,
-            terrain_layout,
+            device: device.clone(),
             prepare_prepass_shader,
             refine_tiles_shader,
         }
@@ -147,11 +162,12 @@ impl SpecializedComputePipeline for TilingPrepassPipelines {
         let mut entry_point = default();
 
         let shader_defs = key.shader_defs();
+        let terrain_layout = create_terrain_layout(&self.device, key.attachment_slot_count());
 
         if key.contains(TilingPrepassPipelineKey::REFINE_TILES) {
             layout = vec![
                 self.culling_data_layout.clone(),
-                self.terrain_layout.clone(),
+                terrain_layout.clone(),
                 self.refine_tiles_layout.clone(),
             ];
             shader = self.refine_tiles_shader.clone();
@@ -160,7 +176,7 @@ impl SpecializedComputePipeline for TilingPrepassPipelines {
         if key.contains(TilingPrepassPipelineKey::PREPARE_ROOT) {
             layout = vec![
                 self.culling_data_layout.clone(),
-                self.terrain_layout.clone(),
+                terrain_layout.clone(),
                 self.refine_tiles_layout.clone(),
                 self.prepare_indirect_layout.clone(),
             ];
@@ -170,7 +186,7 @@ impl SpecializedComputePipeline for TilingPrepassPipelines {
         if key.contains(TilingPrepassPipelineKey::PREPARE_NEXT) {
             layout = vec![
                 self.culling_data_layout.clone(),
-                self.terrain_layout.clone(),
+                terrain_layout.clone(),
                 self.refine_tiles_layout.clone(),
                 self.prepare_indirect_layout.clone(),
             ];
@@ -180,7 +196,7 @@ impl SpecializedComputePipeline for TilingPrepassPipelines {
         if key.contains(TilingPrepassPipelineKey::PREPARE_RENDER) {
             layout = vec![
                 self.culling_data_layout.clone(),
-                self.terrain_layout.clone(),
+                terrain_layout.clone(),
                 self.refine_tiles_layout.clone(),
                 self.prepare_indirect_layout.clone(),
             ];
@@ -283,7 +299,9 @@ pub(crate) fn queue_tiling_prepass(
     for &(terrain, view) in gpu_tile_trees.keys() {
         let gpu_tile_atlas = gpu_tile_atlases.get(&terrain).unwrap();
 
-        let mut key = TilingPrepassPipelineKey::NONE;
+        let mut key = TilingPrepassPipelineKey::from_attachment_slot_count(
+            gpu_tile_atlas.attachment_layout_key().slot_count,
+        );
 
         if gpu_tile_atlas.is_spherical {
             key |= TilingPrepassPipelineKey::SPHERICAL;
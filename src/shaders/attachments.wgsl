@@ -1,54 +1,22 @@
 #define_import_path bevy_terrain::attachments
 
 #import bevy_terrain::types::AtlasTile
-#import bevy_terrain::bindings::{config, atlas_sampler, attachments, attachment0_atlas, attachment1_atlas, attachment2_atlas}
-#import bevy_terrain::functions::tile_count
-
-fn attachment_uv(uv: vec2<f32>, attachment_index: u32) -> vec2<f32> {
-    let attachment = attachments[attachment_index];
-    return uv * attachment.scale + attachment.offset;
-}
-
-fn sample_attachment0(tile: AtlasTile) -> vec4<f32> {
-    let uv = attachment_uv(tile.coordinate.uv, 0u);
-
-#ifdef FRAGMENT
-#ifdef SAMPLE_GRAD
-    return textureSampleGrad(attachment0_atlas, atlas_sampler, uv, tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy);
-#else
-    return textureSampleLevel(attachment0_atlas, atlas_sampler, uv, tile.index, 0.0);
-#endif
-#else
-    return textureSampleLevel(attachment0_atlas, atlas_sampler, uv, tile.index, 0.0);
-#endif
-}
-
-fn sample_attachment1(tile: AtlasTile) -> vec4<f32> {
-    let uv = attachment_uv(tile.coordinate.uv, 1u);
-
-#ifdef FRAGMENT
-#ifdef SAMPLE_GRAD
-    return textureSampleGrad(attachment1_atlas, atlas_sampler, uv, tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy);
-#else
-    return textureSampleLevel(attachment1_atlas, atlas_sampler, uv, tile.index, 0.0);
-#endif
-#else
-    return textureSampleLevel(attachment1_atlas, atlas_sampler, uv, tile.index, 0.0);
-#endif
-}
-
-fn sample_attachment1_gather0(tile: AtlasTile) -> vec4<f32> {
-    let uv = attachment_uv(tile.coordinate.uv, 1u);
-    return textureGather(0, attachment1_atlas, atlas_sampler, uv, tile.index);
-}
+#import bevy_terrain::bindings::{config, attachments}
+#import bevy_terrain::attachment_bindings::{attachment_uv, sample_attachment, sample_attachment_level, sample_attachment_grad}
+#import bevy_terrain::functions::{tile_count, lookup_attachment_tile}
 
 fn sample_height(tile: AtlasTile) -> f32 {
-    let height = sample_attachment0(tile).x;
+    let height = sample_attachment(tile, 0u).x;
 
     return mix(config.min_height, config.max_height, height);
 }
 
-fn sample_normal(tile: AtlasTile, vertex_normal: vec3<f32>) -> vec3<f32> {
+fn sample_normal(lookup_tile: AtlasTile, vertex_normal: vec3<f32>) -> vec3<f32> {
+    let tile = lookup_attachment_tile(lookup_tile, 0u);
+
+    // coarser tiles do not contain the height attachment
+    if (tile.coordinate.lod < attachments[0u].min_lod) { return normalize(vertex_normal); }
+
     let uv = attachment_uv(tile.coordinate.uv, 0u);
 
 #ifdef SPHERICAL
@@ -84,21 +52,21 @@ fn sample_normal(tile: AtlasTile, vertex_normal: vec3<f32>) -> vec3<f32> {
 
 #ifdef FRAGMENT
 #ifdef SAMPLE_GRAD
-    let left  = mix(config.min_height, config.max_height, textureSampleGrad(attachment0_atlas, atlas_sampler, uv + vec2<f32>(-offset,     0.0), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
-    let up    = mix(config.min_height, config.max_height, textureSampleGrad(attachment0_atlas, atlas_sampler, uv + vec2<f32>(    0.0, -offset), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
-    let right = mix(config.min_height, config.max_height, textureSampleGrad(attachment0_atlas, atlas_sampler, uv + vec2<f32>( offset,     0.0), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
-    let down  = mix(config.min_height, config.max_height, textureSampleGrad(attachment0_atlas, atlas_sampler, uv + vec2<f32>(    0.0,  offset), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
+    let left  = mix(config.min_height, config.max_height, sample_attachment_grad(0u, uv + vec2<f32>(-offset,     0.0), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
+    let up    = mix(config.min_height, config.max_height, sample_attachment_grad(0u, uv + vec2<f32>(    0.0, -offset), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
+    let right = mix(config.min_height, config.max_height, sample_attachment_grad(0u, uv + vec2<f32>( offset,     0.0), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
+    let down  = mix(config.min_height, config.max_height, sample_attachment_grad(0u, uv + vec2<f32>(    0.0,  offset), tile.index, tile.coordinate.uv_dx, tile.coordinate.uv_dy).x);
 #else
-    let left  = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>(-offset,     0.0), tile.index, 0.0).x);
-    let up    = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>(    0.0, -offset), tile.index, 0.0).x);
-    let right = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>( offset,     0.0), tile.index, 0.0).x);
-    let down  = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>(    0.0,  offset), tile.index, 0.0).x);
+    let left  = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>(-offset,     0.0), tile.index, 0.0).x);
+    let up    = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>(    0.0, -offset), tile.index, 0.0).x);
+    let right = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>( offset,     0.0), tile.index, 0.0).x);
+    let down  = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>(    0.0,  offset), tile.index, 0.0).x);
 #endif
 #else
-    let left  = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>(-offset,     0.0), tile.index, 0.0).x);
-    let up    = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>(    0.0, -offset), tile.index, 0.0).x);
-    let right = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>( offset,     0.0), tile.index, 0.0).x);
-    let down  = mix(config.min_height, config.max_height, textureSampleLevel(attachment0_atlas, atlas_sampler, uv + vec2<f32>(    0.0,  offset), tile.index, 0.0).x);
+    let left  = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>(-offset,     0.0), tile.index, 0.0).x);
+    let up    = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>(    0.0, -offset), tile.index, 0.0).x);
+    let right = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>( offset,     0.0), tile.index, 0.0).x);
+    let down  = mix(config.min_height, config.max_height, sample_attachment_level(0u, uv + vec2<f32>(    0.0,  offset), tile.index, 0.0).x);
 #endif
 
     let surface_normal = normalize(vec3<f32>(left - right, down - up, distance_between_samples));
@@ -107,7 +75,7 @@ fn sample_normal(tile: AtlasTile, vertex_normal: vec3<f32>) -> vec3<f32> {
 }
 
 fn sample_color(tile: AtlasTile) -> vec4<f32> {
-    let height = sample_attachment0(tile).x;
+    let height = sample_attachment(tile, 0u).x;
 
     return vec4<f32>(height * 0.5);
 }
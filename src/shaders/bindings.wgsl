@@ -1,6 +1,6 @@
 #define_import_path bevy_terrain::bindings
 
-#import bevy_terrain::types::{TerrainViewConfig, TerrainConfig, TileTreeEntry, TileCoordinate, AttachmentConfig, TerrainModelApproximation, CullingData, IndirectBuffer, Parameters}
+#import bevy_terrain::types::{TerrainViewConfig, TerrainConfig, TileTreeEntry, TileCoordinate, AttachmentConfig, TileStatistics, TerrainModelApproximation, CullingData, IndirectBuffer, Parameters}
 #import bevy_pbr::mesh_types::Mesh
 
 // terrain bindings
@@ -9,25 +9,12 @@ var<storage> mesh: array<Mesh>;
 @group(1) @binding(1)
 var<uniform> config: TerrainConfig;
 @group(1) @binding(2)
-var<uniform> attachments: array<AttachmentConfig, 8u>;
+var<storage> attachments: array<AttachmentConfig>;
 @group(1) @binding(3)
 var atlas_sampler: sampler;
 @group(1) @binding(4)
-var attachment0_atlas: texture_2d_array<f32>;
-@group(1) @binding(5)
-var attachment1_atlas: texture_2d_array<f32>;
-@group(1) @binding(6)
-var attachment2_atlas: texture_2d_array<f32>;
-@group(1) @binding(7)
-var attachment3_atlas: texture_2d_array<f32>;
-@group(1) @binding(8)
-var attachment4_atlas: texture_2d_array<f32>;
-@group(1) @binding(9)
-var attachment5_atlas: texture_2d_array<f32>;
-@group(1) @binding(10)
-var attachment6_atlas: texture_2d_array<f32>;
-@group(1) @binding(11)
-var attachment7_atlas: texture_2d_array<f32>;
+var<storage> tile_statistics: array<TileStatistics>;
+// the attachment textures are bound by bevy_terrain::attachment_bindings
 
 // terrain view bindings
 @group(2) @binding(0)
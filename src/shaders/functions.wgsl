@@ -1,6 +1,6 @@
 #define_import_path bevy_terrain::functions
 
-#import bevy_terrain::bindings::{mesh, config, origins, view_config, geometry_tiles, tile_tree, terrain_model_approximation}
+#import bevy_terrain::bindings::{mesh, config, attachments, origins, view_config, geometry_tiles, tile_tree, terrain_model_approximation}
 #import bevy_terrain::types::{TileCoordinate, TileTree, TileTreeEntry, AtlasTile, Blend, BestLookup, Coordinate, Morph}
 #import bevy_pbr::mesh_view_bindings::view
 #import bevy_render::maths::{affine3_to_square, mat2x4_f32_to_mat3x3_unpack}
@@ -189,7 +189,7 @@ fn coordinate_change_lod(coordinate: ptr<function, Coordinate>, new_lod: u32) {
 
 fn compute_tile_tree_uv(coordinate: Coordinate) -> vec2<f32> {
     let origin_xy = vec2<i32>(origins[coordinate.side * config.lod_count + coordinate.lod]);
-    let tree_size = min(f32(view_config.tree_size), tile_count(coordinate.lod));
+    let tree_size = min(vec2<f32>(f32(view_config.tree_size)), tile_count(coordinate.lod) * vec2<f32>(config.root_count));
 
     return (vec2<f32>(vec2<i32>(coordinate.xy) - origin_xy) + coordinate.uv) / tree_size;
 }
@@ -211,7 +211,7 @@ fn lookup_best(lookup_coordinate: Coordinate) -> BestLookup {
 
     var new_coordinate   = lookup_coordinate;
     coordinate_change_lod(&new_coordinate , 0u);
-    var new_tile_tree_uv = new_coordinate.uv;
+    var new_tile_tree_uv = compute_tile_tree_uv(new_coordinate);
 
     while (new_coordinate.lod < config.lod_count && !any(new_tile_tree_uv <= vec2<f32>(0.0)) && !any(new_tile_tree_uv >= vec2<f32>(1.0))) {
         coordinate  = new_coordinate;
@@ -244,3 +244,20 @@ fn lookup_tile(lookup_coordinate: Coordinate, blend: Blend, lod_offset: u32) ->
     return AtlasTile(tile_tree_entry.atlas_index, coordinate);
 #endif
 }
+
+// Finer tiles do not contain attachments, which end at a coarser lod, so their closest ancestor within the lod range is used instead.
+fn lookup_attachment_tile(tile: AtlasTile, attachment_index: u32) -> AtlasTile {
+    let max_lod = attachments[attachment_index].max_lod;
+
+    if (tile.coordinate.lod <= max_lod) { return tile; }
+
+    var coordinate = tile.coordinate;
+
+    coordinate_change_lod(&coordinate, max_lod);
+
+    let tile_tree_entry = lookup_tile_tree_entry(coordinate);
+
+    coordinate_change_lod(&coordinate, tile_tree_entry.atlas_lod);
+
+    return AtlasTile(tile_tree_entry.atlas_index, coordinate);
+}
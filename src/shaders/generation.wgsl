@@ -0,0 +1,35 @@
+#define_import_path bevy_terrain::generation
+#import bevy_terrain::preprocessing::{AtlasTile, atlas_sampler}
+
+struct GenerationData {
+    tile: AtlasTile,
+    world_from_local: mat3x3<f32>,
+    is_spherical: u32,
+    min_height: f32,
+    max_height: f32,
+    tile_index: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> generation_data: GenerationData;
+@group(1) @binding(1)
+var source_atlas_0: texture_2d_array<f32>;
+@group(1) @binding(2)
+var source_atlas_1: texture_2d_array<f32>;
+@group(1) @binding(3)
+var source_atlas_2: texture_2d_array<f32>;
+@group(1) @binding(4)
+var source_atlas_3: texture_2d_array<f32>;
+
+// Samples a source attachment of the tile, the uv is in the texture space (including the border) of the source.
+fn sample_source(source_index: u32, uv: vec2<f32>) -> vec4<f32> {
+    let atlas_index = generation_data.tile.atlas_index;
+
+    switch (source_index) {
+        case 0u:      { return textureSampleLevel(source_atlas_0, atlas_sampler, uv, atlas_index, 0.0); }
+        case 1u:      { return textureSampleLevel(source_atlas_1, atlas_sampler, uv, atlas_index, 0.0); }
+        case 2u:      { return textureSampleLevel(source_atlas_2, atlas_sampler, uv, atlas_index, 0.0); }
+        case 3u:      { return textureSampleLevel(source_atlas_3, atlas_sampler, uv, atlas_index, 0.0); }
+        case default: { return vec4<f32>(0.0); }
+    }
+}
@@ -11,6 +11,9 @@ pub(crate) const SPLIT_SHADER: &str = "embedded://bevy_terrain/shaders/preproces
 pub(crate) const STITCH_SHADER: &str = "embedded://bevy_terrain/shaders/preprocess/stitch.wgsl";
 pub(crate) const DOWNSAMPLE_SHADER: &str =
     "embedded://bevy_terrain/shaders/preprocess/downsample.wgsl";
+pub(crate) const DERIVE_SHADER: &str = "embedded://bevy_terrain/shaders/preprocess/derive.wgsl";
+pub(crate) const FILL_SHADER: &str = "embedded://bevy_terrain/shaders/preprocess/fill.wgsl";
+pub(crate) const GENERATE_SHADER: &str = "embedded://bevy_terrain/shaders/preprocess/generate.wgsl";
 
 #[derive(Default, Resource)]
 pub(crate) struct InternalShaders(Vec<Handle<Shader>>);
@@ -37,6 +40,8 @@ pub(crate) fn load_terrain_shaders(app: &mut App) {
     embedded_asset!(app, "render/fragment.wgsl");
     embedded_asset!(app, "tiling_prepass/prepare_prepass.wgsl");
     embedded_asset!(app, "tiling_prepass/refine_tiles.wgsl");
+    embedded_asset!(app, "preprocess/preprocessing.wgsl");
+    embedded_asset!(app, "generation.wgsl");
 
     InternalShaders::load(
         app,
@@ -48,18 +53,17 @@ pub(crate) fn load_terrain_shaders(app: &mut App) {
             "embedded://bevy_terrain/shaders/debug.wgsl",
             "embedded://bevy_terrain/shaders/render/vertex.wgsl",
             "embedded://bevy_terrain/shaders/render/fragment.wgsl",
+            "embedded://bevy_terrain/shaders/preprocess/preprocessing.wgsl",
+            "embedded://bevy_terrain/shaders/generation.wgsl",
         ],
     );
 }
 
 pub(crate) fn load_preprocess_shaders(app: &mut App) {
-    embedded_asset!(app, "preprocess/preprocessing.wgsl");
     embedded_asset!(app, "preprocess/split.wgsl");
     embedded_asset!(app, "preprocess/stitch.wgsl");
     embedded_asset!(app, "preprocess/downsample.wgsl");
-
-    InternalShaders::load(
-        app,
-        &["embedded://bevy_terrain/shaders/preprocess/preprocessing.wgsl"],
-    );
+    embedded_asset!(app, "preprocess/derive.wgsl");
+    embedded_asset!(app, "preprocess/fill.wgsl");
+    embedded_asset!(app, "preprocess/generate.wgsl");
 }
@@ -0,0 +1,163 @@
+#import bevy_terrain::preprocessing::{AtlasTile, attachment, pixel_value, process_entry, is_border, local_position as tile_local_position}
+
+const PI: f32 = 3.14159265359;
+
+const TANGENT_NORMAL: u32    = 0u;
+const WORLD_NORMAL: u32      = 1u;
+const SLOPE: u32             = 2u;
+const CURVATURE: u32         = 3u;
+const AMBIENT_OCCLUSION: u32 = 4u;
+const MASK: u32              = 5u;
+
+// zero marks missing data, so scalar values are never stored as zero
+const MIN_VALUE: f32 = 1.0 / 65535.0;
+
+struct DeriveData {
+    tile: AtlasTile,
+    world_from_local: mat3x3<f32>,
+    kind: u32,
+    parameter: f32,
+    is_spherical: u32,
+    min_height: f32,
+    max_height: f32,
+    tile_index: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> derive_data: DeriveData;
+@group(1) @binding(1)
+var source_atlas: texture_2d_array<f32>;
+
+fn local_position(coords: vec2<i32>) -> vec3<f32> {
+    return tile_local_position(derive_data.tile.coordinate, vec2<f32>(coords), derive_data.is_spherical == 1u);
+}
+
+fn surface_normal(local_position: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), local_position, derive_data.is_spherical == 1u);
+
+    return normalize(derive_data.world_from_local * up);
+}
+
+fn height(coords: vec2<i32>) -> f32 {
+    let clamped_coords = clamp(coords, vec2<i32>(0), vec2<i32>(i32(attachment.texture_size) - 1));
+    let value = textureLoad(source_atlas, clamped_coords, derive_data.tile.atlas_index, 0).x;
+
+    return mix(derive_data.min_height, derive_data.max_height, value);
+}
+
+// The world space position relative to the base surface at the center, which keeps the precision for large models.
+fn relative_position(coords: vec2<i32>, center: vec3<f32>) -> vec3<f32> {
+    let local_position = local_position(coords);
+
+    return derive_data.world_from_local * (local_position - center) + height(coords) * surface_normal(local_position);
+}
+
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    if (derive_data.is_spherical == 0u) {
+        return mat3x3(normalize(derive_data.world_from_local * vec3<f32>(1.0, 0.0, 0.0)),
+                      normalize(derive_data.world_from_local * vec3<f32>(0.0, 0.0, 1.0)),
+                      normal);
+    }
+
+    // same tangent frame as sample_normal
+    var FACE_UP = array(
+        vec3( 0.0, 1.0,  0.0),
+        vec3( 0.0, 1.0,  0.0),
+        vec3( 0.0, 0.0, -1.0),
+        vec3( 0.0, 0.0, -1.0),
+        vec3(-1.0, 0.0,  0.0),
+        vec3(-1.0, 0.0,  0.0),
+    );
+
+    let face_up   = normalize(derive_data.world_from_local * FACE_UP[derive_data.tile.coordinate.side]);
+    let tangent   = normalize(cross(face_up, normal));
+    let bitangent = cross(normal, tangent);
+
+    return mat3x3(tangent, bitangent, normal);
+}
+
+fn ambient_occlusion(coords: vec2<i32>, center: vec3<f32>, base_normal: vec3<f32>) -> f32 {
+    let radius = i32(derive_data.parameter);
+    let origin = relative_position(coords, center);
+
+    var occlusion = 0.0;
+
+    for (var direction_index = 0u; direction_index < 8u; direction_index += 1u) {
+        let angle = f32(direction_index) * PI / 4.0;
+        let direction = vec2<f32>(cos(angle), sin(angle));
+
+        var horizon = 0.0;
+
+        for (var distance_step = 1; distance_step <= radius; distance_step += 1) {
+            let sample_coords = coords + vec2<i32>(round(direction * f32(distance_step)));
+            let offset = relative_position(sample_coords, center) - origin;
+
+            if (length(offset) > 0.0) {
+                horizon = max(horizon, dot(normalize(offset), base_normal));
+            }
+        }
+
+        occlusion += horizon;
+    }
+
+    return 1.0 - occlusion / 8.0;
+}
+
+override fn pixel_value(coords: vec2<u32>) -> vec4<f32> {
+    // the border is filled by stitching afterwards
+    if (is_border(coords)) {
+        return vec4<f32>(0.0);
+    }
+
+    if (derive_data.kind == MASK) {
+        let value = textureLoad(source_atlas, coords, derive_data.tile.atlas_index, 0);
+        return vec4<f32>(select(0.0, 1.0, any(value.xyz != vec3<f32>(0.0))));
+    }
+
+    let pixel_coords = vec2<i32>(coords);
+    let center       = local_position(pixel_coords);
+    let base_normal  = surface_normal(center);
+
+    let dx = relative_position(pixel_coords + vec2<i32>(1, 0), center) - relative_position(pixel_coords - vec2<i32>(1, 0), center);
+    let dy = relative_position(pixel_coords + vec2<i32>(0, 1), center) - relative_position(pixel_coords - vec2<i32>(0, 1), center);
+
+    var normal = normalize(cross(dy, dx));
+    normal = select(normal, -normal, dot(normal, base_normal) < 0.0);
+
+    switch (derive_data.kind) {
+        case TANGENT_NORMAL: {
+            let tangent_normal = normal * tangent_frame(base_normal);
+            return vec4<f32>(0.5 * tangent_normal + 0.5, 1.0);
+        }
+        case WORLD_NORMAL: {
+            return vec4<f32>(0.5 * normal + 0.5, 1.0);
+        }
+        case SLOPE: {
+            let slope = acos(clamp(dot(normal, base_normal), -1.0, 1.0)) / (PI / 2.0);
+            return vec4<f32>(max(slope, MIN_VALUE));
+        }
+        case CURVATURE: {
+            let pixel_size = 0.25 * (length(derive_data.world_from_local * (local_position(pixel_coords + vec2<i32>(1, 0)) - local_position(pixel_coords - vec2<i32>(1, 0)))) +
+                                     length(derive_data.world_from_local * (local_position(pixel_coords + vec2<i32>(0, 1)) - local_position(pixel_coords - vec2<i32>(0, 1)))));
+
+            let laplacian = (height(pixel_coords + vec2<i32>(1, 0)) + height(pixel_coords - vec2<i32>(1, 0)) +
+                             height(pixel_coords + vec2<i32>(0, 1)) + height(pixel_coords - vec2<i32>(0, 1)) -
+                             4.0 * height(pixel_coords)) / (pixel_size * pixel_size);
+
+            let curvature = 0.5 + 0.5 * clamp(laplacian * derive_data.parameter, -1.0, 1.0);
+            return vec4<f32>(max(curvature, MIN_VALUE));
+        }
+        case AMBIENT_OCCLUSION: {
+            return vec4<f32>(max(ambient_occlusion(pixel_coords, center, base_normal), MIN_VALUE));
+        }
+        case default: {
+            return vec4<f32>(0.0);
+        }
+    }
+}
+
+// Todo: respect memory coalescing
+@compute @workgroup_size(8, 8, 1)
+fn derive(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    process_entry(vec3<u32>(invocation_id.xy, derive_data.tile_index));
+}
@@ -36,6 +36,11 @@ override fn pixel_value(coords: vec2<u32>) -> vec4<f32> {
         }
     }
 
+    // all children are missing, which keeps the void for filling
+    if (count == 0.0) {
+        return vec4<f32>(0.0);
+    }
+
     return value / count;
 }
 
@@ -0,0 +1,88 @@
+#import bevy_terrain::preprocessing::{AtlasTile, INVALID_ATLAS_INDEX, atlas, attachment, inside, pixel_coords, pixel_value, process_entry, is_border}
+
+struct FillData {
+    tile: AtlasTile,
+    parent_tile: AtlasTile,
+    fill_radius: u32,
+    fill_from_parent: u32,
+    tile_index: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> fill_data: FillData;
+
+fn is_valid(value: vec4<f32>) -> bool {
+    return any(value.xyz != vec3<f32>(0.0));
+}
+
+fn inpaint(coords: vec2<u32>) -> vec4<f32> {
+    let radius = i32(fill_data.fill_radius);
+    let bounds = vec4<u32>(attachment.border_size, attachment.border_size, attachment.center_size, attachment.center_size);
+
+    var value  = vec4<f32>(0.0);
+    var weight = 0.0;
+
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y));
+            let offset_length = length(offset);
+
+            if (offset_length == 0.0 || offset_length > f32(radius)) { continue; }
+
+            let sample_coords = vec2<i32>(coords) + vec2<i32>(x, y);
+
+            // the border is not stitched yet, so only the center of the tile is used
+            if (any(sample_coords < vec2<i32>(0)) || !inside(vec2<u32>(sample_coords), bounds)) { continue; }
+
+            let sample_value = textureLoad(atlas, vec2<u32>(sample_coords), fill_data.tile.atlas_index, 0);
+
+            if (is_valid(sample_value)) {
+                let sample_weight = 1.0 / (offset_length * offset_length);
+
+                value  += sample_weight * sample_value;
+                weight += sample_weight;
+            }
+        }
+    }
+
+    return select(vec4<f32>(0.0), value / weight, weight > 0.0);
+}
+
+fn parent_value(coords: vec2<u32>) -> vec4<f32> {
+    let tile_coordinate = fill_data.tile.coordinate;
+    let child_offset = vec2<u32>(tile_coordinate.x % 2u, tile_coordinate.y % 2u) * attachment.center_size / 2u;
+    let parent_coords = vec2<u32>(attachment.border_size) + child_offset + (coords - vec2<u32>(attachment.border_size)) / 2u;
+
+    return textureLoad(atlas, parent_coords, fill_data.parent_tile.atlas_index, 0);
+}
+
+override fn pixel_value(coords: vec2<u32>) -> vec4<f32> {
+    // the border is filled by stitching afterwards
+    if (is_border(coords)) {
+        return vec4<f32>(0.0);
+    }
+
+    let value = textureLoad(atlas, coords, fill_data.tile.atlas_index, 0);
+
+    if (is_valid(value)) {
+        return value;
+    }
+
+    let inpainted_value = inpaint(coords);
+
+    if (is_valid(inpainted_value)) {
+        return inpainted_value;
+    }
+
+    if (fill_data.fill_from_parent == 1u && fill_data.parent_tile.atlas_index != INVALID_ATLAS_INDEX) {
+        return parent_value(coords);
+    }
+
+    return value;
+}
+
+// Todo: respect memory coalescing
+@compute @workgroup_size(8, 8, 1)
+fn fill(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    process_entry(vec3<u32>(invocation_id.xy, fill_data.tile_index));
+}
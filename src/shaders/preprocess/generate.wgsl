@@ -0,0 +1,148 @@
+#import bevy_terrain::preprocessing::{AtlasTile, FORMAT_R16, atlas, attachment, pixel_value, process_entry, inverse_mix, local_position}
+
+const FBM: u32    = 0u;
+const RIDGED: u32 = 1u;
+const WARPED: u32 = 2u;
+
+struct GenerateData {
+    tile: AtlasTile,
+    top_left: vec2<f32>,
+    bottom_right: vec2<f32>,
+    kind: u32,
+    seed: u32,
+    octaves: u32,
+    frequency: f32,
+    lacunarity: f32,
+    gain: f32,
+    amplitude: f32,
+    offset: f32,
+    warp_strength: f32,
+    is_spherical: u32,
+    tile_index: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> generate_data: GenerateData;
+
+// PCG hash of the lattice point, mixed with the seed
+fn hash(cell: vec3<i32>) -> u32 {
+    let value = bitcast<vec3<u32>>(cell);
+    var state = (value.x * 73856093u) ^ (value.y * 19349663u) ^ (value.z * 83492791u) ^ (generate_data.seed * 2654435761u);
+
+    state = state * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+
+    return (word >> 22u) ^ word;
+}
+
+fn gradient(cell: vec3<i32>, offset: vec3<f32>) -> f32 {
+    var GRADIENTS = array(
+        vec3( 1.0,  1.0,  0.0), vec3(-1.0,  1.0,  0.0), vec3( 1.0, -1.0,  0.0), vec3(-1.0, -1.0,  0.0),
+        vec3( 1.0,  0.0,  1.0), vec3(-1.0,  0.0,  1.0), vec3( 1.0,  0.0, -1.0), vec3(-1.0,  0.0, -1.0),
+        vec3( 0.0,  1.0,  1.0), vec3( 0.0, -1.0,  1.0), vec3( 0.0,  1.0, -1.0), vec3( 0.0, -1.0, -1.0),
+    );
+
+    return dot(GRADIENTS[hash(cell) % 12u], offset);
+}
+
+// 3D gradient noise in the range of about -1..1
+fn gradient_noise(position: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(position));
+    let f = fract(position);
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+
+    let n000 = gradient(cell + vec3(0, 0, 0), f - vec3(0.0, 0.0, 0.0));
+    let n100 = gradient(cell + vec3(1, 0, 0), f - vec3(1.0, 0.0, 0.0));
+    let n010 = gradient(cell + vec3(0, 1, 0), f - vec3(0.0, 1.0, 0.0));
+    let n110 = gradient(cell + vec3(1, 1, 0), f - vec3(1.0, 1.0, 0.0));
+    let n001 = gradient(cell + vec3(0, 0, 1), f - vec3(0.0, 0.0, 1.0));
+    let n101 = gradient(cell + vec3(1, 0, 1), f - vec3(1.0, 0.0, 1.0));
+    let n011 = gradient(cell + vec3(0, 1, 1), f - vec3(0.0, 1.0, 1.0));
+    let n111 = gradient(cell + vec3(1, 1, 1), f - vec3(1.0, 1.0, 1.0));
+
+    let x00 = mix(n000, n100, u.x);
+    let x10 = mix(n010, n110, u.x);
+    let x01 = mix(n001, n101, u.x);
+    let x11 = mix(n011, n111, u.x);
+
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+fn fbm(position: vec3<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 1.0;
+    var frequency = generate_data.frequency;
+    var normalization = 0.0;
+
+    for (var octave = 0u; octave < generate_data.octaves; octave += 1u) {
+        value += amplitude * gradient_noise(frequency * position);
+        normalization += amplitude;
+        amplitude *= generate_data.gain;
+        frequency *= generate_data.lacunarity;
+    }
+
+    return value / normalization;
+}
+
+fn ridged(position: vec3<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 1.0;
+    var frequency = generate_data.frequency;
+    var normalization = 0.0;
+    var weight = 1.0;
+
+    for (var octave = 0u; octave < generate_data.octaves; octave += 1u) {
+        var ridge = 1.0 - abs(gradient_noise(frequency * position));
+        ridge = ridge * ridge * weight;
+        weight = clamp(2.0 * ridge, 0.0, 1.0);
+
+        value += amplitude * ridge;
+        normalization += amplitude;
+        amplitude *= generate_data.gain;
+        frequency *= generate_data.lacunarity;
+    }
+
+    return 2.0 * value / normalization - 1.0;
+}
+
+fn warped(position: vec3<f32>) -> f32 {
+    let warp = vec3<f32>(fbm(position),
+                         fbm(position + vec3<f32>(5.2, 1.3, 2.8)),
+                         fbm(position + vec3<f32>(1.7, 9.2, 4.1)));
+
+    return fbm(position + generate_data.warp_strength * warp);
+}
+
+fn noise(position: vec3<f32>) -> f32 {
+    switch (generate_data.kind) {
+        case RIDGED:  { return ridged(position); }
+        case WARPED:  { return warped(position); }
+        case default: { return fbm(position); }
+    }
+}
+
+override fn pixel_value(coords: vec2<u32>) -> vec4<f32> {
+    let tile_coordinate = generate_data.tile.coordinate;
+    let tile_offset = vec2<f32>(f32(tile_coordinate.x), f32(tile_coordinate.y));
+    let tile_coords = (vec2<f32>(coords) - f32(attachment.border_size)) / f32(attachment.center_size);
+    let dataset_coords = inverse_mix(generate_data.top_left, generate_data.bottom_right,
+                                     (tile_offset + tile_coords) / f32(1u << tile_coordinate.lod));
+
+    if (any(dataset_coords < vec2<f32>(0.0)) || any(dataset_coords > vec2<f32>(1.0))) {
+        return textureLoad(atlas, coords, generate_data.tile.atlas_index, 0);
+    }
+
+    let position = local_position(tile_coordinate, vec2<f32>(coords), generate_data.is_spherical == 1u);
+
+    // zero marks missing data, so the value is kept at least one step above it
+    let min_value = select(1.0 / 255.0, 1.0 / 65535.0, attachment.format_id == FORMAT_R16);
+    let value = clamp(generate_data.offset + generate_data.amplitude * noise(position), min_value, 1.0);
+
+    return vec4<f32>(vec3<f32>(value), 1.0);
+}
+
+// Todo: respect memory coalescing
+@compute @workgroup_size(8, 8, 1)
+fn generate(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    process_entry(vec3<u32>(invocation_id.xy, generate_data.tile_index));
+}
@@ -6,6 +6,8 @@ const FORMAT_R16: u32 = 1u;
 
 const INVALID_ATLAS_INDEX: u32 = 4294967295u;
 
+const C_SQR = 0.87 * 0.87;
+
 struct TileCoordinate {
     side: u32,
     lod: u32,
@@ -56,6 +58,35 @@ fn is_border(coords: vec2<u32>) -> bool {
     return !inside(coords, vec4<u32>(attachment.border_size, attachment.border_size, attachment.center_size, attachment.center_size));
 }
 
+// The position of a pixel of the tile in the local space of the terrain model.
+fn local_position(tile_coordinate: TileCoordinate, coords: vec2<f32>, is_spherical: bool) -> vec3<f32> {
+    let tile_offset = vec2<f32>(f32(tile_coordinate.x), f32(tile_coordinate.y));
+    let tile_coords = (coords - f32(attachment.border_size)) / f32(attachment.center_size);
+
+    var uv = (tile_offset + tile_coords) / f32(1u << tile_coordinate.lod);
+
+    if (!is_spherical) {
+        return vec3<f32>(uv.x - 0.5, 0.0, uv.y - 0.5);
+    }
+
+    uv = (uv - 0.5) / 0.5;
+    uv = uv / sqrt(1.0 + C_SQR - C_SQR * uv * uv);
+
+    var local_position: vec3<f32>;
+
+    switch (tile_coordinate.side) {
+        case 0u:      { local_position = vec3( -1.0, -uv.y,  uv.x); }
+        case 1u:      { local_position = vec3( uv.x, -uv.y,   1.0); }
+        case 2u:      { local_position = vec3( uv.x,   1.0,  uv.y); }
+        case 3u:      { local_position = vec3(  1.0, -uv.x,  uv.y); }
+        case 4u:      { local_position = vec3( uv.y, -uv.x,  -1.0); }
+        case 5u:      { local_position = vec3( uv.y,  -1.0,  uv.x); }
+        case default: {}
+    }
+
+    return normalize(local_position);
+}
+
 fn pixel_coords(entry_coords: vec3<u32>, pixel_offset: u32) -> vec2<u32> {
     return vec2<u32>(entry_coords.x * attachment.pixels_per_entry + pixel_offset, entry_coords.y);
 }
@@ -5,6 +5,7 @@ struct SplitData {
     tile: AtlasTile,
     top_left: vec2<f32>,
     bottom_right: vec2<f32>,
+    nodata_value: f32,
     tile_index: u32,
 }
 
@@ -31,7 +32,9 @@ override fn pixel_value(coords: vec2<u32>) -> vec4<f32> {
 
     let value = textureSampleLevel(source_tile, source_tile_sampler, source_coords, 0.0);
 
-    let is_valid  = all(textureGather(0u, source_tile, source_tile_sampler, source_coords) != vec4<f32>(0.0));
+    // zero always marks missing data, e.g. for normalized float sources
+    let source_values = textureGather(0u, source_tile, source_tile_sampler, source_coords);
+    let is_valid  = all(source_values != vec4<f32>(0.0)) && all(source_values != vec4<f32>(split_data.nodata_value));
     let is_inside = inside_square(tile_coords, vec2<f32>(0.0), 1.0) == 1.0;
 
     if (is_valid && is_inside) {
@@ -1,5 +1,5 @@
 #import bevy_terrain::types::TileCoordinate
-#import bevy_terrain::bindings::{view_config, temporary_tiles, parameters, indirect_buffer}
+#import bevy_terrain::bindings::{config, view_config, temporary_tiles, parameters, indirect_buffer}
 
 @compute @workgroup_size(1, 1, 1)
 fn prepare_root() {
@@ -14,12 +14,14 @@ fn prepare_root() {
         temporary_tiles[i] = TileCoordinate(i, 0u, vec2<u32>(0u));
     }
 #else
-    parameters.tile_count = 1u;
+    parameters.tile_count = config.root_count.x * config.root_count.y;
 
-    temporary_tiles[0] = TileCoordinate(0u, 0u, vec2<u32>(0u));
+    for (var i: u32 = 0u; i < parameters.tile_count; i = i + 1u) {
+        temporary_tiles[i] = TileCoordinate(0u, 0u, vec2<u32>(i % config.root_count.x, i / config.root_count.x));
+    }
 #endif
 
-    indirect_buffer.workgroup_count = vec3<u32>(1u, 1u, 1u);
+    indirect_buffer.workgroup_count = vec3<u32>((parameters.tile_count + 63u) / 64u, 1u, 1u);
 }
 
 @compute @workgroup_size(1, 1, 1)
@@ -1,6 +1,6 @@
 #import bevy_terrain::types::{TileCoordinate, Coordinate}
-#import bevy_terrain::bindings::{config, culling_view, view_config, final_tiles, temporary_tiles, parameters, terrain_model_approximation}
-#import bevy_terrain::functions::{approximate_view_distance, compute_relative_position, position_local_to_world, normal_local_to_world, tile_count, compute_subdivision_coordinate}
+#import bevy_terrain::bindings::{config, culling_view, view_config, final_tiles, temporary_tiles, parameters, terrain_model_approximation, tile_statistics}
+#import bevy_terrain::functions::{approximate_view_distance, compute_relative_position, compute_local_position, position_local_to_world, normal_local_to_world, tile_count, compute_subdivision_coordinate, compute_tile_tree_uv, lookup_tile_tree_entry}
 
 fn child_index() -> i32 {
     return atomicAdd(&parameters.child_index, parameters.counter);
@@ -14,6 +14,54 @@ fn final_index() -> i32 {
     return atomicAdd(&parameters.final_index, 1);
 }
 
+// The statistics of the closest loaded ancestor bound the height of the tile.
+// Tiles outside of the tile tree fall back to the height range of the entire terrain.
+fn tile_height_range(tile: TileCoordinate) -> vec2<f32> {
+    let coordinate   = Coordinate(tile.side, tile.lod, tile.xy, vec2<f32>(0.5));
+    let tile_tree_uv = compute_tile_tree_uv(coordinate);
+
+    if (any(tile_tree_uv <= vec2<f32>(0.0)) || any(tile_tree_uv >= vec2<f32>(1.0))) {
+        return vec2<f32>(config.min_height, config.max_height);
+    }
+
+    let tile_tree_entry = lookup_tile_tree_entry(coordinate);
+
+    if (tile_tree_entry.atlas_index >= arrayLength(&tile_statistics)) {
+        return vec2<f32>(config.min_height, config.max_height);
+    }
+
+    let statistics = tile_statistics[tile_tree_entry.atlas_index];
+
+    return vec2<f32>(statistics.min, statistics.max);
+}
+
+fn frustum_cull(tile: TileCoordinate) -> bool {
+    let height_range  = tile_height_range(tile);
+    let center_height = (height_range.x + height_range.y) / 2.0;
+    let center_local  = compute_local_position(Coordinate(tile.side, tile.lod, tile.xy, vec2<f32>(0.5)));
+    let center        = position_local_to_world(center_local) + center_height * normal_local_to_world(center_local);
+
+    // the corners at the lowest and highest point are the furthest away from the center
+    var radius = 0.0;
+
+    for (var i: u32 = 0u; i < 4u; i = i + 1u) {
+        let corner_local  = compute_local_position(Coordinate(tile.side, tile.lod, tile.xy, vec2<f32>(f32(i & 1u), f32(i >> 1u & 1u))));
+        let corner        = position_local_to_world(corner_local);
+        let corner_normal = normal_local_to_world(corner_local);
+
+        radius = max(radius, distance(center, corner + height_range.x * corner_normal));
+        radius = max(radius, distance(center, corner + height_range.y * corner_normal));
+    }
+
+    for (var i: u32 = 0u; i < 5u; i = i + 1u) {
+        let plane = culling_view.planes[i];
+
+        if (dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz)) { return true; }
+    }
+
+    return false;
+}
+
 fn should_be_divided(tile: TileCoordinate) -> bool {
     let coordinate    = compute_subdivision_coordinate(Coordinate(tile.side, tile.lod, tile.xy, vec2<f32>(0.0)));
     let view_distance = approximate_view_distance(coordinate, culling_view.world_position);
@@ -36,6 +84,8 @@ fn refine_tiles(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
 
     let tile = temporary_tiles[parent_index(invocation_id.x)];
 
+    if (frustum_cull(tile)) { return; }
+
     if (should_be_divided(tile)) {
         subdivide(tile);
     } else {
@@ -5,6 +5,7 @@ struct TerrainConfig {
     min_height: f32,
     max_height: f32,
     scale: f32,
+    root_count: vec2<u32>,
 }
 
 struct TerrainViewConfig {
@@ -63,6 +64,13 @@ struct AtlasTile {
     coordinate: Coordinate,
 }
 
+// The height range of a tile in meters, used for tighter culling bounds.
+struct TileStatistics {
+    min: f32,
+    max: f32,
+    mean: f32,
+}
+
 struct BestLookup {
     tile: AtlasTile,
     tile_tree_uv: vec2<f32>,
@@ -72,7 +80,8 @@ struct AttachmentConfig {
     size: f32,
     scale: f32,
     offset: f32,
-    _padding: u32,
+    min_lod: u32,
+    max_lod: u32,
 }
 
 struct SideParameter {
@@ -5,9 +5,16 @@ use crate::big_space::{GridCell, GridTransformOwned, ReferenceFrame};
 
 use crate::{
     math::TerrainModel,
-    terrain_data::{tile_atlas::TileAtlas, AttachmentConfig},
+    terrain_data::{
+        attachment_source::{AttachmentSource, GeneratedAttachment, GpuGeneratedAttachment},
+        tile_atlas::{SharedTileAtlas, TileAtlas, TileAtlasStreamingConfig},
+        AttachmentConfig,
+    },
 };
-use bevy::{ecs::entity::EntityHashMap, prelude::*, render::view::NoFrustumCulling};
+use bevy::{
+    ecs::entity::EntityHashMap, prelude::*, render::view::NoFrustumCulling, utils::HashMap,
+};
+use std::sync::Arc;
 
 /// Resource that stores components that are associated to a terrain entity.
 /// This is used to persist components in the render world.
@@ -34,6 +41,14 @@ pub struct TerrainConfig {
     pub path: String,
     /// The attachments of the terrain.
     pub attachments: Vec<AttachmentConfig>,
+    /// The sources of the attachments, which are generated at runtime, by attachment index.
+    pub generated_attachments: HashMap<u32, GeneratedAttachment>,
+    /// The shaders of the attachments, which are generated on the GPU, by attachment index.
+    pub gpu_generated_attachments: HashMap<u32, GpuGeneratedAttachment>,
+    /// The limits on streaming tiles in and out of the tile atlas.
+    pub streaming: TileAtlasStreamingConfig,
+    /// The atlas shared with other terrains, which replaces the own atlas of `atlas_size` tiles.
+    pub shared_atlas: Option<SharedTileAtlas>,
 }
 
 impl Default for TerrainConfig {
@@ -44,6 +59,10 @@ impl Default for TerrainConfig {
             atlas_size: 1024,
             path: default(),
             attachments: default(),
+            generated_attachments: default(),
+            gpu_generated_attachments: default(),
+            streaming: default(),
+            shared_atlas: None,
         }
     }
 }
@@ -53,6 +72,46 @@ impl TerrainConfig {
         self.attachments.push(attachment_config);
         self
     }
+
+    /// Adds an attachment, whose tiles are generated by the source instead of being loaded from disk.
+    ///
+    /// If `cache` is enabled, the generated tiles are stored on disk and loaded from there later on.
+    /// Generated attachments can not use the [`Rgb8`](crate::terrain_data::AttachmentFormat::Rgb8) format.
+    pub fn add_generated_attachment(
+        mut self,
+        attachment_config: AttachmentConfig,
+        source: impl AttachmentSource,
+        cache: bool,
+    ) -> Self {
+        self.generated_attachments.insert(
+            self.attachments.len() as u32,
+            GeneratedAttachment {
+                source: Arc::new(source),
+                cache,
+            },
+        );
+        self.attachments.push(attachment_config);
+        self
+    }
+
+    /// Adds an attachment, whose tiles are generated by the compute shader, once the source
+    /// attachments of the tile are loaded. See [`GpuGeneratedAttachment`].
+    pub fn add_gpu_generated_attachment(
+        mut self,
+        attachment_config: AttachmentConfig,
+        shader: Handle<Shader>,
+        source_attachments: Vec<u32>,
+    ) -> Self {
+        self.gpu_generated_attachments.insert(
+            self.attachments.len() as u32,
+            GpuGeneratedAttachment {
+                shader,
+                source_attachments,
+            },
+        );
+        self.attachments.push(attachment_config);
+        self
+    }
 }
 
 /// The components of a terrain.
@@ -71,7 +130,6 @@ pub struct TerrainBundle {
 
 impl TerrainBundle {
     /// Creates a new terrain bundle from the config.
-
     pub fn new(
         tile_atlas: TileAtlas,
         #[cfg(feature = "high_precision")] frame: &ReferenceFrame,
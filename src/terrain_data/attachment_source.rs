@@ -0,0 +1,115 @@
+//! Attachments, which are generated at runtime instead of being loaded from disk.
+
+use crate::{
+    math::{Coordinate, TerrainModel, TileCoordinate},
+    terrain_data::{AttachmentData, AttachmentFormat},
+};
+use bevy::{
+    asset::Handle,
+    math::{DVec2, DVec3, Vec4},
+    render::render_resource::Shader,
+};
+use itertools::iproduct;
+use std::sync::Arc;
+
+/// Generates the data of an attachment on the CPU, whenever one of its tiles is loaded.
+///
+/// The source is called on a background task, so it must not block for long.
+/// Tiles are only generated for the existing tiles of the terrain.
+pub trait AttachmentSource: Send + Sync + 'static {
+    /// Generates all pixels of the tile, including its border, in the format of the attachment.
+    ///
+    /// Only the first mip level is generated, the remaining ones are computed afterwards.
+    fn generate(&self, tile: &GeneratedTile) -> AttachmentData;
+}
+
+/// An attachment of a terrain, whose tiles are generated by an [`AttachmentSource`].
+#[derive(Clone)]
+pub struct GeneratedAttachment {
+    pub source: Arc<dyn AttachmentSource>,
+    /// Whether the generated tiles are stored on disk and loaded from there, once they are loaded again.
+    pub cache: bool,
+}
+
+/// An attachment of a terrain, whose tiles are generated by a compute shader on the GPU.
+///
+/// Once all source attachments of a tile are loaded, the `generate` entry point of the shader
+/// is dispatched for it. The shader imports `bevy_terrain::preprocessing` and
+/// `bevy_terrain::generation`, overrides `pixel_value` and passes each entry to `process_entry`,
+/// like the preprocessing shaders.
+/// The source attachments are bound in the given order and can be sampled with `sample_source`.
+///
+/// Only the first mip level is generated and the data is not available on the CPU.
+#[derive(Clone)]
+pub struct GpuGeneratedAttachment {
+    pub shader: Handle<Shader>,
+    /// The attachments, which are accessible by the shader (at most four).
+    pub source_attachments: Vec<u32>,
+}
+
+/// A tile, whose attachment is generated by an [`AttachmentSource`].
+pub struct GeneratedTile {
+    pub coordinate: TileCoordinate,
+    pub model: TerrainModel,
+    pub texture_size: u32,
+    pub border_size: u32,
+    pub format: AttachmentFormat,
+}
+
+impl GeneratedTile {
+    /// The coordinate of the pixel, which lies outside of the tile for the border pixels,
+    /// so that the border matches the neighbouring tiles.
+    pub fn pixel_coordinate(&self, x: u32, y: u32) -> Coordinate {
+        let center_size = (self.texture_size - 2 * self.border_size) as f64;
+        let tile_offset = DVec2::new(self.coordinate.x as f64, self.coordinate.y as f64);
+        let pixel_offset = (DVec2::new(x as f64, y as f64) - self.border_size as f64) / center_size;
+
+        Coordinate::new(
+            self.coordinate.side,
+            (tile_offset + pixel_offset) / TileCoordinate::count(self.coordinate.lod) as f64,
+        )
+    }
+
+    /// The world position of the pixel at the height above the surface of the model.
+    pub fn pixel_position(&self, x: u32, y: u32, height: f32) -> DVec3 {
+        self.pixel_coordinate(x, y)
+            .world_position(&self.model, height)
+    }
+
+    /// The height range of the terrain, which normalized heights are mapped to.
+    pub fn height_range(&self) -> (f32, f32) {
+        (self.model.min_height, self.model.max_height)
+    }
+
+    /// Evaluates the normalized value of each pixel and converts it to the format of the attachment.
+    pub fn fill(&self, value: impl Fn(u32, u32) -> Vec4) -> AttachmentData {
+        let pixels = iproduct!(0..self.texture_size, 0..self.texture_size)
+            .map(|(y, x)| value(x, y).clamp(Vec4::ZERO, Vec4::ONE));
+
+        match self.format {
+            AttachmentFormat::Rgba8 => AttachmentData::Rgba8(
+                pixels
+                    .map(|value| (value * u8::MAX as f32).round().to_array().map(|v| v as u8))
+                    .collect(),
+            ),
+            AttachmentFormat::R16 => AttachmentData::R16(
+                pixels
+                    .map(|value| (value.x * u16::MAX as f32).round() as u16)
+                    .collect(),
+            ),
+            AttachmentFormat::Rg16 => AttachmentData::Rg16(
+                pixels
+                    .map(|value| {
+                        [
+                            (value.x * u16::MAX as f32).round() as u16,
+                            (value.y * u16::MAX as f32).round() as u16,
+                        ]
+                    })
+                    .collect(),
+            ),
+            AttachmentFormat::Rgb8 => {
+                unreachable!("Generated attachments can not use the Rgb8 format.")
+            }
+        }
+    }
+}
@@ -1,10 +1,14 @@
 use crate::{
+    math::TerrainModel,
+    render::{
+        attachment_bindings::AttachmentLayoutKey, attachment_generation::GpuAttachmentGeneration,
+    },
     terrain::TerrainComponents,
     terrain_data::{
         tile_atlas::{
             AtlasAttachment, AtlasTileAttachment, AtlasTileAttachmentWithData, TileAtlas,
         },
-        AttachmentData, AttachmentFormat,
+        AttachmentData, AttachmentFormat, INVALID_ATLAS_INDEX,
     },
     util::StaticBuffer,
 };
@@ -16,9 +20,10 @@ use bevy::{
         Extract, MainWorld,
     },
     tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
 };
 use itertools::Itertools;
-use std::{iter, mem};
+use std::{iter, mem, ops::Range};
 
 const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
 
@@ -60,7 +65,7 @@ pub(crate) struct AtlasBufferInfo {
     pub(crate) texture_size: u32,
     pub(crate) border_size: u32,
     pub(crate) center_size: u32,
-    format: AttachmentFormat,
+    pub(crate) format: AttachmentFormat,
     mip_level_count: u32,
 
     pixels_per_entry: u32,
@@ -127,7 +132,7 @@ impl AtlasBufferInfo {
         texture: &'a Texture,
         index: u32,
         mip_level: u32,
-    ) -> ImageCopyTexture {
+    ) -> ImageCopyTexture<'a> {
         ImageCopyTexture {
             texture,
             mip_level,
@@ -139,7 +144,7 @@ impl AtlasBufferInfo {
         }
     }
 
-    fn image_copy_buffer<'a>(&'a self, buffer: &'a Buffer, index: u32) -> ImageCopyBuffer {
+    fn image_copy_buffer<'a>(&'a self, buffer: &'a Buffer, index: u32) -> ImageCopyBuffer<'a> {
         ImageCopyBuffer {
             buffer,
             layout: ImageDataLayout {
@@ -179,6 +184,8 @@ impl AtlasBufferInfo {
 pub(crate) struct GpuAtlasAttachment {
     pub(crate) name: String,
     pub(crate) buffer_info: AtlasBufferInfo,
+    /// The lods, for which the attachment exists.
+    pub(crate) lod_range: Range<u32>,
 
     pub(crate) atlas_texture: Texture,
     pub(crate) atlas_write_section: StaticBuffer<()>,
@@ -189,6 +196,9 @@ pub(crate) struct GpuAtlasAttachment {
     pub(crate) atlas_write_slots: Vec<AtlasTileAttachment>,
     pub(crate) upload_tiles: Vec<AtlasTileAttachmentWithData>,
     pub(crate) download_tiles: Vec<Task<AtlasTileAttachmentWithData>>,
+
+    /// The generation of the tiles by a compute shader, if the attachment is generated on the GPU.
+    pub(crate) generation: Option<GpuAttachmentGeneration>,
 }
 
 impl GpuAtlasAttachment {
@@ -196,6 +206,7 @@ impl GpuAtlasAttachment {
         device: &RenderDevice,
         attachment: &AtlasAttachment,
         tile_atlas: &TileAtlas,
+        atlas_texture: Option<Texture>,
     ) -> Self {
         let name = attachment.name.clone();
         let max_atlas_write_slots = tile_atlas.state.max_atlas_write_slots;
@@ -205,14 +216,60 @@ impl GpuAtlasAttachment {
 
         // dbg!(&buffer_info);
 
-        let atlas_texture = device.create_texture(&TextureDescriptor {
+        let atlas_texture = atlas_texture.unwrap_or_else(|| {
+            Self::create_atlas_texture(device, &name, &buffer_info, tile_atlas.atlas_size)
+        });
+
+        let atlas_write_section = StaticBuffer::empty_sized(
+            format!("{name}_atlas_write_section").as_str(),
+            device,
+            buffer_info.buffer_size(max_atlas_write_slots) as BufferAddress,
+            BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
+        );
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &name,
+            &buffer_info,
+            &atlas_texture,
+            &atlas_write_section,
+        );
+
+        Self {
+            name,
+            buffer_info,
+            lod_range: attachment.lod_range.clone(),
+            atlas_texture,
+            atlas_write_section,
+            download_buffers: default(),
+            bind_group,
+            max_atlas_write_slots,
+            atlas_write_slots,
+            upload_tiles: default(),
+            download_tiles: default(),
+            generation: attachment.gpu_generated.as_ref().map(|gpu_generated| {
+                GpuAttachmentGeneration::new(
+                    gpu_generated.shader.clone(),
+                    gpu_generated.source_attachments.clone(),
+                )
+            }),
+        }
+    }
+
+    fn create_atlas_texture(
+        device: &RenderDevice,
+        name: &str,
+        buffer_info: &AtlasBufferInfo,
+        atlas_size: u32,
+    ) -> Texture {
+        device.create_texture(&TextureDescriptor {
             label: Some(&format!("{name}_attachment")),
             size: Extent3d {
                 width: buffer_info.texture_size,
                 height: buffer_info.texture_size,
-                depth_or_array_layers: tile_atlas.atlas_size,
+                depth_or_array_layers: atlas_size,
             },
-            mip_level_count: attachment.mip_level_count,
+            mip_level_count: buffer_info.mip_level_count,
             sample_count: 1,
             dimension: TextureDimension::D2,
             format: buffer_info.format.render_format(),
@@ -220,8 +277,16 @@ impl GpuAtlasAttachment {
                 | TextureUsages::COPY_SRC
                 | TextureUsages::TEXTURE_BINDING,
             view_formats: &[buffer_info.format.processing_format()],
-        });
+        })
+    }
 
+    fn create_bind_group(
+        device: &RenderDevice,
+        name: &str,
+        buffer_info: &AtlasBufferInfo,
+        atlas_texture: &Texture,
+        atlas_write_section: &StaticBuffer<()>,
+    ) -> BindGroup {
         let atlas_view = atlas_texture.create_view(&TextureViewDescriptor {
             format: Some(buffer_info.format.processing_format()),
             ..default()
@@ -234,13 +299,6 @@ impl GpuAtlasAttachment {
             ..default()
         });
 
-        let atlas_write_section = StaticBuffer::empty_sized(
-            format!("{name}_atlas_write_section").as_str(),
-            device,
-            buffer_info.buffer_size(max_atlas_write_slots) as BufferAddress,
-            BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
-        );
-
         let attachment_meta_buffer = StaticBuffer::create(
             format!("{name}_attachment_meta").as_str(),
             device,
@@ -248,29 +306,63 @@ impl GpuAtlasAttachment {
             BufferUsages::UNIFORM,
         );
 
-        let bind_group = device.create_bind_group(
+        device.create_bind_group(
             format!("{name}attachment_bind_group").as_str(),
             &create_attachment_layout(device),
             &BindGroupEntries::sequential((
-                &atlas_write_section,
+                atlas_write_section,
                 &atlas_view,
                 &atlas_sampler,
                 &attachment_meta_buffer,
             )),
+        )
+    }
+
+    /// Reallocates the atlas texture with the new size and copies the tiles from their
+    /// source index in the old texture to their index in the new one.
+    fn resize(
+        &mut self,
+        device: &RenderDevice,
+        command_encoder: &mut CommandEncoder,
+        source_indices: &[u32],
+    ) {
+        let atlas_texture = Self::create_atlas_texture(
+            device,
+            &self.name,
+            &self.buffer_info,
+            source_indices.len() as u32,
         );
 
-        Self {
-            name,
-            buffer_info,
-            atlas_texture,
-            atlas_write_section,
-            download_buffers: default(),
-            bind_group,
-            max_atlas_write_slots,
-            atlas_write_slots,
-            upload_tiles: default(),
-            download_tiles: default(),
+        for (atlas_index, &source_index) in source_indices.iter().enumerate() {
+            if source_index == INVALID_ATLAS_INDEX {
+                continue;
+            }
+
+            for mip_level in 0..self.buffer_info.mip_level_count {
+                command_encoder.copy_texture_to_texture(
+                    self.buffer_info.image_copy_texture(
+                        &self.atlas_texture,
+                        source_index,
+                        mip_level,
+                    ),
+                    self.buffer_info.image_copy_texture(
+                        &atlas_texture,
+                        atlas_index as u32,
+                        mip_level,
+                    ),
+                    self.buffer_info.image_copy_size(mip_level),
+                );
+            }
         }
+
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.name,
+            &self.buffer_info,
+            &atlas_texture,
+            &self.atlas_write_section,
+        );
+        self.atlas_texture = atlas_texture;
     }
 
     pub(crate) fn reserve_write_slot(&mut self, tile: AtlasTileAttachment) -> Option<u32> {
@@ -295,7 +387,16 @@ impl GpuAtlasAttachment {
     }
 
     pub(crate) fn copy_tiles_from_write_section(&self, command_encoder: &mut CommandEncoder) {
-        for (section_index, tile) in self.atlas_write_slots.iter().enumerate() {
+        self.copy_to_atlas(self.atlas_write_slots.iter().copied(), command_encoder);
+    }
+
+    /// Copies the tiles from their slot in the write section into the atlas texture.
+    pub(crate) fn copy_to_atlas(
+        &self,
+        tiles: impl Iterator<Item = AtlasTileAttachment>,
+        command_encoder: &mut CommandEncoder,
+    ) {
+        for (section_index, tile) in tiles.enumerate() {
             command_encoder.copy_buffer_to_texture(
                 self.buffer_info
                     .image_copy_buffer(&self.atlas_write_section, section_index as u32),
@@ -412,46 +513,123 @@ impl GpuAtlasAttachment {
     }
 }
 
+/// The attachment textures of each [`SharedTileAtlas`](crate::terrain_data::tile_atlas::SharedTileAtlas),
+/// which are used by all of its terrains.
+#[derive(Default, Resource)]
+pub(crate) struct SharedAtlasTextures(HashMap<u32, Vec<Texture>>);
+
 /// Stores the GPU representation of the [`TileAtlas`] (array textures)
 /// alongside the data to update it.
 ///
 /// All attachments of newly loaded tiles are copied into their according atlas attachment.
 #[derive(Component)]
 pub struct GpuTileAtlas {
-    /// Stores the atlas attachments of the terrain.
-    pub(crate) attachments: Vec<GpuAtlasAttachment>,
+    /// Stores the atlas attachments of the terrain, removed attachments leave their index vacant.
+    pub(crate) attachments: Vec<Option<GpuAtlasAttachment>>,
+    /// The id of the attachment layout in the generated attachment bindings.
+    pub(crate) attachment_layout: u32,
     pub(crate) is_spherical: bool,
+    /// The model is extracted every frame, since its height range may be calibrated from the source data.
+    pub(crate) model: TerrainModel,
+    /// Incremented each time the atlas textures are reallocated, added or removed.
+    pub(crate) generation: u32,
 }
 
 impl GpuTileAtlas {
     /// Creates a new gpu tile atlas and initializes its attachment textures.
-    fn new(device: &RenderDevice, tile_atlas: &TileAtlas) -> Self {
-        let attachments = tile_atlas
-            .attachments
-            .iter()
-            .map(|attachment| GpuAtlasAttachment::new(device, attachment, tile_atlas))
-            .collect_vec();
+    fn new(
+        device: &RenderDevice,
+        tile_atlas: &TileAtlas,
+        shared_textures: &mut SharedAtlasTextures,
+    ) -> Self {
+        let textures = tile_atlas
+            .shared_atlas_id
+            .map(|id| shared_textures.0.entry(id).or_default());
+
+        let mut attachments = Vec::new();
+
+        for (attachment_index, attachment) in tile_atlas.attachments.iter().enumerate() {
+            let atlas_texture = textures
+                .as_ref()
+                .and_then(|textures| textures.get(attachment_index).cloned());
+
+            attachments.push(attachment.as_ref().map(|attachment| {
+                GpuAtlasAttachment::new(device, attachment, tile_atlas, atlas_texture)
+            }));
+        }
+
+        // the first terrain of a shared atlas creates its textures
+        if let Some(textures) = textures {
+            if textures.is_empty() {
+                textures.extend(
+                    attachments
+                        .iter()
+                        .flatten()
+                        .map(|attachment| attachment.atlas_texture.clone()),
+                );
+            }
+        }
 
         Self {
             attachments,
+            attachment_layout: tile_atlas.attachment_layout,
             is_spherical: tile_atlas.model.is_spherical(),
+            model: tile_atlas.model.clone(),
+            generation: 0,
+        }
+    }
+
+    pub(crate) fn attachment_layout_key(&self) -> AttachmentLayoutKey {
+        AttachmentLayoutKey {
+            id: self.attachment_layout,
+            slot_count: self.attachments.len().max(1) as u32,
         }
     }
 
+    pub(crate) fn attachment(&self, attachment_index: u32) -> &GpuAtlasAttachment {
+        self.attachments[attachment_index as usize]
+            .as_ref()
+            .expect("The attachment has been removed.")
+    }
+
+    pub(crate) fn attachment_mut(&mut self, attachment_index: u32) -> &mut GpuAtlasAttachment {
+        self.attachments[attachment_index as usize]
+            .as_mut()
+            .expect("The attachment has been removed.")
+    }
+
     /// Initializes the [`GpuTileAtlas`] of newly created terrains.
     pub(crate) fn initialize(
         device: Res<RenderDevice>,
         mut gpu_tile_atlases: ResMut<TerrainComponents<GpuTileAtlas>>,
+        mut shared_textures: ResMut<SharedAtlasTextures>,
         mut tile_atlases: Extract<Query<(Entity, &TileAtlas), Added<TileAtlas>>>,
     ) {
         for (terrain, tile_atlas) in tile_atlases.iter_mut() {
-            gpu_tile_atlases.insert(terrain, GpuTileAtlas::new(&device, tile_atlas));
+            gpu_tile_atlases.insert(
+                terrain,
+                GpuTileAtlas::new(&device, tile_atlas, &mut shared_textures),
+            );
         }
     }
 
+    /// Releases the textures of the shared atlases, once their last terrain has been despawned.
+    pub(crate) fn release_shared_textures(
+        mut shared_textures: ResMut<SharedAtlasTextures>,
+        tile_atlases: Extract<Query<&TileAtlas>>,
+    ) {
+        shared_textures.0.retain(|&id, _| {
+            tile_atlases
+                .iter()
+                .any(|tile_atlas| tile_atlas.shared_atlas_id == Some(id))
+        });
+    }
+
     /// Extracts the tiles that have finished loading from all [`TileAtlas`]es into the
     /// corresponding [`GpuTileAtlas`]es.
     pub(crate) fn extract(
+        device: Res<RenderDevice>,
+        queue: Res<RenderQueue>,
         mut main_world: ResMut<MainWorld>,
         mut gpu_tile_atlases: ResMut<TerrainComponents<GpuTileAtlas>>,
     ) {
@@ -460,9 +638,52 @@ impl GpuTileAtlas {
         for (terrain, mut tile_atlas) in tile_atlases.iter_mut(&mut main_world) {
             let gpu_tile_atlas = gpu_tile_atlases.get_mut(&terrain).unwrap();
 
+            gpu_tile_atlas.model = tile_atlas.model.clone();
+            gpu_tile_atlas.attachment_layout = tile_atlas.attachment_layout;
+
+            // added attachments start with an empty texture, removed ones release theirs
+            let changed_attachments = mem::take(&mut tile_atlas.changed_attachments);
+
+            for &attachment_index in &changed_attachments {
+                let attachment_index = attachment_index as usize;
+
+                if attachment_index >= gpu_tile_atlas.attachments.len() {
+                    gpu_tile_atlas
+                        .attachments
+                        .resize_with(attachment_index + 1, || None);
+                }
+
+                gpu_tile_atlas.attachments[attachment_index] = tile_atlas.attachments
+                    [attachment_index]
+                    .as_ref()
+                    .map(|attachment| {
+                        GpuAtlasAttachment::new(&device, attachment, &tile_atlas, None)
+                    });
+            }
+
+            if !changed_attachments.is_empty() {
+                gpu_tile_atlas.generation += 1;
+            }
+
+            // the resized textures have to be populated, before the new tiles are uploaded
+            if let Some(source_indices) = tile_atlas.resized_source_indices.take() {
+                let mut command_encoder = device.create_command_encoder(&default());
+
+                for attachment in gpu_tile_atlas.attachments.iter_mut().flatten() {
+                    attachment.resize(&device, &mut command_encoder, &source_indices);
+                }
+
+                queue.submit([command_encoder.finish()]);
+                gpu_tile_atlas.generation += 1;
+            }
+
             for (attachment, gpu_attachment) in
                 iter::zip(&mut tile_atlas.attachments, &mut gpu_tile_atlas.attachments)
             {
+                let (Some(attachment), Some(gpu_attachment)) = (attachment, gpu_attachment) else {
+                    continue;
+                };
+
                 mem::swap(
                     &mut attachment.uploading_tiles,
                     &mut gpu_attachment.upload_tiles,
@@ -471,6 +692,18 @@ impl GpuTileAtlas {
                 attachment
                     .downloading_tiles
                     .extend(mem::take(&mut gpu_attachment.download_tiles));
+
+                if let Some(generation) = &mut gpu_attachment.generation {
+                    generation
+                        .pending_tiles
+                        .extend(mem::take(&mut attachment.generating_tiles));
+                    attachment
+                        .generated_tiles
+                        .extend(mem::take(&mut generation.generated_tiles));
+                    attachment
+                        .waiting_tiles
+                        .extend(mem::take(&mut generation.deferred_tiles));
+                }
             }
         }
     }
@@ -483,7 +716,7 @@ impl GpuTileAtlas {
         mut gpu_tile_atlases: ResMut<TerrainComponents<GpuTileAtlas>>,
     ) {
         for gpu_tile_atlas in gpu_tile_atlases.values_mut() {
-            for attachment in &mut gpu_tile_atlas.attachments {
+            for attachment in gpu_tile_atlas.attachments.iter_mut().flatten() {
                 attachment.create_download_buffers(&device);
                 attachment.upload_tiles(&queue);
             }
@@ -492,9 +725,48 @@ impl GpuTileAtlas {
 
     pub(crate) fn cleanup(mut gpu_tile_atlases: ResMut<TerrainComponents<GpuTileAtlas>>) {
         for gpu_tile_atlas in gpu_tile_atlases.values_mut() {
-            for attachment in &mut gpu_tile_atlas.attachments {
+            for attachment in gpu_tile_atlas.attachments.iter_mut().flatten() {
                 attachment.start_downloading_tiles();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{terrain::TerrainConfig, terrain_data::tile_atlas::SharedTileAtlas};
+
+    #[test]
+    fn shared_textures_are_released_with_their_last_terrain() {
+        let shared_atlas = SharedTileAtlas::new(16);
+        let config = TerrainConfig {
+            shared_atlas: Some(shared_atlas.clone()),
+            ..default()
+        };
+
+        let mut main_world = MainWorld::default();
+        let first = main_world.spawn(TileAtlas::new(&config)).id();
+        let second = main_world.spawn(TileAtlas::new(&config)).id();
+
+        let mut app = App::new();
+        app.insert_resource(main_world)
+            .init_resource::<SharedAtlasTextures>()
+            .add_systems(Update, GpuTileAtlas::release_shared_textures);
+
+        let mut shared_textures = app.world_mut().resource_mut::<SharedAtlasTextures>();
+        shared_textures.0.insert(shared_atlas.id, Vec::new());
+
+        app.world_mut().resource_mut::<MainWorld>().despawn(first);
+        app.update();
+
+        let shared_textures = app.world().resource::<SharedAtlasTextures>();
+        assert!(shared_textures.0.contains_key(&shared_atlas.id));
+
+        app.world_mut().resource_mut::<MainWorld>().despawn(second);
+        app.update();
+
+        let shared_textures = app.world().resource::<SharedAtlasTextures>();
+        assert!(!shared_textures.0.contains_key(&shared_atlas.id));
+    }
+}
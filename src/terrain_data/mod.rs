@@ -23,18 +23,21 @@ use bevy::{math::DVec3, prelude::*, render::render_resource::*};
 use bincode::{Decode, Encode};
 use bytemuck::cast_slice;
 use itertools::iproduct;
-use std::iter;
+use serde::{Deserialize, Serialize};
+use std::{iter, ops::Range};
 
+pub mod attachment_source;
 pub mod gpu_tile_atlas;
 pub mod gpu_tile_tree;
 pub mod tile_atlas;
+pub mod tile_content;
 pub mod tile_tree;
 
 pub const INVALID_ATLAS_INDEX: u32 = u32::MAX;
 pub const INVALID_LOD: u32 = u32::MAX;
 
 /// The data format of an attachment.
-#[derive(Encode, Decode, Clone, Copy, Debug)]
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AttachmentFormat {
     /// Three channels  8 bit
     Rgb8,
@@ -84,9 +87,14 @@ impl AttachmentFormat {
 }
 
 /// Configures an attachment.
-#[derive(Encode, Decode, Clone, Debug)]
+#[derive(Encode, Decode, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct AttachmentConfig {
     /// The name of the attachment.
+    ///
+    /// It has to start with a letter and has to be unique among the attachments of the terrain,
+    /// ignoring case and treating all non-alphanumeric characters as underscores,
+    /// since the terrain shaders access the attachment by its name.
     pub name: String,
     pub texture_size: u32,
     /// The overlapping border size around the tile, used to prevent sampling artifacts.
@@ -94,6 +102,11 @@ pub struct AttachmentConfig {
     pub mip_level_count: u32,
     /// The format of the attachment.
     pub format: AttachmentFormat,
+    /// The lods, for which the attachment exists, or all lods of the terrain if `None`.
+    ///
+    /// Tiles outside of this range do not load the attachment. Finer tiles sample the
+    /// attachment of their closest ancestor within the range instead, while coarser ones sample zero.
+    pub lod_range: Option<Range<u32>>,
 }
 
 impl Default for AttachmentConfig {
@@ -104,12 +117,41 @@ impl Default for AttachmentConfig {
             border_size: 1,
             mip_level_count: 1,
             format: AttachmentFormat::R16,
+            lod_range: None,
         }
     }
 }
 
+/// The height bounds of a tile of the terrain.
+///
+/// The minimum and maximum of a tile include all of its children,
+/// so they bound the data at every lod. The mean only covers the tile itself.
+#[derive(Encode, Decode, Clone, Copy, Debug, Default, PartialEq, ShaderType)]
+pub struct TileStatistics {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl TileStatistics {
+    pub(crate) fn merge_bounds(&mut self, other: TileStatistics) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Converts normalized statistics to the height range of the terrain.
+    pub(crate) fn denormalize(self, min_height: f32, max_height: f32) -> Self {
+        Self {
+            min: f32::lerp(min_height, max_height, self.min),
+            max: f32::lerp(min_height, max_height, self.max),
+            mean: f32::lerp(min_height, max_height, self.mean),
+        }
+    }
+}
+
+/// The pixels of one tile of an attachment.
 #[derive(Clone)]
-pub(crate) enum AttachmentData {
+pub enum AttachmentData {
     None,
     /// Three channels  8 bit
     // Rgb8(Vec<(u8, u8, u8)>), Can not be represented currently
@@ -140,6 +182,33 @@ impl AttachmentData {
         }
     }
 
+    /// Computes the normalized statistics of the center of a height tile, ignoring missing data.
+    pub(crate) fn statistics(&self, texture_size: u32, border_size: u32) -> Option<TileStatistics> {
+        let AttachmentData::R16(data) = self else {
+            return None;
+        };
+
+        let center = border_size..texture_size - border_size;
+
+        let (min, max, sum, count) = iproduct!(center.clone(), center)
+            .map(|(y, x)| data[(y * texture_size + x) as usize])
+            .filter(|&value| value != 0)
+            .fold((u16::MAX, 0, 0, 0), |(min, max, sum, count), value| {
+                (
+                    min.min(value),
+                    max.max(value),
+                    sum + value as u64,
+                    count + 1,
+                )
+            });
+
+        (count > 0).then(|| TileStatistics {
+            min: min as f32 / u16::MAX as f32,
+            max: max as f32 / u16::MAX as f32,
+            mean: sum as f32 / count as f32 / u16::MAX as f32,
+        })
+    }
+
     pub(crate) fn generate_mipmaps(&mut self, texture_size: u32, mip_level_count: u32) {
         fn generate_mipmap_rgba8(
             data: &mut Vec<[u8; 4]>,
@@ -187,11 +256,7 @@ impl AttachmentData {
                     }
                 }
 
-                let value = if count == 0 {
-                    0
-                } else {
-                    (value / count) as u16
-                };
+                let value = value.checked_div(count).unwrap_or(0) as u16;
 
                 data.push(value);
             }
@@ -218,6 +283,35 @@ impl AttachmentData {
         }
     }
 
+    /// Returns the normalized value of the pixel.
+    pub(crate) fn pixel(&self, index: usize) -> Vec4 {
+        match self {
+            AttachmentData::None => Vec4::splat(0.0),
+            AttachmentData::Rgba8(data) => {
+                let value = data[index];
+                Vec4::new(
+                    value[0] as f32 / u8::MAX as f32,
+                    value[1] as f32 / u8::MAX as f32,
+                    value[2] as f32 / u8::MAX as f32,
+                    value[3] as f32 / u8::MAX as f32,
+                )
+            }
+            AttachmentData::R16(data) => {
+                let value = data[index];
+                Vec4::new(value as f32 / u16::MAX as f32, 0.0, 0.0, 0.0)
+            }
+            AttachmentData::Rg16(data) => {
+                let value = data[index];
+                Vec4::new(
+                    value[0] as f32 / u16::MAX as f32,
+                    value[1] as f32 / u16::MAX as f32,
+                    0.0,
+                    0.0,
+                )
+            }
+        }
+    }
+
     pub(crate) fn sample(&self, uv: Vec2, size: u32) -> Vec4 {
         let uv = uv * size as f32 - 0.5;
 
@@ -229,31 +323,7 @@ impl AttachmentData {
         for (x, y) in iproduct!(0..2, 0..2) {
             let index = (uv.y + y) * size as i32 + (uv.x + x);
 
-            values[x as usize][y as usize] = match self {
-                AttachmentData::None => Vec4::splat(0.0),
-                AttachmentData::Rgba8(data) => {
-                    let value = data[index as usize];
-                    Vec4::new(
-                        value[0] as f32 / u8::MAX as f32,
-                        value[1] as f32 / u8::MAX as f32,
-                        value[2] as f32 / u8::MAX as f32,
-                        value[3] as f32 / u8::MAX as f32,
-                    )
-                }
-                AttachmentData::R16(data) => {
-                    let value = data[index as usize];
-                    Vec4::new(value as f32 / u16::MAX as f32, 0.0, 0.0, 0.0)
-                }
-                AttachmentData::Rg16(data) => {
-                    let value = data[index as usize];
-                    Vec4::new(
-                        value[0] as f32 / u16::MAX as f32,
-                        value[1] as f32 / u16::MAX as f32,
-                        0.0,
-                        0.0,
-                    )
-                }
-            };
+            values[x as usize][y as usize] = self.pixel(index as usize);
         }
 
         Vec4::lerp(
@@ -279,11 +349,14 @@ pub fn sample_attachment(
 
     let (lod, blend_ratio) = tile_tree.compute_blend(surface_position);
 
-    let lookup = tile_tree.lookup_tile(surface_position, lod, model);
+    // finer lods do not contain the attachment
+    let max_lod = tile_atlas.attachment(attachment_index).lod_range.end - 1;
+
+    let lookup = tile_tree.lookup_tile(surface_position, lod.min(max_lod), model);
     let mut value = tile_atlas.sample_attachment(lookup, attachment_index);
 
     if blend_ratio > 0.0 {
-        let lookup2 = tile_tree.lookup_tile(surface_position, lod - 1, model);
+        let lookup2 = tile_tree.lookup_tile(surface_position, (lod - 1).min(max_lod), model);
         value = Vec4::lerp(
             value,
             tile_atlas.sample_attachment(lookup2, attachment_index),
@@ -305,3 +378,29 @@ pub fn sample_height(
         sample_attachment(tile_tree, tile_atlas, 0, sample_world_position).x,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_ignore_the_border_and_voids() {
+        // a 4x4 tile with a border of one pixel, whose center contains a void
+        #[rustfmt::skip]
+        let data = AttachmentData::R16(vec![
+            u16::MAX, u16::MAX, u16::MAX, u16::MAX,
+            u16::MAX, 0,        100,      u16::MAX,
+            u16::MAX, 200,      300,      u16::MAX,
+            u16::MAX, u16::MAX, u16::MAX, u16::MAX,
+        ]);
+
+        let statistics = data.statistics(4, 1).unwrap();
+        let normalize = |value: f32| value / u16::MAX as f32;
+        assert_eq!(statistics.min, normalize(100.0));
+        assert_eq!(statistics.max, normalize(300.0));
+        assert_eq!(statistics.mean, normalize(200.0));
+
+        let void = AttachmentData::R16(vec![0; 16]);
+        assert_eq!(void.statistics(4, 1), None);
+    }
+}
@@ -1,24 +1,44 @@
 use crate::{
     formats::TC,
-    math::{TerrainModel, TileCoordinate},
+    math::{Coordinate, TerrainModel, TileBounds, TileCoordinate},
     prelude::{AttachmentConfig, AttachmentFormat},
+    render::{
+        attachment_bindings::attachment_identifier, attachment_generation::MAX_SOURCE_ATTACHMENTS,
+    },
     terrain::TerrainConfig,
     terrain_data::{
+        attachment_source::{
+            AttachmentSource, GeneratedAttachment, GeneratedTile, GpuGeneratedAttachment,
+        },
         tile_tree::{TileLookup, TileTree, TileTreeEntry},
-        AttachmentData, INVALID_ATLAS_INDEX, INVALID_LOD,
+        AttachmentData, TileStatistics, INVALID_ATLAS_INDEX, INVALID_LOD,
     },
     terrain_view::TerrainViewComponents,
 };
 use anyhow::Result;
 use bevy::{
+    math::{DVec2, DVec3},
     prelude::*,
     render::render_resource::*,
     tasks::{futures_lite::future, AsyncComputeTaskPool, Task},
-    utils::{HashMap, HashSet},
+    utils::{HashMap, HashSet, Instant},
 };
-use image::{io::Reader, DynamicImage, ImageBuffer, Luma, LumaA, Rgb, Rgba};
+use fixedbitset::FixedBitSet;
+use image::{DynamicImage, ImageBuffer, ImageReader, Luma, LumaA, Rgb, Rgba};
 use itertools::Itertools;
-use std::{collections::VecDeque, fs, mem, ops::DerefMut};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, VecDeque},
+    fs, iter, mem,
+    ops::{DerefMut, Range},
+    path::Path,
+    sync::{
+        atomic::{self, AtomicU32},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
 
 pub type Rgb8Image = ImageBuffer<Rgb<u8>, Vec<u8>>;
 pub type Rgba8Image = ImageBuffer<Rgba<u8>, Vec<u8>>;
@@ -59,6 +79,419 @@ impl From<AtlasTileAttachment> for AtlasTile {
     }
 }
 
+/// A per frame limit on the amount of tiles and bytes, that are streamed.
+///
+/// The last tile may exceed the byte limit, so that tiles larger than the limit are still streamed.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StreamingBudget {
+    pub tiles: u32,
+    pub bytes: u64,
+}
+
+impl StreamingBudget {
+    pub const UNLIMITED: Self = Self {
+        tiles: u32::MAX,
+        bytes: u64::MAX,
+    };
+
+    pub fn new(tiles: u32, bytes: u64) -> Self {
+        Self { tiles, bytes }
+    }
+
+    /// Scales the budget, while still allowing at least one tile per frame.
+    fn scaled(self, scale: f32) -> Self {
+        Self {
+            tiles: ((self.tiles as f64 * scale as f64) as u32).max(1),
+            bytes: ((self.bytes as f64 * scale as f64) as u64).max(1),
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        self.tiles > 0 && self.bytes > 0
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        self.tiles -= 1;
+        self.bytes = self.bytes.saturating_sub(bytes);
+    }
+}
+
+/// Scales the streaming budgets each frame to keep the frame time below a target.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct AdaptiveStreamingConfig {
+    /// The frame time, which should not be exceeded.
+    pub target_frame_time: Duration,
+    /// The smallest fraction of the configured budgets, which is used when the target is missed.
+    pub min_scale: f32,
+}
+
+impl Default for AdaptiveStreamingConfig {
+    fn default() -> Self {
+        Self {
+            target_frame_time: Duration::from_secs_f32(1.0 / 60.0),
+            min_scale: 0.1,
+        }
+    }
+}
+
+/// Configures how fast a [`TileAtlas`] streams tiles between the disk, the CPU and the GPU.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TileAtlasStreamingConfig {
+    /// The count of attachments, that are loaded from disk concurrently.
+    pub load_slots: u32,
+    /// The count of attachments, that are saved to disk concurrently.
+    pub save_slots: u32,
+    /// The count of attachments, that are read back from the GPU concurrently.
+    pub download_slots: u32,
+    /// The count of attachments per attachment type, that are processed on the GPU each frame
+    /// during preprocessing.
+    pub atlas_write_slots: u32,
+    /// The limit on the attachments, that start loading from disk each frame.
+    pub load_budget: StreamingBudget,
+    /// The limit on the attachments, that are uploaded to the GPU each frame.
+    pub upload_budget: StreamingBudget,
+    /// The limit on the attachments, that start being read back from the GPU each frame.
+    pub download_budget: StreamingBudget,
+    /// Scales the budgets to the frame time, if enabled.
+    pub adaptive: Option<AdaptiveStreamingConfig>,
+}
+
+impl Default for TileAtlasStreamingConfig {
+    fn default() -> Self {
+        Self {
+            load_slots: 64,
+            save_slots: 64,
+            download_slots: 128,
+            atlas_write_slots: 32,
+            load_budget: StreamingBudget::UNLIMITED,
+            upload_budget: StreamingBudget::UNLIMITED,
+            download_budget: StreamingBudget::UNLIMITED,
+            adaptive: None,
+        }
+    }
+}
+
+/// A histogram of the time between requesting a tile and all of its attachments being uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct LoadLatencyHistogram {
+    /// The count of loads per bucket, where bucket `i` contains the loads that took less than
+    /// [`LoadLatencyHistogram::BUCKET_BOUNDS`]`[i]` and the last bucket contains all slower ones.
+    pub counts: [u64; 12],
+    /// The summed latency of all loads.
+    pub total: Duration,
+}
+
+impl LoadLatencyHistogram {
+    pub const BUCKET_BOUNDS: [Duration; 11] = [
+        Duration::from_millis(1),
+        Duration::from_millis(2),
+        Duration::from_millis(4),
+        Duration::from_millis(8),
+        Duration::from_millis(16),
+        Duration::from_millis(32),
+        Duration::from_millis(64),
+        Duration::from_millis(128),
+        Duration::from_millis(256),
+        Duration::from_millis(512),
+        Duration::from_millis(1024),
+    ];
+
+    fn record(&mut self, latency: Duration) {
+        let bucket = Self::BUCKET_BOUNDS
+            .iter()
+            .position(|&bound| latency < bound)
+            .unwrap_or(Self::BUCKET_BOUNDS.len());
+
+        self.counts[bucket] += 1;
+        self.total += latency;
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (count, other_count) in iter::zip(&mut self.counts, other.counts) {
+            *count += other_count;
+        }
+
+        self.total += other.total;
+    }
+
+    /// The count of all recorded loads.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The average latency of all recorded loads.
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => self.total.div_f64(count as f64),
+        }
+    }
+}
+
+/// Keeps a set of tiles resident in a [`TileAtlas`], until the pin and all of its clones are dropped.
+///
+/// See [`TileAtlas::pin_tiles`] and [`TileAtlas::pin_region`].
+#[derive(Clone)]
+pub struct TilePin(Arc<()>);
+
+/// Sent when more tiles are requested than fit into a [`TileAtlas`].
+///
+/// The requests with the lowest priority are rejected or their tiles evicted, so that the
+/// [`TileTree`]s fall back to coarser tiles. Increasing the atlas size avoids this.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TileAtlasExhausted {
+    pub terrain: Entity,
+    /// The count of requests rejected in the last frame.
+    pub rejected_tiles: u32,
+    /// The count of requested tiles evicted in the last frame, in favor of more important ones.
+    pub evicted_tiles: u32,
+}
+
+/// Sent once a tile becomes resident in the [`TileAtlas`] and starts loading its attachments.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TileRequested {
+    pub terrain: Entity,
+    pub tile_coordinate: TileCoordinate,
+    /// The index of the tile inside the atlas, which stays the same until the tile is unloaded,
+    /// unless the atlas is resized.
+    pub atlas_index: u32,
+    /// The world space bounds of the tile between its minimum and maximum height.
+    pub bounds: TileBounds,
+}
+
+/// Sent once an attachment of a resident tile has been loaded, and once all of its attachments
+/// are ready and the tile is used by the [`TileTree`]s.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TileLoaded {
+    pub terrain: Entity,
+    pub tile_coordinate: TileCoordinate,
+    pub atlas_index: u32,
+    pub bounds: TileBounds,
+    /// The loaded attachment, or `None` once all attachments of the tile are ready.
+    pub attachment_index: Option<u32>,
+}
+
+/// Sent once a tile has been evicted from the [`TileAtlas`], or an attachment has been
+/// removed from a resident tile.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TileUnloaded {
+    pub terrain: Entity,
+    pub tile_coordinate: TileCoordinate,
+    pub atlas_index: u32,
+    pub bounds: TileBounds,
+    /// The removed attachment, or `None` if the entire tile has been evicted.
+    pub attachment_index: Option<u32>,
+}
+
+/// A change of a resident tile, which is sent as an event by the [`TileAtlas`].
+#[derive(Clone, Copy)]
+enum TileLifecycle {
+    Requested,
+    Loaded(Option<u32>),
+    Unloaded(Option<u32>),
+}
+
+static NEXT_SHARED_ATLAS_ID: AtomicU32 = AtomicU32::new(0);
+
+/// One physical tile atlas, which is shared by multiple terrains.
+///
+/// The terrains store their tiles in the same attachment textures and compete for the same
+/// atlas indices, so that the memory is distributed according to the demand of the views.
+/// Unused tiles of all terrains are evicted in least recently used order.
+/// All terrains sharing an atlas need the same attachment sizes and formats.
+///
+/// Shared atlases can not be resized.
+#[derive(Clone)]
+pub struct SharedTileAtlas {
+    pub(crate) id: u32,
+    pool: Arc<Mutex<AtlasIndexPool>>,
+}
+
+impl SharedTileAtlas {
+    /// Creates a shared atlas, which holds `atlas_size` tiles of all of its terrains combined.
+    pub fn new(atlas_size: u32) -> Self {
+        Self {
+            id: NEXT_SHARED_ATLAS_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            pool: Arc::new(Mutex::new(AtlasIndexPool::new(atlas_size))),
+        }
+    }
+
+    /// The amount of tiles the atlas can hold.
+    pub fn atlas_size(&self) -> u32 {
+        self.pool.lock().unwrap().atlas_size
+    }
+}
+
+/// The atlas indices of one or multiple terrains (owners).
+struct AtlasIndexPool {
+    atlas_size: u32,
+    /// The unused atlas indices and their owner, from least to most recently used.
+    /// Free indices are stored with an invalid coordinate at the front.
+    unused_tiles: VecDeque<(u32, AtlasTile)>,
+    /// The tiles of each owner, which have been evicted by other owners.
+    evictions: HashMap<u32, Vec<TileCoordinate>>,
+    /// The size, border, mip levels and format of each attachment, which all owners have to match.
+    attachments: Option<Vec<(u32, u32, u32, AttachmentFormat)>>,
+    owner_count: u32,
+}
+
+impl AtlasIndexPool {
+    fn new(atlas_size: u32) -> Self {
+        Self {
+            atlas_size,
+            unused_tiles: (0..atlas_size)
+                .map(|atlas_index| (0, AtlasTile::new(TileCoordinate::INVALID, atlas_index)))
+                .collect(),
+            evictions: default(),
+            attachments: None,
+            owner_count: 0,
+        }
+    }
+
+    /// Registers a new owner of the pool and returns its id.
+    fn register(&mut self, attachments: &[AttachmentConfig]) -> u32 {
+        let attachments = attachments
+            .iter()
+            .map(|attachment| {
+                (
+                    attachment.texture_size,
+                    attachment.border_size,
+                    attachment.mip_level_count,
+                    attachment.format,
+                )
+            })
+            .collect_vec();
+
+        if let Some(shared_attachments) = &self.attachments {
+            assert_eq!(
+                shared_attachments, &attachments,
+                "The attachments of all terrains sharing a tile atlas have to be compatible."
+            );
+        } else {
+            self.attachments = Some(attachments);
+        }
+
+        self.owner_count += 1;
+        self.owner_count - 1
+    }
+
+    fn len(&self) -> usize {
+        self.unused_tiles.len()
+    }
+
+    /// The unused tiles of the owner and the free indices, from least to most recently used.
+    fn unused_tiles(&self, owner: u32) -> impl Iterator<Item = AtlasTile> + '_ {
+        self.unused_tiles
+            .iter()
+            .filter(move |&&(tile_owner, tile)| {
+                tile_owner == owner || tile.coordinate == TileCoordinate::INVALID
+            })
+            .map(|&(_, tile)| tile)
+    }
+
+    /// Takes the least recently used index.
+    /// Tiles of other owners are evicted once they apply their evictions, so only own tiles
+    /// are returned with a valid coordinate.
+    fn pop(&mut self, owner: u32) -> Option<AtlasTile> {
+        let (tile_owner, tile) = self.unused_tiles.pop_front()?;
+
+        if tile_owner != owner && tile.coordinate != TileCoordinate::INVALID {
+            self.evictions
+                .entry(tile_owner)
+                .or_default()
+                .push(tile.coordinate);
+
+            return Some(AtlasTile::new(TileCoordinate::INVALID, tile.atlas_index));
+        }
+
+        Some(tile)
+    }
+
+    fn push_unused(&mut self, owner: u32, tile: AtlasTile) {
+        self.unused_tiles.push_back((owner, tile));
+    }
+
+    fn push_free(&mut self, atlas_index: u32) {
+        self.unused_tiles
+            .push_front((0, AtlasTile::new(TileCoordinate::INVALID, atlas_index)));
+    }
+
+    /// Removes the index from the unused ones, because it is used again.
+    fn reclaim(&mut self, atlas_index: u32) {
+        self.unused_tiles
+            .retain(|&(_, tile)| tile.atlas_index != atlas_index);
+    }
+
+    fn take_evictions(&mut self, owner: u32) -> Vec<TileCoordinate> {
+        self.evictions.remove(&owner).unwrap_or_default()
+    }
+
+    /// Frees all indices of a removed owner.
+    fn remove_owner(&mut self, owner: u32, tile_states: &HashMap<TileCoordinate, TileState>) {
+        let evicted_tiles = self.take_evictions(owner);
+
+        self.unused_tiles.retain(|&(tile_owner, tile)| {
+            tile_owner != owner || tile.coordinate == TileCoordinate::INVALID
+        });
+
+        for (tile_coordinate, tile) in tile_states {
+            if !evicted_tiles.contains(tile_coordinate) {
+                self.push_free(tile.atlas_index);
+            }
+        }
+    }
+
+    /// Changes the amount of indices and returns the unused tiles, whose indices were removed.
+    fn resize(&mut self, new_size: u32) -> Vec<TileCoordinate> {
+        let mut removed_tiles = Vec::new();
+
+        for atlas_index in self.atlas_size..new_size {
+            self.push_free(atlas_index);
+        }
+
+        self.unused_tiles.retain(|&(_, tile)| {
+            let keep = tile.atlas_index < new_size;
+
+            if !keep {
+                removed_tiles.push(tile.coordinate);
+            }
+
+            keep
+        });
+
+        self.atlas_size = new_size;
+
+        removed_tiles
+    }
+}
+
+/// A snapshot of the residency and streaming activity of a [`TileAtlas`].
+#[derive(Clone, Debug, Default)]
+pub struct TileAtlasStats {
+    /// The amount of tiles the atlas can hold.
+    pub atlas_size: u32,
+    /// The tiles, which occupy an atlas index.
+    pub resident_tiles: u32,
+    /// The resident tiles, which are currently requested by a [`TileTree`], pinned or in use by preprocessing.
+    pub requested_tiles: u32,
+    /// The resident tiles, which are pinned by a [`TilePin`].
+    pub pinned_tiles: u32,
+    /// The resident tiles, which are not loaded completely yet.
+    pub loading_tiles: u32,
+    /// The resident tiles, which are not used anymore, but kept until their atlas index is required.
+    pub unused_tiles: u32,
+    /// The count of tiles, which have been evicted from the atlas in total.
+    pub evictions: u64,
+    /// The count of evicted tiles, which have been loaded again in total.
+    pub reloads: u64,
+    pub load_latency: LoadLatencyHistogram,
+    /// The bytes loaded from disk in the last frame.
+    pub bytes_loaded: u64,
+    /// The bytes uploaded to the GPU in the last frame.
+    pub bytes_uploaded: u64,
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct AtlasTileAttachment {
     pub(crate) coordinate: TileCoordinate,
@@ -74,8 +507,19 @@ pub(crate) struct AtlasTileAttachmentWithData {
 }
 
 impl AtlasTileAttachmentWithData {
-    pub(crate) fn start_saving(self, path: String) -> Task<AtlasTileAttachment> {
+    pub(crate) fn start_saving(
+        self,
+        path: String,
+        border_size: u32,
+    ) -> Task<(AtlasTileAttachment, Option<TileStatistics>)> {
         AsyncComputeTaskPool::get().spawn(async move {
+            // the first attachment stores the height
+            let statistics = if self.tile.attachment_index == 0 {
+                self.data.statistics(self.texture_size, border_size)
+            } else {
+                None
+            };
+
             if STORE_PNG {
                 let path = self.tile.coordinate.path(&path, "png");
 
@@ -111,7 +555,7 @@ impl AtlasTileAttachmentWithData {
                 // println!("Finished saving tile: {path}");
             }
 
-            self.tile
+            (self.tile, statistics)
         })
     }
 
@@ -126,7 +570,7 @@ impl AtlasTileAttachmentWithData {
             let mut data = if STORE_PNG {
                 let path = tile.coordinate.path(&path, "png");
 
-                let mut reader = Reader::open(path)?;
+                let mut reader = ImageReader::open(path)?;
                 reader.no_limits();
                 let image = reader.decode().unwrap();
                 AttachmentData::from_bytes(image.as_bytes(), format)
@@ -147,6 +591,42 @@ impl AtlasTileAttachmentWithData {
             })
         })
     }
+
+    pub(crate) fn start_generating(
+        tile: AtlasTileAttachment,
+        generated: GeneratedAttachment,
+        generated_tile: GeneratedTile,
+        path: String,
+        mip_level_count: u32,
+    ) -> Task<Result<Self>> {
+        AsyncComputeTaskPool::get().spawn(async move {
+            let path = tile.coordinate.path(&path, "bin");
+            let (texture_size, format) = (generated_tile.texture_size, generated_tile.format);
+
+            let cached = generated.cache.then(|| fs::read(&path).ok()).flatten();
+
+            let mut data = if let Some(bytes) = cached {
+                AttachmentData::from_bytes(&bytes, format)
+            } else {
+                let data = generated.source.generate(&generated_tile);
+
+                if generated.cache {
+                    fs::create_dir_all(Path::new(&path).parent().unwrap())?;
+                    fs::write(&path, data.bytes())?;
+                }
+
+                data
+            };
+
+            data.generate_mipmaps(texture_size, mip_level_count);
+
+            Ok(Self {
+                tile,
+                data,
+                texture_size: 0,
+            })
+        })
+    }
 }
 
 /// An attachment of a [`TileAtlas`].
@@ -160,19 +640,52 @@ pub struct AtlasAttachment {
     offset: f32,
     pub(crate) mip_level_count: u32,
     pub(crate) format: AttachmentFormat,
+    /// The lods, for which the attachment exists.
+    pub(crate) lod_range: Range<u32>,
     pub(crate) data: Vec<AttachmentData>,
+    /// The source of the attachment, if it is generated instead of loaded.
+    generated: Option<GeneratedAttachment>,
+    /// The shader of the attachment, if it is generated on the GPU instead of loaded.
+    pub(crate) gpu_generated: Option<GpuGeneratedAttachment>,
 
-    pub(crate) saving_tiles: Vec<Task<AtlasTileAttachment>>,
+    pub(crate) saving_tiles: Vec<Task<(AtlasTileAttachment, Option<TileStatistics>)>>,
     pub(crate) loading_tiles: Vec<Task<Result<AtlasTileAttachmentWithData>>>,
+    /// The loaded tiles, which wait for the upload budget.
+    pending_uploads: VecDeque<AtlasTileAttachmentWithData>,
     pub(crate) uploading_tiles: Vec<AtlasTileAttachmentWithData>,
     pub(crate) downloading_tiles: Vec<Task<AtlasTileAttachmentWithData>>,
+    /// The tiles, which wait for their source attachments, before they are generated on the GPU.
+    pub(crate) waiting_tiles: Vec<AtlasTileAttachment>,
+    /// The tiles, which are generated on the GPU in this frame.
+    pub(crate) generating_tiles: Vec<AtlasTileAttachment>,
+    /// The tiles, which have been generated on the GPU.
+    pub(crate) generated_tiles: Vec<AtlasTileAttachment>,
 }
 
 impl AtlasAttachment {
-    fn new(config: &AttachmentConfig, tile_atlas_size: u32, path: &str) -> Self {
+    fn new(
+        config: &AttachmentConfig,
+        tile_atlas_size: u32,
+        lod_count: u32,
+        path: &str,
+        generated: Option<GeneratedAttachment>,
+        gpu_generated: Option<GpuGeneratedAttachment>,
+    ) -> Self {
         let name = config.name.clone();
         let path = format!("assets/{path}/data/{name}");
         let center_size = config.texture_size - 2 * config.border_size;
+        let lod_range = config.lod_range.clone().map_or(0..lod_count, |lod_range| {
+            lod_range.start..lod_range.end.min(lod_count)
+        });
+
+        assert!(
+            !lod_range.is_empty(),
+            "The lod range of the attachment {name} is empty."
+        );
+        assert!(
+            generated.is_none() || config.format != AttachmentFormat::Rgb8,
+            "The generated attachment {name} can not use the Rgb8 format."
+        );
 
         Self {
             name,
@@ -184,31 +697,37 @@ impl AtlasAttachment {
             offset: config.border_size as f32 / config.texture_size as f32,
             mip_level_count: config.mip_level_count,
             format: config.format,
+            lod_range,
             data: vec![AttachmentData::None; tile_atlas_size as usize],
+            generated,
+            gpu_generated,
             saving_tiles: default(),
             loading_tiles: default(),
+            pending_uploads: default(),
             uploading_tiles: default(),
             downloading_tiles: default(),
+            waiting_tiles: default(),
+            generating_tiles: default(),
+            generated_tiles: default(),
         }
     }
 
     fn update(&mut self, atlas_state: &mut TileAtlasState) {
         self.loading_tiles.retain_mut(|tile| {
-            future::block_on(future::poll_once(tile)).map_or(true, |tile| {
+            future::block_on(future::poll_once(tile)).is_none_or(|tile| {
                 if let Ok(tile) = tile {
-                    atlas_state.loaded_tile_attachment(tile.tile);
-                    self.uploading_tiles.push(tile.clone());
-                    self.data[tile.tile.atlas_index as usize] = tile.data;
-                } else {
-                    atlas_state.load_slots += 1;
+                    atlas_state.stats.bytes_loaded += tile.data.bytes().len() as u64;
+                    self.pending_uploads.push_back(tile);
                 }
 
+                atlas_state.load_slots += 1;
+
                 false
             })
         });
 
         self.downloading_tiles.retain_mut(|tile| {
-            future::block_on(future::poll_once(tile)).map_or(true, |tile| {
+            future::block_on(future::poll_once(tile)).is_none_or(|tile| {
                 atlas_state.downloaded_tile_attachment(tile.tile);
                 self.data[tile.tile.atlas_index as usize] = tile.data;
                 false
@@ -216,33 +735,136 @@ impl AtlasAttachment {
         });
 
         self.saving_tiles.retain_mut(|task| {
-            future::block_on(future::poll_once(task)).map_or(true, |tile| {
-                atlas_state.saved_tile_attachment(tile);
+            future::block_on(future::poll_once(task)).is_none_or(|(tile, statistics)| {
+                atlas_state.saved_tile_attachment(tile, statistics);
                 false
             })
         });
+
+        for tile in self.generated_tiles.drain(..) {
+            atlas_state.finished_loading(tile);
+        }
+    }
+
+    /// Uploads the next pending tile within the budget and marks it as loaded.
+    /// Returns `false`, if there is nothing left to upload.
+    fn upload_next(
+        &mut self,
+        atlas_state: &mut TileAtlasState,
+        budget: &mut StreamingBudget,
+    ) -> bool {
+        if !budget.is_available() {
+            return false;
+        }
+
+        let Some(tile) = self.pending_uploads.pop_front() else {
+            return false;
+        };
+
+        budget.consume(tile.data.bytes().len() as u64);
+
+        // tiles, which have been evicted in the meantime, are skipped
+        if atlas_state.finished_loading(tile.tile) {
+            atlas_state.stats.bytes_uploaded += tile.data.bytes().len() as u64;
+            self.data[tile.tile.atlas_index as usize] = tile.data.clone();
+            self.uploading_tiles.push(tile);
+        }
+
+        true
+    }
+
+    /// Moves the data of the relocated tiles and drops the data of the removed atlas indices.
+    fn resize(&mut self, atlas_size: u32, relocations: &[(u32, u32)]) {
+        // all loading tiles are evicted when shrinking
+        if atlas_size < self.data.len() as u32 {
+            self.waiting_tiles.clear();
+            self.generating_tiles.clear();
+        }
+
+        for &(old_index, new_index) in relocations {
+            self.data[new_index as usize] =
+                mem::replace(&mut self.data[old_index as usize], AttachmentData::None);
+        }
+
+        self.data.resize(atlas_size as usize, AttachmentData::None);
+
+        for tile in &mut self.uploading_tiles {
+            if let Some(&(_, new_index)) = relocations
+                .iter()
+                .find(|&&(old_index, _)| old_index == tile.tile.atlas_index)
+            {
+                tile.tile.atlas_index = new_index;
+            }
+        }
+
+        // the tiles of the removed atlas indices have been evicted
+        self.uploading_tiles
+            .retain(|tile| tile.tile.atlas_index < atlas_size);
+        self.pending_uploads
+            .retain(|tile| tile.tile.atlas_index < atlas_size);
+    }
+
+    /// The size of the data of one tile in bytes, including all mip levels.
+    fn tile_size(&self) -> u64 {
+        (0..self.mip_level_count)
+            .map(|mip_level| (self.texture_size >> mip_level).pow(2) as u64)
+            .sum::<u64>()
+            * self.format.pixel_size() as u64
+    }
+
+    /// Resets the data of a tile, which has not been stored yet, so that no stale data of the
+    /// previous occupant of its atlas index remains.
+    fn clear(&mut self, tile: AtlasTileAttachment) {
+        let size = self.texture_size * self.texture_size * self.format.pixel_size();
+        let mut data = AttachmentData::from_bytes(&vec![0; size as usize], self.format);
+        data.generate_mipmaps(self.texture_size, self.mip_level_count);
+
+        self.pending_uploads.push_back(AtlasTileAttachmentWithData {
+            tile,
+            data,
+            texture_size: self.texture_size,
+        });
     }
 
-    fn load(&mut self, tile: AtlasTileAttachment) {
+    fn load(&mut self, tile: AtlasTileAttachment, model: &TerrainModel) {
         // Todo: build customizable loader abstraction
-        self.loading_tiles
-            .push(AtlasTileAttachmentWithData::start_loading(
+        let task = if let Some(generated) = &self.generated {
+            let generated_tile = GeneratedTile {
+                coordinate: tile.coordinate,
+                model: model.clone(),
+                texture_size: self.texture_size,
+                border_size: self.border_size,
+                format: self.format,
+            };
+
+            AtlasTileAttachmentWithData::start_generating(
+                tile,
+                generated.clone(),
+                generated_tile,
+                self.path.clone(),
+                self.mip_level_count,
+            )
+        } else {
+            AtlasTileAttachmentWithData::start_loading(
                 tile,
                 self.path.clone(),
                 self.texture_size,
                 self.format,
                 self.mip_level_count,
-            ));
+            )
+        };
+
+        self.loading_tiles.push(task);
     }
 
     fn save(&mut self, tile: AtlasTileAttachment) {
         self.saving_tiles.push(
             AtlasTileAttachmentWithData {
-                tile: tile,
+                tile,
                 data: self.data[tile.atlas_index as usize].clone(),
                 texture_size: self.texture_size,
             }
-            .start_saving(self.path.clone()),
+            .start_saving(self.path.clone(), self.border_size),
         );
     }
 
@@ -251,6 +873,11 @@ impl AtlasAttachment {
             return Vec4::splat(0.0); // Todo: Handle this better
         }
 
+        // coarser lods do not contain the attachment
+        if lookup.atlas_lod < self.lod_range.start {
+            return Vec4::splat(0.0);
+        }
+
         let data = &self.data[lookup.atlas_index as usize];
         let uv = lookup.atlas_uv * self.scale + self.offset;
 
@@ -270,355 +897,2581 @@ enum LoadingState {
 }
 
 /// The internal representation of a present tile in a [`TileAtlas`].
-struct TileState {
+pub(crate) struct TileState {
     /// Indicates whether or not the tile is loading or loaded.
     state: LoadingState,
     /// The index of the tile inside the atlas.
     atlas_index: u32,
     /// The count of [`TileTrees`] that have requested this tile.
     requests: u32,
+    /// The attachments, which have been modified by preprocessing, but not saved yet.
+    dirty_attachments: AttachmentMask,
+    /// The attachments, which have finished loading.
+    loaded_attachments: AttachmentMask,
+    /// The time at which the tile started loading.
+    load_start: Instant,
 }
 
-pub(crate) struct TileAtlasState {
-    tile_states: HashMap<TileCoordinate, TileState>,
-    unused_tiles: VecDeque<AtlasTile>,
-    pub(crate) existing_tiles: HashSet<TileCoordinate>,
-
-    attachment_count: u32,
-
-    to_load: VecDeque<AtlasTileAttachment>,
-    load_slots: u32,
-    to_save: VecDeque<AtlasTileAttachment>,
-    pub(crate) save_slots: u32,
-    pub(crate) max_save_slots: u32,
+/// An attachment of a tile waiting to be loaded.
+///
+/// Requests are ordered by their priority, so that tiles close to the view relative to their size
+/// are loaded first.
+struct LoadRequest {
+    tile: AtlasTileAttachment,
+    /// Whether the tile is only requested ahead of time along the predicted path of a view.
+    prefetch: bool,
+    /// The smallest distance to the view of all [`TileTree`]s, that requested the tile.
+    distance: f64,
+}
 
-    pub(crate) download_slots: u32,
-    pub(crate) max_download_slots: u32,
+impl LoadRequest {
+    fn priority(&self) -> impl Ord {
+        (
+            tile_priority(self.tile.coordinate, self.prefetch, self.distance),
+            Reverse(self.tile.attachment_index),
+        )
+    }
+}
 
-    pub(crate) max_atlas_write_slots: u32,
+/// The priority of a requested tile, where tiles close to the view relative to their size come first.
+///
+/// The distance is weighted by the tile count of the lod, so that it is measured in tile sizes.
+/// A parent is never further away than its children, while being twice as large,
+/// so it is always loaded before them. Equally distant tiles are ordered by their lod.
+fn tile_priority(tile_coordinate: TileCoordinate, prefetch: bool, distance: f64) -> impl Ord {
+    let tile_distance = distance * TileCoordinate::count(tile_coordinate.lod) as f64;
+
+    (
+        Reverse(prefetch),
+        Reverse(OrderedDistance(tile_distance)),
+        Reverse(tile_coordinate.lod),
+    )
 }
 
-impl TileAtlasState {
-    fn new(
-        atlas_size: u32,
-        attachment_count: u32,
-        existing_tiles: HashSet<TileCoordinate>,
-    ) -> Self {
-        let unused_tiles = (0..atlas_size)
-            .map(|atlas_index| AtlasTile::new(TileCoordinate::INVALID, atlas_index))
-            .collect();
+/// A set of attachment indices, which grows with the attachments of the terrain.
+#[derive(Clone, Default)]
+pub(crate) struct AttachmentMask(FixedBitSet);
 
-        Self {
-            tile_states: default(),
-            unused_tiles,
-            existing_tiles,
-            attachment_count,
-            to_save: default(),
-            to_load: default(),
-            save_slots: 64,
-            max_save_slots: 64,
-            load_slots: 64,
-            download_slots: 128,
-            max_download_slots: 128,
-            max_atlas_write_slots: 32,
-        }
+impl AttachmentMask {
+    fn insert(&mut self, attachment_index: u32) {
+        self.0.grow_and_insert(attachment_index as usize);
     }
 
-    fn update(&mut self, attachments: &mut [AtlasAttachment]) {
-        while self.save_slots > 0 {
-            if let Some(tile) = self.to_save.pop_front() {
-                attachments[tile.attachment_index as usize].save(tile);
-                self.save_slots -= 1;
-            } else {
-                break;
-            }
+    fn remove(&mut self, attachment_index: u32) {
+        if self.contains(attachment_index) {
+            self.0.remove(attachment_index as usize);
         }
+    }
 
-        while self.load_slots > 0 {
-            if let Some(tile) = self.to_load.pop_front() {
-                attachments[tile.attachment_index as usize].load(tile);
-                self.load_slots -= 1;
-            } else {
-                break;
-            }
-        }
+    fn contains(&self, attachment_index: u32) -> bool {
+        self.0.contains(attachment_index as usize)
     }
 
-    fn loaded_tile_attachment(&mut self, tile: AtlasTileAttachment) {
-        self.load_slots += 1;
+    fn is_empty(&self) -> bool {
+        self.0.is_clear()
+    }
 
-        let tile_state = self.tile_states.get_mut(&tile.coordinate).unwrap();
+    fn len(&self) -> u32 {
+        self.0.count_ones(..) as u32
+    }
 
-        tile_state.state = match tile_state.state {
-            LoadingState::Loading(1) => LoadingState::Loaded,
-            LoadingState::Loading(n) => LoadingState::Loading(n - 1),
-            LoadingState::Loaded => {
-                panic!("Loaded more attachments, than registered with the tile atlas.")
-            }
-        };
+    fn is_subset(&self, other: &Self) -> bool {
+        self.0.is_subset(&other.0)
     }
 
-    fn saved_tile_attachment(&mut self, _tile: AtlasTileAttachment) {
-        self.save_slots += 1;
+    /// The indices of the attachments contained in the mask.
+    fn indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0
+            .ones()
+            .map(|attachment_index| attachment_index as u32)
     }
+}
 
-    fn downloaded_tile_attachment(&mut self, _tile: AtlasTileAttachment) {
-        self.download_slots += 1;
-    }
+impl FromIterator<u32> for AttachmentMask {
+    fn from_iter<T: IntoIterator<Item = u32>>(attachment_indices: T) -> Self {
+        let mut mask = Self::default();
 
-    fn get_tile(&mut self, tile_coordinate: TileCoordinate) -> AtlasTile {
-        if tile_coordinate == TileCoordinate::INVALID {
-            return AtlasTile::new(TileCoordinate::INVALID, INVALID_ATLAS_INDEX);
+        for attachment_index in attachment_indices {
+            mask.insert(attachment_index);
         }
 
-        let atlas_index = if self.existing_tiles.contains(&tile_coordinate) {
-            self.tile_states.get(&tile_coordinate).unwrap().atlas_index
-        } else {
-            INVALID_ATLAS_INDEX
-        };
+        mask
+    }
+}
 
-        AtlasTile::new(tile_coordinate, atlas_index)
+struct OrderedDistance(f64);
+
+impl PartialEq for OrderedDistance {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
+}
 
-    fn allocate_tile(&mut self) -> u32 {
-        let unused_tile = self.unused_tiles.pop_front().expect("Atlas out of indices");
+impl Eq for OrderedDistance {}
 
-        self.tile_states.remove(&unused_tile.coordinate);
+impl PartialOrd for OrderedDistance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-        unused_tile.atlas_index
+impl Ord for OrderedDistance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
     }
+}
 
-    fn get_or_allocate_tile(&mut self, tile_coordinate: TileCoordinate) -> AtlasTile {
-        if tile_coordinate == TileCoordinate::INVALID {
-            return AtlasTile::new(TileCoordinate::INVALID, INVALID_ATLAS_INDEX);
-        }
+impl PartialEq for LoadRequest {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
 
-        self.existing_tiles.insert(tile_coordinate);
+impl Eq for LoadRequest {}
 
-        let atlas_index = if let Some(tile) = self.tile_states.get(&tile_coordinate) {
-            tile.atlas_index
-        } else {
-            let atlas_index = self.allocate_tile();
+impl PartialOrd for LoadRequest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-            self.tile_states.insert(
-                tile_coordinate,
-                TileState {
-                    requests: 1,
-                    state: LoadingState::Loaded,
-                    atlas_index,
-                },
-            );
+impl Ord for LoadRequest {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority().cmp(&other.priority())
+    }
+}
 
-            atlas_index
-        };
+pub(crate) struct TileAtlasState {
+    pub(crate) tile_states: HashMap<TileCoordinate, TileState>,
+    /// The unused atlas indices, which may be shared with other terrains.
+    index_pool: Arc<Mutex<AtlasIndexPool>>,
+    /// The id of this terrain in the index pool.
+    owner: u32,
+    pub(crate) existing_tiles: HashSet<TileCoordinate>,
+    /// The attachments of each tile, which are stored on disk.
+    pub(crate) stored_attachments: HashMap<TileCoordinate, AttachmentMask>,
+    /// The normalized height statistics of the existing tiles.
+    pub(crate) tile_statistics: HashMap<TileCoordinate, TileStatistics>,
+    /// Incremented, whenever the statistics of the tiles in the atlas may have changed.
+    pub(crate) statistics_generation: u32,
+
+    /// The attachments of each lod, which are currently attached to the atlas.
+    lod_attachments: Vec<AttachmentMask>,
+
+    /// The attachments waiting to be loaded, with the highest priority first.
+    to_load: BinaryHeap<LoadRequest>,
+    /// The distances to the view of the tiles requested in the current frame.
+    load_distances: HashMap<TileCoordinate, f64>,
+    /// The distances along the predicted path of the tiles prefetched in the current frame.
+    prefetch_distances: HashMap<TileCoordinate, f64>,
+    load_slots: u32,
+    to_save: VecDeque<AtlasTileAttachment>,
+    pub(crate) save_slots: u32,
+    pub(crate) max_save_slots: u32,
 
-        AtlasTile::new(tile_coordinate, atlas_index)
+    pub(crate) download_slots: u32,
+    pub(crate) max_download_slots: u32,
+
+    pub(crate) max_atlas_write_slots: u32,
+
+    streaming: TileAtlasStreamingConfig,
+    /// The fraction of the configured budgets, which is currently used.
+    budget_scale: f32,
+    /// The remaining download budget of the current frame.
+    download_budget: StreamingBudget,
+
+    /// The streaming counters, the residency counts are computed on demand.
+    stats: TileAtlasStats,
+    /// The tiles, which have been evicted, used to detect reloads.
+    evicted_tiles: HashSet<TileCoordinate>,
+
+    /// The count of [`TilePin`]s of each pinned tile.
+    pinned_tiles: HashMap<TileCoordinate, u32>,
+    /// The tiles of each pin, which are released once the pin is dropped.
+    pins: Vec<(Weak<()>, Vec<TileCoordinate>)>,
+
+    /// The request count of each tile, which could not be loaded, because the atlas is full.
+    rejected_requests: HashMap<TileCoordinate, u32>,
+    /// The rejected requests of the current frame, caused by a full atlas.
+    rejected_tiles: u32,
+    /// The requested tiles evicted in the current frame, caused by a full atlas.
+    evicted_requested_tiles: u32,
+    /// Whether the atlas was full in the previous frame.
+    exhausted: bool,
+    /// The changes of the resident tiles and their atlas indices, which have not been sent yet.
+    lifecycle_events: Vec<(TileLifecycle, TileCoordinate, u32)>,
+}
+
+impl Drop for TileAtlasState {
+    fn drop(&mut self) {
+        // return the indices to the other terrains sharing the atlas
+        if let Ok(mut index_pool) = self.index_pool.lock() {
+            index_pool.remove_owner(self.owner, &self.tile_states);
+        }
     }
+}
 
-    fn request_tile(&mut self, tile_coordinate: TileCoordinate) {
-        if !self.existing_tiles.contains(&tile_coordinate) {
-            return;
+impl TileAtlasState {
+    fn new(
+        index_pool: Arc<Mutex<AtlasIndexPool>>,
+        owner: u32,
+        lod_attachments: Vec<AttachmentMask>,
+        existing_tiles: HashSet<TileCoordinate>,
+        tile_statistics: HashMap<TileCoordinate, TileStatistics>,
+        streaming: TileAtlasStreamingConfig,
+    ) -> Self {
+        let atlas_size = index_pool.lock().unwrap().atlas_size;
+
+        let stored_attachments = existing_tiles
+            .iter()
+            .map(|&tile_coordinate| {
+                (
+                    tile_coordinate,
+                    lod_attachments[tile_coordinate.lod as usize].clone(),
+                )
+            })
+            .collect();
+
+        Self {
+            tile_states: default(),
+            index_pool,
+            owner,
+            existing_tiles,
+            stored_attachments,
+            tile_statistics,
+            statistics_generation: 0,
+            lod_attachments,
+            to_save: default(),
+            to_load: default(),
+            load_distances: default(),
+            prefetch_distances: default(),
+            save_slots: streaming.save_slots,
+            max_save_slots: streaming.save_slots,
+            load_slots: streaming.load_slots,
+            download_slots: streaming.download_slots,
+            max_download_slots: streaming.download_slots,
+            max_atlas_write_slots: streaming.atlas_write_slots,
+            download_budget: streaming.download_budget,
+            budget_scale: 1.0,
+            streaming,
+            stats: TileAtlasStats {
+                atlas_size,
+                ..default()
+            },
+            evicted_tiles: default(),
+            pinned_tiles: default(),
+            pins: default(),
+            rejected_requests: default(),
+            rejected_tiles: 0,
+            evicted_requested_tiles: 0,
+            exhausted: false,
+            lifecycle_events: default(),
         }
+    }
 
-        let mut tile_states = mem::take(&mut self.tile_states);
+    /// Adjusts the budget scale to the duration of the last frame.
+    fn adapt_budgets(&mut self, frame_time: Duration) {
+        if let Some(adaptive) = self.streaming.adaptive {
+            self.budget_scale = if frame_time > adaptive.target_frame_time {
+                (self.budget_scale * 0.75).max(adaptive.min_scale)
+            } else {
+                (self.budget_scale * 1.1).min(1.0)
+            };
+        }
 
-        // check if the tile is already present else start loading it
-        if let Some(tile) = tile_states.get_mut(&tile_coordinate) {
-            if tile.requests == 0 {
-                // the tile is now used again
-                self.unused_tiles
-                    .retain(|unused_tile| tile.atlas_index != unused_tile.atlas_index);
-            }
+        self.download_budget = self.streaming.download_budget.scaled(self.budget_scale);
 
-            tile.requests += 1;
-        } else {
-            // Todo: implement better loading strategy
-            let atlas_index = self.allocate_tile();
+        self.stats.bytes_loaded = 0;
+        self.stats.bytes_uploaded = 0;
+    }
 
-            tile_states.insert(
-                tile_coordinate,
-                TileState {
-                    requests: 1,
-                    state: LoadingState::Loading(self.attachment_count),
-                    atlas_index,
-                },
-            );
+    fn update(&mut self, attachments: &mut [Option<AtlasAttachment>], model: &TerrainModel) {
+        self.release_dropped_pins();
+        self.retry_rejected_requests();
+        self.prioritize_loads();
 
-            for attachment_index in 0..self.attachment_count {
-                self.to_load.push_back(AtlasTileAttachment {
-                    coordinate: tile_coordinate,
-                    atlas_index,
-                    attachment_index,
-                });
+        while self.save_slots > 0 {
+            if let Some(tile) = self.to_save.pop_front() {
+                attachments[tile.attachment_index as usize]
+                    .as_mut()
+                    .unwrap()
+                    .save(tile);
+                self.save_slots -= 1;
+            } else {
+                break;
             }
         }
 
-        self.tile_states = tile_states;
+        let mut load_budget = self.streaming.load_budget.scaled(self.budget_scale);
+
+        while self.load_slots > 0 && load_budget.is_available() {
+            if let Some(LoadRequest { tile, .. }) = self.to_load.pop() {
+                let attachment = attachments[tile.attachment_index as usize]
+                    .as_mut()
+                    .unwrap();
+
+                if attachment.gpu_generated.is_some() {
+                    // generated on the GPU, once its source attachments are loaded
+                    attachment.waiting_tiles.push(tile);
+                } else if attachment.generated.is_some() || self.is_stored(tile) {
+                    // generated attachments are never stored by preprocessing
+                    attachment.load(tile, model);
+                    load_budget.consume(attachment.tile_size());
+                    self.load_slots -= 1;
+                } else {
+                    attachment.clear(tile);
+                }
+            } else {
+                break;
+            }
+        }
     }
 
-    fn release_tile(&mut self, tile_coordinate: TileCoordinate) {
-        if !self.existing_tiles.contains(&tile_coordinate) {
-            return;
-        }
+    /// Uploads the loaded tiles of all attachments in turn, until the upload budget is exhausted.
+    fn upload(&mut self, attachments: &mut [Option<AtlasAttachment>]) {
+        let mut upload_budget = self.streaming.upload_budget.scaled(self.budget_scale);
 
-        let tile = self
-            .tile_states
-            .get_mut(&tile_coordinate)
-            .expect("Tried releasing a tile, which is not present.");
-        tile.requests -= 1;
+        loop {
+            let mut uploaded = false;
 
-        if tile.requests == 0 {
-            // the tile is not used anymore
-            self.unused_tiles
-                .push_back(AtlasTile::new(tile_coordinate, tile.atlas_index));
+            for attachment in attachments.iter_mut().flatten() {
+                uploaded |= attachment.upload_next(self, &mut upload_budget);
+            }
+
+            if !uploaded {
+                break;
+            }
         }
     }
 
-    fn get_best_tile(&self, tile_coordinate: TileCoordinate) -> TileTreeEntry {
-        let mut best_tile_coordinate = tile_coordinate;
+    /// Hands the waiting tiles of the GPU generated attachments, whose source attachments are
+    /// loaded, over to the [`GpuTileAtlas`](super::gpu_tile_atlas::GpuTileAtlas).
+    fn schedule_generation(&mut self, attachments: &mut [Option<AtlasAttachment>]) {
+        for attachment in attachments.iter_mut().flatten() {
+            let Some(gpu_generated) = &attachment.gpu_generated else {
+                continue;
+            };
 
-        loop {
-            if best_tile_coordinate == TileCoordinate::INVALID
-                || best_tile_coordinate.lod == INVALID_LOD
-            {
-                // highest lod is not loaded
-                return TileTreeEntry {
-                    atlas_index: INVALID_ATLAS_INDEX,
-                    atlas_lod: INVALID_LOD,
+            let source_attachments = gpu_generated
+                .source_attachments
+                .iter()
+                .copied()
+                .collect::<AttachmentMask>();
+
+            let generating_tiles = &mut attachment.generating_tiles;
+
+            attachment.waiting_tiles.retain(|tile| {
+                // tiles, which have been evicted in the meantime, are dropped
+                let Some(tile_state) = self
+                    .tile_states
+                    .get(&tile.coordinate)
+                    .filter(|tile_state| tile_state.atlas_index == tile.atlas_index)
+                else {
+                    return false;
                 };
-            }
 
-            if let Some(atlas_tile) = self.tile_states.get(&best_tile_coordinate) {
-                if matches!(atlas_tile.state, LoadingState::Loaded) {
-                    // found best loaded tile
-                    return TileTreeEntry {
-                        atlas_index: atlas_tile.atlas_index,
-                        atlas_lod: best_tile_coordinate.lod,
-                    };
+                if source_attachments.is_subset(&tile_state.loaded_attachments)
+                    && generating_tiles.len() < self.max_atlas_write_slots as usize
+                {
+                    generating_tiles.push(*tile);
+                    return false;
                 }
-            }
 
-            best_tile_coordinate = best_tile_coordinate.parent();
+                true
+            });
         }
     }
-}
 
-/// A sparse storage of all terrain attachments, which streams data in and out of memory
-/// depending on the decisions of the corresponding [`TileTree`]s.
-///
-/// A tile is considered present and assigned an [`u32`] as soon as it is
-/// requested by any tile_tree. Then the tile atlas will start loading all of its attachments
-/// by storing the [`TileCoordinate`] (for one frame) in `load_events` for which
-/// attachment-loading-systems can listen.
-/// Tiles that are not being used by any tile_tree anymore are cached (LRU),
-/// until new atlas indices are required.
-///
-/// The [`u32`] can be used for accessing the attached data in systems by the CPU
-/// and in shaders by the GPU.
-#[derive(Component)]
-pub struct TileAtlas {
-    pub(crate) attachments: Vec<AtlasAttachment>,
-    // stores the attachment data
-    pub(crate) state: TileAtlasState,
-    pub(crate) path: String,
-    pub(crate) atlas_size: u32,
-    pub(crate) lod_count: u32,
-    pub(crate) model: TerrainModel,
-}
+    /// Whether another attachment can be read back from the GPU in this frame.
+    pub(crate) fn can_download(&self) -> bool {
+        self.download_slots > 0 && self.download_budget.is_available()
+    }
 
-impl TileAtlas {
-    /// Creates a new tile_tree from a terrain config.
-    pub fn new(config: &TerrainConfig) -> Self {
-        let attachments = config
-            .attachments
-            .iter()
-            .map(|attachment| AtlasAttachment::new(attachment, config.atlas_size, &config.path))
-            .collect_vec();
+    pub(crate) fn start_download(&mut self, tile_size: u64) {
+        self.download_slots -= 1;
+        self.download_budget.consume(tile_size);
+    }
 
-        let existing_tiles = Self::load_tile_config(&config.path);
+    /// Updates the priorities of all pending loads with the distances of the current frame.
+    fn prioritize_loads(&mut self) {
+        let mut requests = mem::take(&mut self.to_load).into_vec();
 
-        let state =
-            TileAtlasState::new(config.atlas_size, attachments.len() as u32, existing_tiles);
+        for request in &mut requests {
+            (request.prefetch, request.distance) = self.load_priority(request.tile.coordinate);
+        }
 
-        Self {
-            model: config.model.clone(),
-            attachments,
-            state,
-            path: config.path.to_string(),
-            atlas_size: config.atlas_size,
-            lod_count: config.lod_count,
+        self.to_load = requests.into();
+        self.load_distances.clear();
+        self.prefetch_distances.clear();
+    }
+
+    fn pin(&mut self, tile_coordinates: Vec<TileCoordinate>) -> TilePin {
+        let pin = TilePin(Arc::new(()));
+
+        for &tile_coordinate in &tile_coordinates {
+            *self.pinned_tiles.entry(tile_coordinate).or_default() += 1;
+            self.request_tile(tile_coordinate);
         }
+
+        self.pins.push((Arc::downgrade(&pin.0), tile_coordinates));
+
+        pin
     }
 
-    pub fn get_tile(&mut self, tile_coordinate: TileCoordinate) -> AtlasTile {
-        self.state.get_tile(tile_coordinate)
+    fn release_dropped_pins(&mut self) {
+        let (dropped_pins, pins) = mem::take(&mut self.pins)
+            .into_iter()
+            .partition(|(pin, _)| pin.strong_count() == 0);
+        self.pins = pins;
+
+        for (_, tile_coordinates) in dropped_pins {
+            for tile_coordinate in tile_coordinates {
+                let pins = self.pinned_tiles.get_mut(&tile_coordinate).unwrap();
+                *pins -= 1;
+
+                if *pins == 0 {
+                    self.pinned_tiles.remove(&tile_coordinate);
+                }
+
+                self.release_tile(tile_coordinate);
+            }
+        }
     }
 
-    pub fn get_or_allocate_tile(&mut self, tile_coordinate: TileCoordinate) -> AtlasTile {
-        self.state.get_or_allocate_tile(tile_coordinate)
+    /// Returns whether the tile is only prefetched and its distance to the view.
+    /// Pinned tiles are loaded first.
+    fn load_priority(&self, tile_coordinate: TileCoordinate) -> (bool, f64) {
+        if self.pinned_tiles.contains_key(&tile_coordinate) {
+            (false, 0.0)
+        } else if let Some(&distance) = self.load_distances.get(&tile_coordinate) {
+            (false, distance)
+        } else if let Some(&distance) = self.prefetch_distances.get(&tile_coordinate) {
+            (true, distance)
+        } else {
+            (false, f64::INFINITY)
+        }
     }
 
-    pub fn save(&mut self, tile: AtlasTileAttachment) {
-        self.state.to_save.push_back(tile);
+    fn tile_priority(&self, tile_coordinate: TileCoordinate) -> impl Ord {
+        let (prefetch, distance) = self.load_priority(tile_coordinate);
+        tile_priority(tile_coordinate, prefetch, distance)
     }
 
-    pub(super) fn get_best_tile(&self, tile_coordinate: TileCoordinate) -> TileTreeEntry {
-        self.state.get_best_tile(tile_coordinate)
+    fn update_load_distance(&mut self, tile_coordinate: TileCoordinate, distance: f64) {
+        let load_distance = self
+            .load_distances
+            .entry(tile_coordinate)
+            .or_insert(f64::INFINITY);
+        *load_distance = load_distance.min(distance);
     }
 
-    pub(super) fn sample_attachment(&self, tile_lookup: TileLookup, attachment_index: u32) -> Vec4 {
-        self.attachments[attachment_index as usize].sample(tile_lookup)
+    fn update_prefetch_distance(&mut self, tile_coordinate: TileCoordinate, distance: f64) {
+        let prefetch_distance = self
+            .prefetch_distances
+            .entry(tile_coordinate)
+            .or_insert(f64::INFINITY);
+        *prefetch_distance = prefetch_distance.min(distance);
     }
 
-    /// Updates the tile atlas according to all corresponding tile_trees.
-    pub(crate) fn update(
-        mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
-        mut tile_atlases: Query<&mut TileAtlas>,
-    ) {
-        for mut tile_atlas in tile_atlases.iter_mut() {
-            let TileAtlas {
-                state, attachments, ..
-            } = tile_atlas.deref_mut();
+    fn queue_load(&mut self, tile_coordinate: TileCoordinate, atlas_index: u32) {
+        let (prefetch, distance) = self.load_priority(tile_coordinate);
 
-            state.update(attachments);
+        if self.evicted_tiles.remove(&tile_coordinate) {
+            self.stats.reloads += 1;
+        }
 
-            for attachment in attachments {
-                attachment.update(state);
-            }
+        for attachment_index in self.lod_attachments[tile_coordinate.lod as usize].indices() {
+            self.to_load.push(LoadRequest {
+                tile: AtlasTileAttachment {
+                    coordinate: tile_coordinate,
+                    atlas_index,
+                    attachment_index,
+                },
+                prefetch,
+                distance,
+            });
         }
+    }
 
-        for (&(terrain, _view), tile_tree) in tile_trees.iter_mut() {
-            let mut tile_atlas = tile_atlases.get_mut(terrain).unwrap();
+    /// The attachments, which exist at the lod of the tile.
+    fn tile_attachments(&self, tile_coordinate: TileCoordinate) -> &AttachmentMask {
+        &self.lod_attachments[tile_coordinate.lod as usize]
+    }
 
-            for tile_coordinate in tile_tree.released_tiles.drain(..) {
-                tile_atlas.state.release_tile(tile_coordinate);
-            }
+    /// Tiles without any attachments are loaded right away.
+    fn initial_loading_state(&self, tile_coordinate: TileCoordinate) -> LoadingState {
+        match self.tile_attachments(tile_coordinate).len() {
+            0 => LoadingState::Loaded,
+            attachment_count => LoadingState::Loading(attachment_count),
+        }
+    }
 
-            for tile_coordinate in tile_tree.requested_tiles.drain(..) {
-                tile_atlas.state.request_tile(tile_coordinate);
+    /// Loads the added attachment for all resident tiles within its lod range,
+    /// which remain usable in the meantime.
+    ///
+    /// Like the attachments of the config, the attachment is expected to be stored for all
+    /// existing tiles within its lod range, unless it is generated.
+    fn add_attachment(&mut self, attachment_index: u32, lod_range: Range<u32>) {
+        for lod in lod_range.clone() {
+            self.lod_attachments[lod as usize].insert(attachment_index);
+        }
+
+        for &tile_coordinate in &self.existing_tiles {
+            if lod_range.contains(&tile_coordinate.lod) {
+                self.stored_attachments
+                    .entry(tile_coordinate)
+                    .or_default()
+                    .insert(attachment_index);
             }
         }
-    }
 
-    /// Saves the tile configuration of the terrain, which stores the [`TileCoordinate`]s of all the tiles
-    /// of the terrain.
-    pub(crate) fn save_tile_config(&self) {
-        let tc = TC {
-            tiles: self.state.existing_tiles.iter().copied().collect_vec(),
-        };
+        let resident_tiles = self
+            .tile_states
+            .iter_mut()
+            .filter(|(tile_coordinate, _)| lod_range.contains(&tile_coordinate.lod))
+            .map(|(&tile_coordinate, tile)| {
+                if let LoadingState::Loading(n) = tile.state {
+                    tile.state = LoadingState::Loading(n + 1);
+                }
 
-        tc.save_file(format!("assets/{}/config.tc", &self.path))
-            .unwrap();
+                (tile_coordinate, tile.atlas_index)
+            })
+            .collect_vec();
+
+        for (tile_coordinate, atlas_index) in resident_tiles {
+            let (prefetch, distance) = self.load_priority(tile_coordinate);
+
+            self.to_load.push(LoadRequest {
+                tile: AtlasTileAttachment {
+                    coordinate: tile_coordinate,
+                    atlas_index,
+                    attachment_index,
+                },
+                prefetch,
+                distance,
+            });
+        }
     }
 
-    /// Loads the tile configuration of the terrain, which stores the [`TileCoordinate`]s of all the tiles
-    /// of the terrain.
-    pub(crate) fn load_tile_config(path: &str) -> HashSet<TileCoordinate> {
-        if let Ok(tc) = TC::load_file(format!("assets/{}/config.tc", path)) {
-            tc.tiles.into_iter().collect()
-        } else {
-            println!("Tile config not found.");
-            HashSet::default()
+    /// Drops all pending work of the removed attachment and completes the tiles,
+    /// which were only waiting for it.
+    fn remove_attachment(&mut self, attachment_index: u32, attachment: AtlasAttachment) {
+        let lod_range = attachment.lod_range.clone();
+
+        for lod in lod_range.clone() {
+            self.lod_attachments[lod as usize].remove(attachment_index);
         }
+
+        self.to_load
+            .retain(|request| request.tile.attachment_index != attachment_index);
+
+        // the tiles of unsaved attachments were kept resident, until their data is written to disk
+        let (removed_saves, to_save) = mem::take(&mut self.to_save)
+            .into_iter()
+            .partition::<Vec<_>, _>(|tile| tile.attachment_index == attachment_index);
+        self.to_save = to_save.into();
+
+        for tile in removed_saves {
+            self.release_tile(tile.coordinate);
+        }
+
+        // running saves and downloads are finished, loads are cancelled
+        for task in attachment.saving_tiles {
+            let (tile, statistics) = future::block_on(task);
+            self.saved_tile_attachment(tile, statistics);
+        }
+
+        for task in attachment.downloading_tiles {
+            self.downloaded_tile_attachment(future::block_on(task).tile);
+        }
+
+        self.load_slots += attachment.loading_tiles.len() as u32;
+
+        // an attachment added at the same index later on is not stored yet
+        for stored_attachments in self.stored_attachments.values_mut() {
+            stored_attachments.remove(attachment_index);
+        }
+
+        for (&tile_coordinate, tile) in &mut self.tile_states {
+            if tile.loaded_attachments.contains(attachment_index) {
+                self.lifecycle_events.push((
+                    TileLifecycle::Unloaded(Some(attachment_index)),
+                    tile_coordinate,
+                    tile.atlas_index,
+                ));
+            } else if lod_range.contains(&tile_coordinate.lod) {
+                // tiles outside of the lod range were never waiting for the attachment
+                tile.state = match tile.state {
+                    LoadingState::Loading(1) => {
+                        self.lifecycle_events.push((
+                            TileLifecycle::Loaded(None),
+                            tile_coordinate,
+                            tile.atlas_index,
+                        ));
+                        LoadingState::Loaded
+                    }
+                    LoadingState::Loaded => LoadingState::Loaded,
+                    LoadingState::Loading(n) => LoadingState::Loading(n - 1),
+                };
+            }
+
+            tile.loaded_attachments.remove(attachment_index);
+            tile.dirty_attachments.remove(attachment_index);
+        }
+    }
+
+    fn is_stored(&self, tile: AtlasTileAttachment) -> bool {
+        self.stored_attachments
+            .get(&tile.coordinate)
+            .is_some_and(|attachments| attachments.contains(tile.attachment_index))
+    }
+
+    /// Marks the attachment of the tile as loaded.
+    /// Returns `false`, if the tile is no longer resident at the same atlas index.
+    fn finished_loading(&mut self, tile: AtlasTileAttachment) -> bool {
+        let Some(tile_state) = self
+            .tile_states
+            .get_mut(&tile.coordinate)
+            .filter(|tile_state| tile_state.atlas_index == tile.atlas_index)
+        else {
+            return false;
+        };
+
+        assert!(
+            !tile_state
+                .loaded_attachments
+                .contains(tile.attachment_index),
+            "Loaded more attachments, than registered with the tile atlas."
+        );
+
+        tile_state.loaded_attachments.insert(tile.attachment_index);
+        self.lifecycle_events.push((
+            TileLifecycle::Loaded(Some(tile.attachment_index)),
+            tile.coordinate,
+            tile.atlas_index,
+        ));
+
+        tile_state.state = match tile_state.state {
+            LoadingState::Loading(1) => {
+                self.stats
+                    .load_latency
+                    .record(tile_state.load_start.elapsed());
+                self.lifecycle_events.push((
+                    TileLifecycle::Loaded(None),
+                    tile.coordinate,
+                    tile.atlas_index,
+                ));
+                LoadingState::Loaded
+            }
+            LoadingState::Loading(n) => LoadingState::Loading(n - 1),
+            // attachments added at runtime stream into the loaded tiles
+            LoadingState::Loaded => LoadingState::Loaded,
+        };
+
+        true
+    }
+
+    fn saved_tile_attachment(
+        &mut self,
+        tile: AtlasTileAttachment,
+        statistics: Option<TileStatistics>,
+    ) {
+        self.save_slots += 1;
+
+        self.stored_attachments
+            .entry(tile.coordinate)
+            .or_default()
+            .insert(tile.attachment_index);
+
+        if let Some(statistics) = statistics {
+            self.tile_statistics.insert(tile.coordinate, statistics);
+            self.statistics_generation += 1;
+        }
+
+        // the tile was kept resident, until its data is written to disk
+        self.release_tile(tile.coordinate);
+    }
+
+    /// Saves the attachment of a resident tile and keeps the tile resident until it is written to disk.
+    fn save(&mut self, tile: AtlasTileAttachment) {
+        self.pin_tile(tile.coordinate);
+
+        let tile_state = self.tile_states.get_mut(&tile.coordinate).unwrap();
+        tile_state.dirty_attachments.remove(tile.attachment_index);
+
+        self.to_save.push_back(tile);
+    }
+
+    /// Includes the bounds of all children in the statistics of their parents.
+    fn propagate_tile_statistics(&mut self) {
+        let tile_coordinates = self
+            .tile_statistics
+            .keys()
+            .copied()
+            .filter(|tile_coordinate| tile_coordinate.lod > 0)
+            .sorted_by_key(|tile_coordinate| Reverse(tile_coordinate.lod))
+            .collect_vec();
+
+        for tile_coordinate in tile_coordinates {
+            let statistics = self.tile_statistics[&tile_coordinate];
+
+            if let Some(parent) = self.tile_statistics.get_mut(&tile_coordinate.parent()) {
+                parent.merge_bounds(statistics);
+            }
+        }
+
+        self.statistics_generation += 1;
+    }
+
+    fn downloaded_tile_attachment(&mut self, _tile: AtlasTileAttachment) {
+        self.download_slots += 1;
+    }
+
+    fn get_tile(&mut self, tile_coordinate: TileCoordinate) -> AtlasTile {
+        if tile_coordinate == TileCoordinate::INVALID {
+            return AtlasTile::new(TileCoordinate::INVALID, INVALID_ATLAS_INDEX);
+        }
+
+        let atlas_index = if self.existing_tiles.contains(&tile_coordinate) {
+            self.tile_states.get(&tile_coordinate).unwrap().atlas_index
+        } else {
+            INVALID_ATLAS_INDEX
+        };
+
+        AtlasTile::new(tile_coordinate, atlas_index)
+    }
+
+    /// Takes the least recently used atlas index and evicts the tile, which still occupies it.
+    /// Returns `None`, if all atlas indices are in use.
+    fn allocate_tile(&mut self) -> Option<u32> {
+        let unused_tile = self.index_pool.lock().unwrap().pop(self.owner)?;
+
+        self.evict_tile(unused_tile.coordinate);
+
+        Some(unused_tile.atlas_index)
+    }
+
+    fn evict_tile(&mut self, tile_coordinate: TileCoordinate) {
+        if let Some(tile) = self.tile_states.remove(&tile_coordinate) {
+            self.stats.evictions += 1;
+            self.statistics_generation += 1;
+            self.evicted_tiles.insert(tile_coordinate);
+            self.lifecycle_events.push((
+                TileLifecycle::Unloaded(None),
+                tile_coordinate,
+                tile.atlas_index,
+            ));
+        }
+    }
+
+    /// Evicts the tiles, whose atlas indices have been taken by other terrains sharing the atlas.
+    pub(crate) fn apply_evictions(&mut self) {
+        let evicted_tiles = self.index_pool.lock().unwrap().take_evictions(self.owner);
+
+        for tile_coordinate in evicted_tiles {
+            self.to_load
+                .retain(|request| request.tile.coordinate != tile_coordinate);
+            self.evict_tile(tile_coordinate);
+        }
+    }
+
+    /// Changes the amount of atlas indices.
+    ///
+    /// When shrinking, the loaded tiles with the highest priority are moved to the remaining
+    /// indices, while all other tiles are evicted and their requests are retried later.
+    /// Returns the relocated tiles as pairs of their old and new atlas index.
+    fn resize(&mut self, old_size: u32, new_size: u32) -> Vec<(u32, u32)> {
+        self.stats.atlas_size = new_size;
+
+        let removed_tiles = self.index_pool.lock().unwrap().resize(new_size);
+
+        if new_size >= old_size {
+            return Vec::new();
+        }
+
+        for tile_coordinate in removed_tiles {
+            self.evict_tile(tile_coordinate);
+        }
+
+        let moved_tiles = self
+            .tile_states
+            .iter()
+            .filter(|(_, tile)| tile.atlas_index >= new_size)
+            .map(|(&tile_coordinate, _)| tile_coordinate)
+            .sorted_by_cached_key(|&tile_coordinate| Reverse(self.tile_priority(tile_coordinate)))
+            .collect_vec();
+
+        let mut relocations = Vec::new();
+
+        for tile_coordinate in moved_tiles {
+            let tile = &self.tile_states[&tile_coordinate];
+            let (old_index, requests) = (tile.atlas_index, tile.requests);
+
+            // loading tiles are evicted, since their pending loads refer to the old index
+            let new_index = if matches!(tile.state, LoadingState::Loaded) {
+                self.allocate_tile()
+            } else {
+                None
+            };
+
+            if let Some(new_index) = new_index {
+                self.tile_states
+                    .get_mut(&tile_coordinate)
+                    .unwrap()
+                    .atlas_index = new_index;
+                relocations.push((old_index, new_index));
+                self.statistics_generation += 1;
+            } else {
+                self.to_load
+                    .retain(|request| request.tile.coordinate != tile_coordinate);
+                self.evict_tile(tile_coordinate);
+                self.rejected_requests.insert(tile_coordinate, requests);
+            }
+        }
+
+        relocations
+    }
+
+    fn stats(&self) -> TileAtlasStats {
+        let mut stats = self.stats.clone();
+
+        stats.resident_tiles = self.tile_states.len() as u32;
+        stats.requested_tiles = self
+            .tile_states
+            .values()
+            .filter(|tile| tile.requests > 0)
+            .count() as u32;
+        stats.pinned_tiles = self
+            .pinned_tiles
+            .keys()
+            .filter(|tile_coordinate| self.tile_states.contains_key(*tile_coordinate))
+            .count() as u32;
+        stats.loading_tiles = self
+            .tile_states
+            .values()
+            .filter(|tile| matches!(tile.state, LoadingState::Loading(_)))
+            .count() as u32;
+        stats.unused_tiles = self
+            .index_pool
+            .lock()
+            .unwrap()
+            .unused_tiles(self.owner)
+            .filter(|tile| tile.coordinate != TileCoordinate::INVALID)
+            .count() as u32;
+
+        stats
+    }
+
+    /// Allocates an atlas index for preprocessing, without evicting the excluded tiles.
+    ///
+    /// Unused tiles, which are not needed by any pending task, are evicted first.
+    /// Modified tiles are written back to disk beforehand, so that they can be reloaded later.
+    /// Returns `None`, if no tile can be evicted right now.
+    fn try_allocate_tile(
+        &mut self,
+        excluded_tiles: &HashSet<TileCoordinate>,
+        pending_uses: &HashMap<TileCoordinate, u32>,
+    ) -> Option<u32> {
+        let tile_states = &self.tile_states;
+        let index_pool = self.index_pool.lock().unwrap();
+
+        let is_evictable = |tile: &AtlasTile| {
+            tile.coordinate == TileCoordinate::INVALID
+                || !excluded_tiles.contains(&tile.coordinate)
+                    && matches!(tile_states[&tile.coordinate].state, LoadingState::Loaded)
+        };
+        let is_clean = |tile: &AtlasTile| {
+            tile.coordinate == TileCoordinate::INVALID
+                || tile_states[&tile.coordinate].dirty_attachments.is_empty()
+        };
+        let is_needed = |tile: &AtlasTile| pending_uses.contains_key(&tile.coordinate);
+
+        let unused_tile = index_pool
+            .unused_tiles(self.owner)
+            .find(|tile| is_evictable(tile) && is_clean(tile) && !is_needed(tile))
+            .or_else(|| {
+                index_pool
+                    .unused_tiles(self.owner)
+                    .find(|tile| is_evictable(tile) && is_clean(tile))
+            });
+
+        drop(index_pool);
+
+        if let Some(unused_tile) = unused_tile {
+            self.index_pool
+                .lock()
+                .unwrap()
+                .reclaim(unused_tile.atlas_index);
+            self.evict_tile(unused_tile.coordinate);
+
+            return Some(unused_tile.atlas_index);
+        }
+
+        // wait for the previous write back to finish, before starting the next one
+        if !self.to_save.is_empty() || self.save_slots < self.max_save_slots {
+            return None;
+        }
+
+        let write_back_tiles = self
+            .index_pool
+            .lock()
+            .unwrap()
+            .unused_tiles(self.owner)
+            .filter(|tile| is_evictable(tile))
+            .sorted_by_key(|tile| is_needed(tile))
+            .take(self.max_save_slots as usize)
+            .map(|tile| tile.coordinate)
+            .collect_vec();
+
+        for tile_coordinate in write_back_tiles {
+            let tile_state = &self.tile_states[&tile_coordinate];
+            let atlas_index = tile_state.atlas_index;
+            let dirty_attachments = tile_state.dirty_attachments.clone();
+
+            for attachment_index in dirty_attachments.indices() {
+                self.save(AtlasTileAttachment {
+                    coordinate: tile_coordinate,
+                    atlas_index,
+                    attachment_index,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Makes an existing tile resident for preprocessing and loads its stored attachments.
+    /// Returns `false`, if no atlas index is available right now.
+    pub(crate) fn make_resident(
+        &mut self,
+        tile_coordinate: TileCoordinate,
+        excluded_tiles: &HashSet<TileCoordinate>,
+        pending_uses: &HashMap<TileCoordinate, u32>,
+    ) -> bool {
+        self.apply_evictions();
+
+        if self.tile_states.contains_key(&tile_coordinate) {
+            return true;
+        }
+
+        let Some(atlas_index) = self.try_allocate_tile(excluded_tiles, pending_uses) else {
+            return false;
+        };
+
+        self.tile_states.insert(
+            tile_coordinate,
+            TileState {
+                requests: 0,
+                state: self.initial_loading_state(tile_coordinate),
+                atlas_index,
+                dirty_attachments: default(),
+                loaded_attachments: default(),
+                load_start: Instant::now(),
+            },
+        );
+        self.index_pool
+            .lock()
+            .unwrap()
+            .push_unused(self.owner, AtlasTile::new(tile_coordinate, atlas_index));
+
+        self.inserted_tile(tile_coordinate, atlas_index);
+        self.queue_load(tile_coordinate, atlas_index);
+
+        true
+    }
+
+    /// Whether the tile is resident and all of its attachments are loaded.
+    pub(crate) fn is_loaded(&self, tile_coordinate: TileCoordinate) -> bool {
+        self.tile_states
+            .get(&tile_coordinate)
+            .is_some_and(|tile| matches!(tile.state, LoadingState::Loaded))
+    }
+
+    /// Whether the attachment of the tile has been modified, but not saved yet.
+    pub(crate) fn is_dirty(&self, tile: AtlasTileAttachment) -> bool {
+        self.tile_states
+            .get(&tile.coordinate)
+            .is_some_and(|tile_state| tile_state.dirty_attachments.contains(tile.attachment_index))
+    }
+
+    pub(crate) fn modified_tile_attachment(&mut self, tile: AtlasTileAttachment) {
+        let tile_state = self.tile_states.get_mut(&tile.coordinate).unwrap();
+        tile_state.dirty_attachments.insert(tile.attachment_index);
+    }
+
+    /// Prevents a resident tile from being evicted, until it is released again.
+    pub(crate) fn pin_tile(&mut self, tile_coordinate: TileCoordinate) {
+        self.apply_evictions();
+
+        let tile = self.tile_states.get_mut(&tile_coordinate).unwrap();
+
+        if tile.requests == 0 {
+            // the tile is now used again
+            self.index_pool.lock().unwrap().reclaim(tile.atlas_index);
+        }
+
+        tile.requests += 1;
+    }
+
+    fn get_or_allocate_tile(&mut self, tile_coordinate: TileCoordinate) -> Option<AtlasTile> {
+        if tile_coordinate == TileCoordinate::INVALID {
+            return Some(AtlasTile::new(TileCoordinate::INVALID, INVALID_ATLAS_INDEX));
+        }
+
+        self.apply_evictions();
+
+        let atlas_index = if let Some(tile) = self.tile_states.get(&tile_coordinate) {
+            tile.atlas_index
+        } else {
+            let atlas_index = self.allocate_requested_tile(tile_coordinate)?;
+
+            self.tile_states.insert(
+                tile_coordinate,
+                TileState {
+                    requests: 1,
+                    state: LoadingState::Loaded,
+                    atlas_index,
+                    dirty_attachments: default(),
+                    loaded_attachments: self.tile_attachments(tile_coordinate).clone(),
+                    load_start: Instant::now(),
+                },
+            );
+
+            self.inserted_tile(tile_coordinate, atlas_index);
+
+            atlas_index
+        };
+
+        self.existing_tiles.insert(tile_coordinate);
+
+        Some(AtlasTile::new(tile_coordinate, atlas_index))
+    }
+
+    fn request_tile(&mut self, tile_coordinate: TileCoordinate) {
+        if !self.existing_tiles.contains(&tile_coordinate) {
+            return;
+        }
+
+        self.apply_evictions();
+
+        // check if the tile is already present else start loading it
+        if let Some(tile) = self.tile_states.get_mut(&tile_coordinate) {
+            if tile.requests == 0 {
+                // the tile is now used again
+                self.index_pool.lock().unwrap().reclaim(tile.atlas_index);
+            }
+
+            tile.requests += 1;
+        } else if let Some(requests) = self.rejected_requests.get_mut(&tile_coordinate) {
+            // the tile is retried, once an atlas index becomes available
+            *requests += 1;
+        } else if let Some(atlas_index) = self.allocate_requested_tile(tile_coordinate) {
+            self.insert_requested_tile(tile_coordinate, atlas_index, 1);
+        } else {
+            self.rejected_requests.insert(tile_coordinate, 1);
+            self.rejected_tiles += 1;
+        }
+    }
+
+    fn insert_requested_tile(
+        &mut self,
+        tile_coordinate: TileCoordinate,
+        atlas_index: u32,
+        requests: u32,
+    ) {
+        self.tile_states.insert(
+            tile_coordinate,
+            TileState {
+                requests,
+                state: self.initial_loading_state(tile_coordinate),
+                atlas_index,
+                dirty_attachments: default(),
+                loaded_attachments: default(),
+                load_start: Instant::now(),
+            },
+        );
+
+        self.inserted_tile(tile_coordinate, atlas_index);
+        self.queue_load(tile_coordinate, atlas_index);
+    }
+
+    /// Records the newly resident tile, which is ready right away, if it has no attachments.
+    fn inserted_tile(&mut self, tile_coordinate: TileCoordinate, atlas_index: u32) {
+        self.statistics_generation += 1;
+        self.lifecycle_events
+            .push((TileLifecycle::Requested, tile_coordinate, atlas_index));
+
+        if self.is_loaded(tile_coordinate) {
+            self.lifecycle_events
+                .push((TileLifecycle::Loaded(None), tile_coordinate, atlas_index));
+        }
+    }
+
+    /// Allocates an atlas index for a requested tile.
+    ///
+    /// If the atlas is full, the requested tile with the lowest priority is evicted instead,
+    /// unless the tile itself has an even lower priority.
+    /// Returns `None`, if the request has to be rejected.
+    fn allocate_requested_tile(&mut self, tile_coordinate: TileCoordinate) -> Option<u32> {
+        if let Some(atlas_index) = self.allocate_tile() {
+            return Some(atlas_index);
+        }
+
+        // only tiles requested by tile_trees may be evicted, pinned and preprocessed tiles are kept
+        let victim = self
+            .tile_states
+            .iter()
+            .filter(|&(tile_coordinate, tile)| {
+                tile.dirty_attachments.is_empty()
+                    && !self.pinned_tiles.contains_key(tile_coordinate)
+                    && (self.load_distances.contains_key(tile_coordinate)
+                        || self.prefetch_distances.contains_key(tile_coordinate))
+            })
+            .min_by_key(|&(&tile_coordinate, _)| self.tile_priority(tile_coordinate))
+            .map(|(&tile_coordinate, tile)| (tile_coordinate, tile.atlas_index, tile.requests))?;
+
+        let (victim_coordinate, atlas_index, requests) = victim;
+
+        if self.tile_priority(tile_coordinate) <= self.tile_priority(victim_coordinate) {
+            return None;
+        }
+
+        // the requests of the evicted tile are retried, once an atlas index becomes available
+        self.to_load
+            .retain(|request| request.tile.coordinate != victim_coordinate);
+        self.evict_tile(victim_coordinate);
+        self.rejected_requests.insert(victim_coordinate, requests);
+        self.evicted_requested_tiles += 1;
+
+        Some(atlas_index)
+    }
+
+    /// Loads the rejected tiles with the highest priority, as long as there are unused atlas indices.
+    fn retry_rejected_requests(&mut self) {
+        if self.rejected_requests.is_empty() {
+            return;
+        }
+
+        let rejected_tiles = self
+            .rejected_requests
+            .keys()
+            .copied()
+            .sorted_by_cached_key(|&tile_coordinate| Reverse(self.tile_priority(tile_coordinate)))
+            .take(self.index_pool.lock().unwrap().len())
+            .collect_vec();
+
+        for tile_coordinate in rejected_tiles {
+            let Some(atlas_index) = self.allocate_tile() else {
+                // the request stays rejected, until the next atlas index becomes available
+                self.rejected_tiles += 1;
+                break;
+            };
+
+            let requests = self.rejected_requests.remove(&tile_coordinate).unwrap();
+            self.insert_requested_tile(tile_coordinate, atlas_index, requests);
+        }
+    }
+
+    pub(crate) fn release_tile(&mut self, tile_coordinate: TileCoordinate) {
+        if !self.existing_tiles.contains(&tile_coordinate) {
+            return;
+        }
+
+        if let Some(requests) = self.rejected_requests.get_mut(&tile_coordinate) {
+            *requests -= 1;
+
+            if *requests == 0 {
+                self.rejected_requests.remove(&tile_coordinate);
+            }
+
+            return;
+        }
+
+        let tile = self
+            .tile_states
+            .get_mut(&tile_coordinate)
+            .expect("Tried releasing a tile, which is not present.");
+        tile.requests -= 1;
+
+        if tile.requests == 0 {
+            let atlas_index = tile.atlas_index;
+
+            if let LoadingState::Loading(remaining) = tile.state {
+                let queued = self
+                    .to_load
+                    .iter()
+                    .filter(|request| request.tile.coordinate == tile_coordinate)
+                    .count();
+
+                // drop the tile, if none of its attachments have started loading yet
+                if queued == remaining as usize {
+                    self.to_load
+                        .retain(|request| request.tile.coordinate != tile_coordinate);
+                    self.tile_states.remove(&tile_coordinate);
+                    self.index_pool.lock().unwrap().push_free(atlas_index);
+                    self.lifecycle_events.push((
+                        TileLifecycle::Unloaded(None),
+                        tile_coordinate,
+                        atlas_index,
+                    ));
+
+                    return;
+                }
+            }
+
+            // the tile is not used anymore
+            self.index_pool
+                .lock()
+                .unwrap()
+                .push_unused(self.owner, AtlasTile::new(tile_coordinate, atlas_index));
+        }
+    }
+
+    /// Returns the closest loaded ancestor of the tile (including itself).
+    ///
+    /// A tile is loaded once all attachments, whose lod range contains it, are loaded.
+    /// Attachments, which end at a coarser lod, are looked up at their finest lod instead.
+    fn get_best_tile(&self, tile_coordinate: TileCoordinate) -> TileTreeEntry {
+        let mut best_tile_coordinate = tile_coordinate;
+
+        loop {
+            if best_tile_coordinate == TileCoordinate::INVALID
+                || best_tile_coordinate.lod == INVALID_LOD
+            {
+                // highest lod is not loaded
+                return TileTreeEntry {
+                    atlas_index: INVALID_ATLAS_INDEX,
+                    atlas_lod: INVALID_LOD,
+                };
+            }
+
+            if let Some(atlas_tile) = self.tile_states.get(&best_tile_coordinate) {
+                if matches!(atlas_tile.state, LoadingState::Loaded) {
+                    // found best loaded tile
+                    return TileTreeEntry {
+                        atlas_index: atlas_tile.atlas_index,
+                        atlas_lod: best_tile_coordinate.lod,
+                    };
+                }
+            }
+
+            best_tile_coordinate = best_tile_coordinate.parent();
+        }
+    }
+}
+
+/// A sparse storage of all terrain attachments, which streams data in and out of memory
+/// depending on the decisions of the corresponding [`TileTree`]s.
+///
+/// A tile is considered present and assigned an [`u32`] as soon as it is
+/// requested by any tile_tree. Then the tile atlas will start loading all of its attachments,
+/// whose lod range contains the tile, by storing the [`TileCoordinate`] (for one frame)
+/// in `load_events` for which attachment-loading-systems can listen.
+/// Pending loads are ordered by their distance to the closest view relative to their size each frame,
+/// and are dropped, if the tile is released before any of its attachments started loading.
+/// Tiles that are not being used by any tile_tree anymore are cached (LRU),
+/// until new atlas indices are required.
+///
+/// The [`u32`] can be used for accessing the attached data in systems by the CPU
+/// and in shaders by the GPU.
+#[derive(Component)]
+pub struct TileAtlas {
+    /// The attachments by their index, removed attachments leave their index vacant.
+    pub(crate) attachments: Vec<Option<AtlasAttachment>>,
+    /// The indices of the attachments, which have been added or removed since the last extraction.
+    pub(crate) changed_attachments: Vec<u32>,
+    /// The id of the attachment layout in the generated attachment bindings.
+    pub(crate) attachment_layout: u32,
+    // stores the attachment data
+    pub(crate) state: TileAtlasState,
+    pub(crate) path: String,
+    pub(crate) atlas_size: u32,
+    pub(crate) lod_count: u32,
+    pub(crate) model: TerrainModel,
+    /// The height range calibrated from the source data, which overrides the one of the model.
+    pub(crate) height_range: Option<(f32, f32)>,
+    /// The index in the previous atlas textures of each atlas index, after the atlas has been resized.
+    pub(crate) resized_source_indices: Option<Vec<u32>>,
+    /// The id of the [`SharedTileAtlas`], whose textures are used by this terrain.
+    pub(crate) shared_atlas_id: Option<u32>,
+}
+
+impl TileAtlas {
+    /// Creates a new tile_tree from a terrain config.
+    ///
+    /// If the config specifies a [`SharedTileAtlas`], its size is used instead of the `atlas_size`.
+    pub fn new(config: &TerrainConfig) -> Self {
+        let (index_pool, owner, shared_atlas_id) = match &config.shared_atlas {
+            Some(shared_atlas) => {
+                let owner = shared_atlas
+                    .pool
+                    .lock()
+                    .unwrap()
+                    .register(&config.attachments);
+
+                (shared_atlas.pool.clone(), owner, Some(shared_atlas.id))
+            }
+            None => (
+                Arc::new(Mutex::new(AtlasIndexPool::new(config.atlas_size))),
+                0,
+                None,
+            ),
+        };
+
+        let atlas_size = index_pool.lock().unwrap().atlas_size;
+
+        let attachments = config
+            .attachments
+            .iter()
+            .enumerate()
+            .map(|(attachment_index, attachment)| {
+                let attachment_index = attachment_index as u32;
+
+                Some(AtlasAttachment::new(
+                    attachment,
+                    atlas_size,
+                    config.lod_count,
+                    &config.path,
+                    config.generated_attachments.get(&attachment_index).cloned(),
+                    config
+                        .gpu_generated_attachments
+                        .get(&attachment_index)
+                        .cloned(),
+                ))
+            })
+            .collect_vec();
+
+        let tc = Self::load_tile_config(&config.path);
+
+        let lod_attachments = (0..config.lod_count)
+            .map(|lod| {
+                attachments
+                    .iter()
+                    .flatten()
+                    .positions(|attachment| attachment.lod_range.contains(&lod))
+                    .map(|attachment_index| attachment_index as u32)
+                    .collect()
+            })
+            .collect_vec();
+
+        let state = TileAtlasState::new(
+            index_pool,
+            owner,
+            lod_attachments,
+            tc.tiles.into_iter().collect(),
+            tc.tile_statistics.into_iter().collect(),
+            config.streaming.clone(),
+        );
+
+        let mut tile_atlas = Self {
+            model: config.model.clone(),
+            attachments,
+            changed_attachments: default(),
+            attachment_layout: 0,
+            state,
+            path: config.path.to_string(),
+            atlas_size,
+            lod_count: config.lod_count,
+            height_range: None,
+            resized_source_indices: None,
+            shared_atlas_id,
+        };
+
+        for attachment_index in 0..config.attachments.len() as u32 {
+            tile_atlas.validate_name(attachment_index);
+            tile_atlas.validate_gpu_generated(attachment_index);
+        }
+
+        if let Some((min_height, max_height)) = tc.height_range {
+            tile_atlas.calibrate_height_range(min_height, max_height);
+        }
+
+        tile_atlas
+    }
+
+    pub(crate) fn attachment(&self, attachment_index: u32) -> &AtlasAttachment {
+        self.attachments[attachment_index as usize]
+            .as_ref()
+            .expect("The attachment has been removed.")
+    }
+
+    /// Panics, if the name of the attachment does not result in a valid and unique identifier
+    /// in the generated shader code.
+    fn validate_name(&self, attachment_index: u32) {
+        let name = &self.attachment(attachment_index).name;
+        let identifier = attachment_identifier(name);
+
+        assert!(
+            identifier
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic()),
+            "The name of the attachment {name:?} has to start with a letter."
+        );
+        assert!(
+            self.attachments
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| index as u32 != attachment_index)
+                .flat_map(|(_, attachment)| attachment)
+                .all(|attachment| !attachment_identifier(&attachment.name)
+                    .eq_ignore_ascii_case(&identifier)),
+            "The name of the attachment {name:?} collides with the one of another attachment."
+        );
+    }
+
+    /// Panics, if the attachment is generated on the GPU from invalid source attachments.
+    fn validate_gpu_generated(&self, attachment_index: u32) {
+        let attachment = self.attachment(attachment_index);
+
+        let Some(gpu_generated) = &attachment.gpu_generated else {
+            return;
+        };
+
+        assert!(
+            gpu_generated.source_attachments.len() <= MAX_SOURCE_ATTACHMENTS
+                && gpu_generated.source_attachments.iter().all(|&index| {
+                    index != attachment_index
+                        && self
+                            .attachments
+                            .get(index as usize)
+                            .is_some_and(Option::is_some)
+                }),
+            "The source attachments of the GPU generated attachment {} are invalid.",
+            attachment.name
+        );
+        assert!(
+            gpu_generated.source_attachments.iter().all(|&index| {
+                let source_lod_range = &self.attachment(index).lod_range;
+
+                source_lod_range.start <= attachment.lod_range.start
+                    && attachment.lod_range.end <= source_lod_range.end
+            }),
+            "The source attachments of the GPU generated attachment {} do not cover its lod range.",
+            attachment.name
+        );
+        assert_eq!(
+            attachment.mip_level_count, 1,
+            "GPU generated attachments only support a single mip level."
+        );
+    }
+
+    /// Adds an attachment to the live terrain and returns its index.
+    ///
+    /// The index of a previously removed attachment is reused, otherwise the attachment is appended.
+    /// The attachment streams in for all resident tiles, which remain usable in the meantime,
+    /// and is bound to the terrain shaders in the next frame.
+    pub fn add_attachment(&mut self, attachment_config: AttachmentConfig) -> u32 {
+        self.insert_attachment(&attachment_config, None, None)
+    }
+
+    /// Adds an attachment to the live terrain, whose tiles are generated by the source.
+    /// See [`TerrainConfig::add_generated_attachment`] and [`TileAtlas::add_attachment`].
+    ///
+    /// Panics, if the attachment uses the [`AttachmentFormat::Rgb8`] format.
+    pub fn add_generated_attachment(
+        &mut self,
+        attachment_config: AttachmentConfig,
+        source: impl AttachmentSource,
+        cache: bool,
+    ) -> u32 {
+        let generated = GeneratedAttachment {
+            source: Arc::new(source),
+            cache,
+        };
+
+        self.insert_attachment(&attachment_config, Some(generated), None)
+    }
+
+    /// Adds an attachment to the live terrain, whose tiles are generated by the compute shader.
+    /// See [`TerrainConfig::add_gpu_generated_attachment`] and [`TileAtlas::add_attachment`].
+    pub fn add_gpu_generated_attachment(
+        &mut self,
+        attachment_config: AttachmentConfig,
+        shader: Handle<Shader>,
+        source_attachments: Vec<u32>,
+    ) -> u32 {
+        let gpu_generated = GpuGeneratedAttachment {
+            shader,
+            source_attachments,
+        };
+
+        self.insert_attachment(&attachment_config, None, Some(gpu_generated))
+    }
+
+    fn insert_attachment(
+        &mut self,
+        attachment_config: &AttachmentConfig,
+        generated: Option<GeneratedAttachment>,
+        gpu_generated: Option<GpuGeneratedAttachment>,
+    ) -> u32 {
+        assert!(
+            self.shared_atlas_id.is_none(),
+            "The attachments of a shared tile atlas can not be changed."
+        );
+
+        let attachment_index = self
+            .attachments
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.attachments.len());
+
+        if attachment_index == self.attachments.len() {
+            self.attachments.push(None);
+        }
+
+        self.attachments[attachment_index] = Some(AtlasAttachment::new(
+            attachment_config,
+            self.atlas_size,
+            self.lod_count,
+            &self.path,
+            generated,
+            gpu_generated,
+        ));
+
+        let attachment_index = attachment_index as u32;
+
+        self.validate_name(attachment_index);
+        self.validate_gpu_generated(attachment_index);
+        self.state.add_attachment(
+            attachment_index,
+            self.attachment(attachment_index).lod_range.clone(),
+        );
+        self.changed_attachments.push(attachment_index);
+
+        attachment_index
+    }
+
+    /// Removes the attachment from the live terrain.
+    ///
+    /// Its index stays vacant, until another attachment is added, and the terrain shaders
+    /// sample the fallback texture instead. Stored data is kept on disk, while modified data,
+    /// which has not been saved yet, is discarded.
+    /// Attachments must not be removed while preprocessing.
+    ///
+    /// The first attachment stores the height and can not be removed.
+    pub fn remove_attachment(&mut self, attachment_index: u32) {
+        assert!(
+            self.shared_atlas_id.is_none(),
+            "The attachments of a shared tile atlas can not be changed."
+        );
+        assert_ne!(
+            attachment_index, 0,
+            "The height attachment can not be removed."
+        );
+        assert!(
+            self.attachments.iter().flatten().all(|attachment| {
+                attachment
+                    .gpu_generated
+                    .as_ref()
+                    .is_none_or(|gpu_generated| {
+                        !gpu_generated.source_attachments.contains(&attachment_index)
+                    })
+            }),
+            "The attachment is a source of a GPU generated attachment."
+        );
+
+        let attachment = self
+            .attachments
+            .get_mut(attachment_index as usize)
+            .and_then(Option::take)
+            .expect("The attachment does not exist.");
+
+        self.state.remove_attachment(attachment_index, attachment);
+        self.changed_attachments.push(attachment_index);
+    }
+
+    /// Keeps the tiles and all of their ancestors resident, until the returned pin is dropped.
+    ///
+    /// The tiles are loaded ahead of any requests of the [`TileTree`]s and are never evicted
+    /// while pinned.
+    pub fn pin_tiles(
+        &mut self,
+        tile_coordinates: impl IntoIterator<Item = TileCoordinate>,
+    ) -> TilePin {
+        let tile_coordinates = tile_coordinates
+            .into_iter()
+            .flat_map(|tile_coordinate| {
+                iter::successors(Some(tile_coordinate), |tile_coordinate| {
+                    (tile_coordinate.lod > 0).then(|| tile_coordinate.parent())
+                })
+            })
+            .filter(|tile_coordinate| self.state.existing_tiles.contains(tile_coordinate))
+            .unique()
+            .collect_vec();
+
+        self.state.pin(tile_coordinates)
+    }
+
+    /// Keeps all tiles up to the `max_lod`, which intersect the sphere around the world position,
+    /// resident until the returned pin is dropped.
+    pub fn pin_region(&mut self, center: DVec3, radius: f64, max_lod: u32) -> TilePin {
+        let max_lod = max_lod.min(self.lod_count - 1);
+        let height = (self.model.min_height + self.model.max_height) / 2.0;
+        let center_coordinate = Coordinate::from_world_position(center, &self.model);
+
+        let mut tile_coordinates = Vec::new();
+        let mut candidates = self.model.root_tiles().collect_vec();
+
+        while let Some(tile_coordinate) = candidates.pop() {
+            let tile_count = TileCoordinate::count(tile_coordinate.lod) as f64;
+            let tile_min =
+                DVec2::new(tile_coordinate.x as f64, tile_coordinate.y as f64) / tile_count;
+            let tile_max = tile_min + 1.0 / tile_count;
+
+            // the closest point of the tile to the center of the region
+            let coordinate = center_coordinate.project_to_side(tile_coordinate.side, &self.model);
+            let closest_coordinate = Coordinate::new(
+                tile_coordinate.side,
+                coordinate.uv.clamp(tile_min, tile_max),
+            );
+
+            if closest_coordinate
+                .world_position(&self.model, height)
+                .distance(center)
+                > radius
+            {
+                continue;
+            }
+
+            if self.state.existing_tiles.contains(&tile_coordinate) {
+                tile_coordinates.push(tile_coordinate);
+            }
+
+            if tile_coordinate.lod < max_lod {
+                candidates.extend(tile_coordinate.children());
+            }
+        }
+
+        self.state.pin(tile_coordinates)
+    }
+
+    /// Grows or shrinks the atlas to the new amount of tiles, while keeping the resident tiles
+    /// where possible.
+    ///
+    /// The attachment textures are reallocated and copied on the GPU in the next frame.
+    /// The atlas must not be resized while preprocessing.
+    pub fn resize(&mut self, atlas_size: u32) {
+        assert!(
+            self.shared_atlas_id.is_none(),
+            "A shared tile atlas can not be resized."
+        );
+
+        if atlas_size == self.atlas_size {
+            return;
+        }
+
+        let relocations = self.state.resize(self.atlas_size, atlas_size);
+
+        for attachment in self.attachments.iter_mut().flatten() {
+            attachment.resize(atlas_size, &relocations);
+        }
+
+        // resizing multiple times before the next extraction refers to the oldest textures
+        let mut source_indices = self
+            .resized_source_indices
+            .take()
+            .unwrap_or_else(|| (0..self.atlas_size).collect());
+
+        for &(old_index, new_index) in &relocations {
+            source_indices[new_index as usize] = source_indices[old_index as usize];
+        }
+
+        source_indices.resize(atlas_size as usize, INVALID_ATLAS_INDEX);
+
+        self.resized_source_indices = Some(source_indices);
+        self.atlas_size = atlas_size;
+    }
+
+    /// Returns a snapshot of the residency and streaming statistics of the atlas.
+    pub fn stats(&self) -> TileAtlasStats {
+        self.state.stats()
+    }
+
+    /// Returns the height statistics of the tile, if it has been preprocessed.
+    pub fn tile_statistics(&self, tile_coordinate: TileCoordinate) -> Option<TileStatistics> {
+        self.state
+            .tile_statistics
+            .get(&tile_coordinate)
+            .map(|statistics| statistics.denormalize(self.model.min_height, self.model.max_height))
+    }
+
+    /// Returns the world space bounds of the tile, which are based on its height statistics,
+    /// or on the height range of the terrain, if it has none.
+    pub fn tile_bounds(&self, tile_coordinate: TileCoordinate) -> TileBounds {
+        let (min_height, max_height) = self.tile_statistics(tile_coordinate).map_or(
+            (self.model.min_height, self.model.max_height),
+            |statistics| (statistics.min, statistics.max),
+        );
+
+        tile_coordinate.world_bounds(&self.model, min_height, max_height)
+    }
+
+    /// Returns the height statistics of the tiles currently stored in the atlas, indexed by their atlas index.
+    /// Tiles without statistics span the entire height range of the terrain.
+    pub(crate) fn atlas_tile_statistics(&self) -> Vec<TileStatistics> {
+        let (min_height, max_height) = (self.model.min_height, self.model.max_height);
+
+        let mut statistics = vec![
+            TileStatistics {
+                min: min_height,
+                max: max_height,
+                mean: (min_height + max_height) / 2.0,
+            };
+            self.atlas_size as usize
+        ];
+
+        for (tile_coordinate, tile) in &self.state.tile_states {
+            if let Some(tile_statistics) = self.state.tile_statistics.get(tile_coordinate) {
+                statistics[tile.atlas_index as usize] =
+                    tile_statistics.denormalize(min_height, max_height);
+            }
+        }
+
+        statistics
+    }
+
+    /// Overrides the height range of the model with the one the height data was normalized with.
+    pub fn calibrate_height_range(&mut self, min_height: f32, max_height: f32) {
+        self.model.min_height = min_height;
+        self.model.max_height = max_height;
+        self.height_range = Some((min_height, max_height));
+        self.state.statistics_generation += 1;
+    }
+
+    pub fn get_tile(&mut self, tile_coordinate: TileCoordinate) -> AtlasTile {
+        self.state.get_tile(tile_coordinate)
+    }
+
+    /// Returns the resident tile or allocates a new one, which is marked as existing.
+    ///
+    /// Returns `None`, if the atlas is full and no tile with a lower priority can be evicted.
+    pub fn get_or_allocate_tile(&mut self, tile_coordinate: TileCoordinate) -> Option<AtlasTile> {
+        self.state.get_or_allocate_tile(tile_coordinate)
+    }
+
+    pub fn save(&mut self, tile: AtlasTileAttachment) {
+        self.state.save(tile);
+    }
+
+    /// Reserves a download slot and the download budget for reading the tile back from the GPU.
+    pub(crate) fn start_download(&mut self, tile: AtlasTileAttachment) {
+        let attachment = self.attachment(tile.attachment_index);
+        let size = attachment.texture_size.pow(2) * attachment.format.pixel_size();
+
+        self.state.start_download(size as u64);
+    }
+
+    pub(super) fn get_best_tile(&self, tile_coordinate: TileCoordinate) -> TileTreeEntry {
+        self.state.get_best_tile(tile_coordinate)
+    }
+
+    pub(super) fn sample_attachment(&self, tile_lookup: TileLookup, attachment_index: u32) -> Vec4 {
+        self.attachment(attachment_index).sample(tile_lookup)
+    }
+
+    /// Updates the tile atlas according to all corresponding tile_trees.
+    pub(crate) fn update(
+        mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
+        mut tile_atlases: Query<(Entity, &mut TileAtlas)>,
+        mut exhausted_events: EventWriter<TileAtlasExhausted>,
+        mut requested_events: EventWriter<TileRequested>,
+        mut loaded_events: EventWriter<TileLoaded>,
+        mut unloaded_events: EventWriter<TileUnloaded>,
+        time: Res<Time<Real>>,
+    ) {
+        // the priorities of all views are required, before any tiles are allocated
+        for (&(terrain, _view), tile_tree) in tile_trees.iter() {
+            let (_, mut tile_atlas) = tile_atlases.get_mut(terrain).unwrap();
+
+            for (tile_coordinate, distance) in tile_tree.requested_tile_distances() {
+                tile_atlas
+                    .state
+                    .update_load_distance(tile_coordinate, distance);
+            }
+
+            for (tile_coordinate, distance) in tile_tree.prefetched_tile_distances() {
+                tile_atlas
+                    .state
+                    .update_prefetch_distance(tile_coordinate, distance);
+            }
+        }
+
+        for (&(terrain, _view), tile_tree) in tile_trees.iter_mut() {
+            let (_, mut tile_atlas) = tile_atlases.get_mut(terrain).unwrap();
+
+            for tile_coordinate in tile_tree.released_tiles.drain(..) {
+                tile_atlas.state.release_tile(tile_coordinate);
+            }
+
+            for tile_coordinate in tile_tree.requested_tiles.drain(..) {
+                tile_atlas.state.request_tile(tile_coordinate);
+            }
+        }
+
+        for (terrain, mut tile_atlas) in tile_atlases.iter_mut() {
+            let TileAtlas {
+                state,
+                attachments,
+                model,
+                ..
+            } = tile_atlas.deref_mut();
+
+            let rejected_tiles = mem::take(&mut state.rejected_tiles);
+            let evicted_tiles = mem::take(&mut state.evicted_requested_tiles);
+            let exhausted = rejected_tiles > 0 || evicted_tiles > 0;
+
+            if exhausted {
+                if !state.exhausted {
+                    warn!("The tile atlas of {terrain} is full, consider increasing its size.");
+                }
+
+                exhausted_events.send(TileAtlasExhausted {
+                    terrain,
+                    rejected_tiles,
+                    evicted_tiles,
+                });
+            }
+
+            state.exhausted = exhausted;
+
+            state.adapt_budgets(time.delta());
+            state.update(attachments, model);
+
+            for attachment in attachments.iter_mut().flatten() {
+                attachment.update(state);
+            }
+
+            state.upload(attachments);
+            state.schedule_generation(attachments);
+        }
+
+        // terrains sharing an atlas may have taken the indices of terrains updated before them
+        for (_, mut tile_atlas) in tile_atlases.iter_mut() {
+            tile_atlas.state.apply_evictions();
+        }
+
+        for (terrain, mut tile_atlas) in tile_atlases.iter_mut() {
+            for (lifecycle, tile_coordinate, atlas_index) in
+                mem::take(&mut tile_atlas.state.lifecycle_events)
+            {
+                let bounds = tile_atlas.tile_bounds(tile_coordinate);
+
+                match lifecycle {
+                    TileLifecycle::Requested => {
+                        requested_events.send(TileRequested {
+                            terrain,
+                            tile_coordinate,
+                            atlas_index,
+                            bounds,
+                        });
+                    }
+                    TileLifecycle::Loaded(attachment_index) => {
+                        loaded_events.send(TileLoaded {
+                            terrain,
+                            tile_coordinate,
+                            atlas_index,
+                            bounds,
+                            attachment_index,
+                        });
+                    }
+                    TileLifecycle::Unloaded(attachment_index) => {
+                        unloaded_events.send(TileUnloaded {
+                            terrain,
+                            tile_coordinate,
+                            atlas_index,
+                            bounds,
+                            attachment_index,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Saves the tile configuration of the terrain, which stores the [`TileCoordinate`]s of all the tiles
+    /// of the terrain.
+    pub(crate) fn save_tile_config(&mut self) {
+        self.state.propagate_tile_statistics();
+
+        let tc = TC {
+            tiles: self.state.existing_tiles.iter().copied().collect_vec(),
+            height_range: self.height_range,
+            tile_statistics: self
+                .state
+                .tile_statistics
+                .iter()
+                .map(|(&tile_coordinate, &statistics)| (tile_coordinate, statistics))
+                .collect_vec(),
+        };
+
+        tc.save_file(format!("assets/{}/config.tc", &self.path))
+            .unwrap();
+    }
+
+    /// Loads the tile configuration of the terrain, which stores the [`TileCoordinate`]s of all the tiles
+    /// of the terrain.
+    pub(crate) fn load_tile_config(path: &str) -> TC {
+        if let Ok(tc) = TC::load_file(format!("assets/{}/config.tc", path)) {
+            tc
+        } else {
+            println!("Tile config not found.");
+            TC::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math::TerrainModel, terrain::TerrainConfig};
+    use bevy::math::DVec3;
+    use itertools::iproduct;
+
+    /// Creates an atlas without attachments, whose tiles are loaded as soon as they are resident.
+    fn tile_atlas(atlas_size: u32, tiles: &[TileCoordinate]) -> TileAtlas {
+        let config = TerrainConfig {
+            lod_count: 3,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            atlas_size,
+            ..default()
+        };
+
+        let mut tile_atlas = TileAtlas::new(&config);
+        tile_atlas.state.existing_tiles.extend(tiles);
+        tile_atlas
+    }
+
+    /// Runs [`TileAtlas::update`] for the terrain, without any views.
+    fn update_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<TerrainViewComponents<TileTree>>()
+            .add_event::<TileAtlasExhausted>()
+            .add_event::<TileRequested>()
+            .add_event::<TileLoaded>()
+            .add_event::<TileUnloaded>()
+            .add_systems(Update, TileAtlas::update);
+        app
+    }
+
+    #[test]
+    fn full_atlas_rejects_requests_until_an_index_is_released() {
+        let coarse_tiles = (0..4).map(|i| TileCoordinate::new(0, 1, i % 2, i / 2));
+        let fine_tile = TileCoordinate::new(0, 2, 0, 0);
+        let tiles = coarse_tiles.clone().chain([fine_tile]).collect_vec();
+
+        let mut app = update_app();
+        let terrain = app.world_mut().spawn(tile_atlas(4, &tiles)).id();
+
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+
+        for tile_coordinate in coarse_tiles {
+            tile_atlas.state.request_tile(tile_coordinate);
+        }
+
+        // the atlas is full, so the request is rejected instead of panicking
+        tile_atlas.state.request_tile(fine_tile);
+        assert!(!tile_atlas.state.tile_states.contains_key(&fine_tile));
+
+        app.update();
+
+        let events = app.world().resource::<Events<TileAtlasExhausted>>();
+        let event = *events.get_reader().read(events).next().unwrap();
+        assert_eq!((event.terrain, event.rejected_tiles), (terrain, 1));
+
+        // the rejected request takes the first atlas index, which becomes available
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        tile_atlas
+            .state
+            .release_tile(TileCoordinate::new(0, 1, 0, 0));
+
+        app.update();
+
+        let tile_atlas = app.world().get::<TileAtlas>(terrain).unwrap();
+        assert!(tile_atlas.state.is_loaded(fine_tile));
+        assert_eq!(tile_atlas.state.tile_states.len(), 4);
+    }
+
+    #[test]
+    fn removed_attachment_is_no_longer_stored_until_it_is_added_again() {
+        let tile_coordinate = TileCoordinate::new(0, 1, 0, 0);
+        let attachment_config = |name: &str, lod_range: Option<Range<u32>>| AttachmentConfig {
+            name: name.to_string(),
+            texture_size: 4,
+            lod_range,
+            ..default()
+        };
+        let albedo = AtlasTileAttachment {
+            coordinate: tile_coordinate,
+            atlas_index: 0,
+            attachment_index: 1,
+        };
+
+        let mut app = update_app();
+        let terrain = app
+            .world_mut()
+            .spawn(tile_atlas(4, &[tile_coordinate]))
+            .id();
+
+        // the tile has no height, so that it loads without any data on disk
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        tile_atlas.add_attachment(attachment_config("height", Some(0..1)));
+        tile_atlas.add_attachment(attachment_config("albedo", None));
+        assert!(tile_atlas.state.is_stored(albedo));
+
+        tile_atlas.remove_attachment(1);
+        assert!(!tile_atlas.state.is_stored(albedo));
+
+        tile_atlas.state.request_tile(tile_coordinate);
+        app.update();
+
+        // an attachment at the vacant index, which does not exist for the tile, is not stored
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        assert!(tile_atlas.state.is_loaded(tile_coordinate));
+        assert_eq!(
+            tile_atlas.add_attachment(attachment_config("normal", Some(0..1))),
+            1
+        );
+        assert!(!tile_atlas.state.is_stored(albedo));
+        assert!(tile_atlas.state.to_load.is_empty());
+
+        // the attachment added again is reloaded for the resident tile
+        tile_atlas.remove_attachment(1);
+        tile_atlas.add_attachment(attachment_config("albedo", None));
+        assert!(tile_atlas.state.is_stored(albedo));
+
+        app.update();
+
+        let tile_atlas = app.world().get::<TileAtlas>(terrain).unwrap();
+        assert!(tile_atlas.state.is_loaded(tile_coordinate));
+        assert_eq!(tile_atlas.attachment(1).loading_tiles.len(), 1);
+    }
+
+    #[test]
+    fn attachments_are_not_limited_to_the_bits_of_an_integer() {
+        let tile_coordinate = TileCoordinate::new(0, 1, 0, 0);
+        let attachment_config = |name: String, lod_range: Option<Range<u32>>| AttachmentConfig {
+            name,
+            texture_size: 4,
+            lod_range,
+            ..default()
+        };
+
+        let mut app = update_app();
+        let terrain = app
+            .world_mut()
+            .spawn(tile_atlas(4, &[tile_coordinate]))
+            .id();
+
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        tile_atlas.add_attachment(attachment_config("height".to_string(), Some(0..1)));
+        tile_atlas.state.request_tile(tile_coordinate);
+
+        let attachment_indices = (1..40)
+            .map(|i| tile_atlas.add_attachment(attachment_config(format!("attachment{i}"), None)))
+            .collect_vec();
+        assert_eq!(attachment_indices, (1..40).collect_vec());
+
+        let stored_attachments = &tile_atlas.state.stored_attachments[&tile_coordinate];
+        assert_eq!(
+            stored_attachments.indices().collect_vec(),
+            attachment_indices
+        );
+        assert_eq!(tile_atlas.state.to_load.len(), 39);
+
+        tile_atlas.remove_attachment(39);
+
+        let stored_attachments = &tile_atlas.state.stored_attachments[&tile_coordinate];
+        assert!(!stored_attachments.contains(39));
+        assert!(stored_attachments.contains(38));
+        assert!(!tile_atlas
+            .state
+            .to_load
+            .iter()
+            .any(|request| request.tile.attachment_index == 39));
+    }
+
+    #[test]
+    fn tile_statistics_bound_the_data_of_all_children() {
+        let parent = TileCoordinate::new(0, 0, 0, 0);
+        let children = parent.children().collect_vec();
+        let statistics = |min: f32, max: f32| TileStatistics {
+            min,
+            max,
+            mean: (min + max) / 2.0,
+        };
+
+        let mut tile_atlas = tile_atlas(4, &[]);
+        tile_atlas.calibrate_height_range(100.0, 200.0);
+        tile_atlas.state.tile_statistics.extend([
+            (parent, statistics(0.4, 0.6)),
+            (children[0], statistics(0.2, 0.5)),
+            (children[3], statistics(0.5, 0.9)),
+        ]);
+        tile_atlas.state.propagate_tile_statistics();
+
+        // the bounds are denormalized to the height range, while the mean stays the one of the tile
+        let parent_statistics = tile_atlas.tile_statistics(parent).unwrap();
+        assert!((parent_statistics.min - 120.0).abs() < 1e-3);
+        assert!((parent_statistics.max - 190.0).abs() < 1e-3);
+        assert!((parent_statistics.mean - 150.0).abs() < 1e-3);
+
+        // tiles without statistics span the entire height range of the terrain
+        let bounds = tile_atlas.tile_bounds(children[1]);
+        assert_eq!(tile_atlas.tile_statistics(children[1]), None);
+        assert_eq!(
+            bounds,
+            children[1].world_bounds(&tile_atlas.model, 100.0, 200.0)
+        );
+    }
+
+    #[test]
+    fn load_budget_limits_the_tiles_loaded_per_frame() {
+        AsyncComputeTaskPool::get_or_init(default);
+
+        let tiles = (0..4)
+            .map(|i| TileCoordinate::new(0, 1, i % 2, i / 2))
+            .collect_vec();
+
+        let config = TerrainConfig {
+            lod_count: 2,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            atlas_size: 4,
+            streaming: TileAtlasStreamingConfig {
+                load_budget: StreamingBudget::new(2, u64::MAX),
+                ..default()
+            },
+            ..default()
+        }
+        .add_attachment(AttachmentConfig {
+            name: "height".to_string(),
+            texture_size: 4,
+            ..default()
+        });
+
+        let mut tile_atlas = TileAtlas::new(&config);
+        let TileAtlas {
+            state,
+            attachments,
+            model,
+            ..
+        } = &mut tile_atlas;
+        state.existing_tiles.extend(&tiles);
+        state.stored_attachments.extend(
+            tiles
+                .iter()
+                .map(|&tile_coordinate| (tile_coordinate, AttachmentMask::from_iter([0]))),
+        );
+
+        for &tile_coordinate in &tiles {
+            state.request_tile(tile_coordinate);
+        }
+
+        state.update(attachments, model);
+        assert_eq!(attachments[0].as_ref().unwrap().loading_tiles.len(), 2);
+        assert_eq!(state.to_load.len(), 2);
+
+        state.update(attachments, model);
+        assert_eq!(attachments[0].as_ref().unwrap().loading_tiles.len(), 4);
+        assert!(state.to_load.is_empty());
+    }
+
+    #[test]
+    fn adaptive_budgets_follow_the_frame_time() {
+        let mut tile_atlas = tile_atlas(4, &[]);
+        let state = &mut tile_atlas.state;
+        state.streaming.download_budget = StreamingBudget::new(100, 1000);
+        state.streaming.adaptive = Some(AdaptiveStreamingConfig {
+            target_frame_time: Duration::from_millis(16),
+            min_scale: 0.1,
+        });
+
+        for _ in 0..20 {
+            state.adapt_budgets(Duration::from_millis(50));
+        }
+
+        assert_eq!(state.budget_scale, 0.1);
+        assert_eq!(state.download_budget.tiles, 10);
+
+        for _ in 0..40 {
+            state.adapt_budgets(Duration::from_millis(5));
+        }
+
+        assert_eq!(state.budget_scale, 1.0);
+        assert_eq!(state.download_budget.tiles, 100);
+
+        // at least one tile is streamed per frame, regardless of the scale
+        assert_eq!(StreamingBudget::new(3, 3).scaled(0.1).tiles, 1);
+    }
+
+    #[test]
+    fn stats_count_residency_evictions_and_reloads() {
+        let coarse_tiles = (0..4)
+            .map(|i| TileCoordinate::new(0, 1, i % 2, i / 2))
+            .collect_vec();
+        let fine_tile = TileCoordinate::new(0, 2, 0, 0);
+
+        let mut tiles = coarse_tiles.clone();
+        tiles.push(fine_tile);
+        let mut tile_atlas = tile_atlas(4, &tiles);
+        let state = &mut tile_atlas.state;
+
+        for &tile_coordinate in &coarse_tiles {
+            state.request_tile(tile_coordinate);
+        }
+
+        state.release_tile(coarse_tiles[0]);
+        state.release_tile(coarse_tiles[1]);
+
+        let stats = tile_atlas.stats();
+        assert_eq!(stats.atlas_size, 4);
+        assert_eq!(stats.resident_tiles, 4);
+        assert_eq!(stats.requested_tiles, 2);
+        assert_eq!(stats.unused_tiles, 2);
+        assert_eq!(stats.loading_tiles, 0);
+
+        // the least recently used tile is evicted for the new request and loaded again afterwards
+        let state = &mut tile_atlas.state;
+        state.request_tile(fine_tile);
+        state.apply_evictions();
+        state.request_tile(coarse_tiles[0]);
+        state.apply_evictions();
+
+        let stats = tile_atlas.stats();
+        assert_eq!((stats.evictions, stats.reloads), (2, 1));
+        assert_eq!(stats.requested_tiles, 4);
+        assert_eq!(stats.unused_tiles, 0);
+    }
+
+    #[test]
+    fn load_latency_is_recorded_in_exponential_buckets() {
+        let mut histogram = LoadLatencyHistogram::default();
+        histogram.record(Duration::from_micros(500));
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_micros(3500));
+        histogram.record(Duration::from_secs(2));
+
+        assert_eq!(histogram.counts[0], 1);
+        assert_eq!(histogram.counts[2], 2);
+        assert_eq!(histogram.counts[11], 1);
+        assert_eq!(histogram.count(), 4);
+    }
+
+    #[test]
+    fn pinned_tiles_stay_resident_until_the_pin_is_dropped() {
+        let tiles = (0..3)
+            .flat_map(|lod| {
+                let count = TileCoordinate::count(lod);
+                iproduct!(0..count, 0..count).map(move |(x, y)| TileCoordinate::new(0, lod, x, y))
+            })
+            .collect_vec();
+        let mut tile_atlas = tile_atlas(8, &tiles);
+
+        // the pinned tile is kept resident together with all of its ancestors
+        let tile_pin = tile_atlas.pin_tiles([TileCoordinate::new(0, 2, 3, 1)]);
+        assert_eq!(tile_atlas.stats().pinned_tiles, 3);
+
+        // the region around the corner of the terrain only touches one tile of each lod
+        let region_pin = tile_atlas.pin_region(DVec3::new(-0.4, 0.5, -0.4), 0.1, 2);
+        let stats = tile_atlas.stats();
+        assert_eq!(stats.pinned_tiles, 5);
+        assert_eq!(stats.requested_tiles, 5);
+
+        // pinned tiles are never evicted for other requests
+        for &tile_coordinate in &tiles[5..] {
+            tile_atlas.state.request_tile(tile_coordinate);
+            tile_atlas.state.release_tile(tile_coordinate);
+        }
+        tile_atlas.state.apply_evictions();
+        assert_eq!(tile_atlas.stats().pinned_tiles, 5);
+
+        drop(tile_pin);
+        drop(region_pin);
+        tile_atlas.state.release_dropped_pins();
+
+        let stats = tile_atlas.stats();
+        assert_eq!(stats.pinned_tiles, 0);
+        assert_eq!(stats.requested_tiles, 0);
+    }
+
+    #[test]
+    fn resizing_keeps_the_requested_tiles_and_relocates_their_data() {
+        let tiles = (0..4)
+            .map(|i| TileCoordinate::new(0, 1, i % 2, i / 2))
+            .collect_vec();
+        let mut tile_atlas = tile_atlas(4, &tiles);
+
+        for &tile_coordinate in &tiles {
+            tile_atlas.state.request_tile(tile_coordinate);
+        }
+
+        tile_atlas.state.release_tile(tiles[0]);
+        tile_atlas.state.release_tile(tiles[1]);
+
+        let old_indices = tiles
+            .iter()
+            .map(|tile_coordinate| tile_atlas.state.tile_states[tile_coordinate].atlas_index)
+            .collect_vec();
+
+        // the unused tiles are evicted, while the requested ones move into the remaining indices
+        tile_atlas.resize(2);
+
+        assert_eq!(tile_atlas.atlas_size, 2);
+        assert_eq!(tile_atlas.stats().resident_tiles, 2);
+        assert!(!tile_atlas.state.tile_states.contains_key(&tiles[0]));
+
+        let source_indices = tile_atlas.resized_source_indices.clone().unwrap();
+
+        for (&tile_coordinate, &old_index) in iter::zip(&tiles, &old_indices).skip(2) {
+            let new_index = tile_atlas.state.tile_states[&tile_coordinate].atlas_index;
+            assert!(new_index < 2);
+            assert_eq!(source_indices[new_index as usize], old_index);
+        }
+
+        // growing the atlas again makes room for the evicted tiles
+        tile_atlas.resize(4);
+        tile_atlas.state.request_tile(tiles[0]);
+
+        assert_eq!(tile_atlas.stats().resident_tiles, 3);
+        let source_indices = tile_atlas.resized_source_indices.as_ref().unwrap();
+        assert_eq!(source_indices[2..], [INVALID_ATLAS_INDEX; 2]);
+    }
+
+    /// Fills each row of the tile with a gradient from zero to one.
+    struct GradientSource;
+
+    impl AttachmentSource for GradientSource {
+        fn generate(&self, tile: &GeneratedTile) -> AttachmentData {
+            let max = (tile.texture_size - 1) as f32;
+            tile.fill(|x, _| Vec4::splat(x as f32 / max))
+        }
+    }
+
+    #[test]
+    fn generated_attachments_are_loaded_from_their_source() {
+        AsyncComputeTaskPool::get_or_init(default);
+
+        let tile_coordinate = TileCoordinate::new(0, 1, 1, 0);
+
+        let mut app = update_app();
+        let terrain = app
+            .world_mut()
+            .spawn(tile_atlas(4, &[tile_coordinate]))
+            .id();
+
+        // nothing is stored for the tile, since generated attachments are never preprocessed
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        let attachment_config = AttachmentConfig {
+            name: "height".to_string(),
+            texture_size: 4,
+            ..default()
+        };
+        tile_atlas.add_generated_attachment(attachment_config, GradientSource, false);
+        tile_atlas.state.request_tile(tile_coordinate);
+
+        for _ in 0..100 {
+            app.update();
+
+            let tile_atlas = app.world().get::<TileAtlas>(terrain).unwrap();
+            if tile_atlas.state.is_loaded(tile_coordinate) {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let tile_atlas = app.world().get::<TileAtlas>(terrain).unwrap();
+        assert!(tile_atlas.state.is_loaded(tile_coordinate));
+
+        let atlas_index = tile_atlas.state.tile_states[&tile_coordinate].atlas_index;
+        let AttachmentData::R16(data) = &tile_atlas.attachment(0).data[atlas_index as usize] else {
+            panic!("The generated tile has not been uploaded.");
+        };
+        assert_eq!(data.len(), 16);
+        assert_eq!(data[4..8], [0, 21845, 43690, u16::MAX]);
+    }
+
+    #[test]
+    fn gpu_generated_attachments_wait_for_their_source_attachments() {
+        let tile_coordinate = TileCoordinate::new(0, 0, 0, 0);
+        let attachment_config = |name: &str| AttachmentConfig {
+            name: name.to_string(),
+            texture_size: 4,
+            lod_range: Some(0..1),
+            ..default()
+        };
+
+        let mut tile_atlas = tile_atlas(4, &[tile_coordinate]);
+        tile_atlas.add_attachment(attachment_config("height"));
+        tile_atlas.add_gpu_generated_attachment(
+            attachment_config("normal"),
+            Handle::default(),
+            vec![0],
+        );
+
+        // nothing has been preprocessed, so the height is cleared instead of loaded from disk
+        tile_atlas.state.stored_attachments.clear();
+        tile_atlas.state.request_tile(tile_coordinate);
+
+        let TileAtlas {
+            state,
+            attachments,
+            model,
+            ..
+        } = &mut tile_atlas;
+
+        // the height has not been uploaded yet, so the tile keeps waiting
+        state.update(attachments, model);
+        state.schedule_generation(attachments);
+
+        let normal = attachments[1].as_ref().unwrap();
+        assert_eq!(normal.waiting_tiles.len(), 1);
+        assert!(normal.generating_tiles.is_empty());
+
+        state.upload(attachments);
+        state.schedule_generation(attachments);
+
+        let normal = attachments[1].as_mut().unwrap();
+        assert!(normal.waiting_tiles.is_empty());
+        assert_eq!(normal.generating_tiles.len(), 1);
+        assert!(!state.is_loaded(tile_coordinate));
+
+        // the tile is loaded, once the GPU has generated its attachment
+        normal.generated_tiles = mem::take(&mut normal.generating_tiles);
+        normal.update(state);
+        assert!(state.is_loaded(tile_coordinate));
+    }
+
+    #[test]
+    fn attachments_are_only_loaded_and_sampled_within_their_lod_range() {
+        let coarse_tile = TileCoordinate::new(0, 0, 0, 0);
+        let fine_tile = TileCoordinate::new(0, 1, 0, 0);
+        let attachment_config = |name: &str, lod_range: Option<Range<u32>>| AttachmentConfig {
+            name: name.to_string(),
+            texture_size: 4,
+            lod_range,
+            ..default()
+        };
+
+        let mut tile_atlas = tile_atlas(4, &[coarse_tile, fine_tile]);
+        tile_atlas.add_attachment(attachment_config("height", None));
+        tile_atlas.add_attachment(attachment_config("detail", Some(1..3)));
+
+        tile_atlas.state.request_tile(coarse_tile);
+        tile_atlas.state.request_tile(fine_tile);
+
+        let requested_attachments = |tile_coordinate| {
+            tile_atlas
+                .state
+                .to_load
+                .iter()
+                .filter(|request| request.tile.coordinate == tile_coordinate)
+                .map(|request| request.tile.attachment_index)
+                .sorted()
+                .collect_vec()
+        };
+        assert_eq!(requested_attachments(coarse_tile), [0]);
+        assert_eq!(requested_attachments(fine_tile), [0, 1]);
+
+        // coarser lods do not contain the attachment, so they sample zero instead of its data
+        tile_atlas.attachments[1].as_mut().unwrap().data[0] =
+            AttachmentData::R16(vec![u16::MAX; 16]);
+        let lookup = |atlas_lod| TileLookup {
+            atlas_index: 0,
+            atlas_lod,
+            atlas_uv: Vec2::splat(0.5),
+        };
+
+        assert_eq!(tile_atlas.sample_attachment(lookup(0), 1), Vec4::ZERO);
+        assert_eq!(tile_atlas.sample_attachment(lookup(1), 1).x, 1.0);
+    }
+
+    #[test]
+    fn lifecycle_events_follow_the_residency_of_tiles() {
+        let first_tile = TileCoordinate::new(0, 0, 0, 0);
+        let second_tile = TileCoordinate::new(0, 1, 0, 0);
+
+        let mut app = update_app();
+        let terrain = app
+            .world_mut()
+            .spawn(tile_atlas(1, &[first_tile, second_tile]))
+            .id();
+
+        // nothing has been preprocessed, so the height is cleared instead of loaded from disk
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        tile_atlas.add_attachment(AttachmentConfig {
+            name: "height".to_string(),
+            texture_size: 4,
+            ..default()
+        });
+        tile_atlas.state.stored_attachments.clear();
+        tile_atlas.state.request_tile(first_tile);
+
+        let world = app.world();
+        let mut requested_reader = world.resource::<Events<TileRequested>>().get_reader();
+        let mut loaded_reader = world.resource::<Events<TileLoaded>>().get_reader();
+        let mut unloaded_reader = world.resource::<Events<TileUnloaded>>().get_reader();
+
+        let mut update = |app: &mut App| {
+            app.update();
+
+            let world = app.world();
+            let requested = world.resource::<Events<TileRequested>>();
+            let loaded = world.resource::<Events<TileLoaded>>();
+            let unloaded = world.resource::<Events<TileUnloaded>>();
+
+            (
+                requested_reader
+                    .read(requested)
+                    .map(|event| event.tile_coordinate)
+                    .collect_vec(),
+                loaded_reader
+                    .read(loaded)
+                    .map(|event| (event.tile_coordinate, event.attachment_index))
+                    .collect_vec(),
+                unloaded_reader
+                    .read(unloaded)
+                    .map(|event| (event.tile_coordinate, event.attachment_index))
+                    .collect_vec(),
+            )
+        };
+
+        let (requested, loaded, unloaded) = update(&mut app);
+        assert_eq!(requested, [first_tile]);
+        assert_eq!(loaded, [(first_tile, Some(0)), (first_tile, None)]);
+        assert!(unloaded.is_empty());
+
+        // the second tile takes the only atlas index of the first one
+        let mut tile_atlas = app.world_mut().get_mut::<TileAtlas>(terrain).unwrap();
+        tile_atlas.state.release_tile(first_tile);
+        tile_atlas.state.request_tile(second_tile);
+
+        let (requested, loaded, unloaded) = update(&mut app);
+        assert_eq!(requested, [second_tile]);
+        assert_eq!(loaded, [(second_tile, Some(0)), (second_tile, None)]);
+        assert_eq!(unloaded, [(first_tile, None)]);
+    }
+
+    #[test]
+    fn load_priority_measures_distance_in_tile_sizes() {
+        let coarse = tile_priority(TileCoordinate::new(0, 1, 0, 0), false, 100.0);
+        let fine = tile_priority(TileCoordinate::new(0, 4, 3, 3), false, 1.0);
+        assert!(
+            fine > coarse,
+            "close fine tiles are loaded before far coarse ones"
+        );
+
+        let parent = tile_priority(TileCoordinate::new(0, 2, 1, 1), false, 10.0);
+        let child = tile_priority(TileCoordinate::new(0, 3, 2, 2), false, 10.0);
+        assert!(parent > child, "parents are loaded before their children");
+
+        let parent = tile_priority(TileCoordinate::new(0, 2, 1, 1), false, 0.0);
+        let child = tile_priority(TileCoordinate::new(0, 3, 2, 2), false, 0.0);
+        assert!(parent > child, "equally distant tiles are ordered by lod");
+
+        let requested = tile_priority(TileCoordinate::new(0, 4, 0, 0), false, 1000.0);
+        let prefetched = tile_priority(TileCoordinate::new(0, 0, 0, 0), true, 0.0);
+        assert!(requested > prefetched, "prefetched tiles are loaded last");
     }
 }
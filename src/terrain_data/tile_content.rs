@@ -0,0 +1,328 @@
+//! Entities, which are streamed in and out together with the tiles of the terrain.
+
+#[cfg(feature = "high_precision")]
+use crate::big_space::{GridCell, ReferenceFrames};
+
+use crate::{
+    math::{Coordinate, TerrainModel, TileBounds, TileCoordinate},
+    terrain_data::{tile_atlas::TileAtlas, tile_tree::TileTree},
+    terrain_view::TerrainViewComponents,
+};
+use bevy::{
+    ecs::system::EntityCommands,
+    math::{DVec2, DVec3},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use std::{fs, sync::Arc};
+
+/// Spawns the content of a tile, once the tile becomes resident near a view.
+pub trait TileSpawner: Send + Sync + 'static {
+    /// Populates the root entity of the tile, which is placed at the origin of the tile.
+    ///
+    /// The root entity is despawned recursively once the tile is released,
+    /// so all content should be spawned as its children.
+    fn spawn(&self, tile: &ContentTile, root: &mut EntityCommands);
+}
+
+impl<F: Fn(&ContentTile, &mut EntityCommands) + Send + Sync + 'static> TileSpawner for F {
+    fn spawn(&self, tile: &ContentTile, root: &mut EntityCommands) {
+        self(tile, root)
+    }
+}
+
+/// Spawns the glTF scene stored next to the attachments of the tile, if the tile has one.
+///
+/// The scene of a tile is loaded from `{terrain path}/data/{folder}/{tile coordinate}.glb`
+/// and its origin is placed at the origin of the tile.
+pub struct TileSceneSpawner {
+    pub folder: String,
+}
+
+impl TileSpawner for TileSceneSpawner {
+    fn spawn(&self, tile: &ContentTile, root: &mut EntityCommands) {
+        let path = tile
+            .coordinate
+            .path(&format!("{}/data/{}", tile.path, self.folder), "glb");
+
+        // most tiles do not contain a scene
+        if fs::metadata(format!("assets/{path}")).is_ok() {
+            root.insert(tile.asset_server.load::<Scene>(format!("{path}#Scene0")));
+        }
+    }
+}
+
+/// A tile, whose content is spawned by a [`TileSpawner`].
+pub struct ContentTile<'a> {
+    pub terrain: Entity,
+    pub coordinate: TileCoordinate,
+    /// The world space bounds of the tile between its minimum and maximum height.
+    pub bounds: TileBounds,
+    pub model: &'a TerrainModel,
+    /// The path to the terrain folder inside the assets directory.
+    pub path: &'a str,
+    pub asset_server: &'a AssetServer,
+    /// The world position of the root entity, which lies on the surface at the center of the tile.
+    pub origin: DVec3,
+}
+
+impl ContentTile<'_> {
+    /// The coordinate of the location, given its uv inside of the tile.
+    pub fn location(&self, uv: Vec2) -> Coordinate {
+        let tile_offset = DVec2::new(self.coordinate.x as f64, self.coordinate.y as f64);
+
+        Coordinate::new(
+            self.coordinate.side,
+            (tile_offset + uv.as_dvec2()) / TileCoordinate::count(self.coordinate.lod) as f64,
+        )
+    }
+
+    /// The transform relative to the root entity of the location at the height above the surface,
+    /// whose y axis points along the surface normal.
+    pub fn surface_transform(&self, uv: Vec2, height: f32) -> Transform {
+        let local_position = self.location(uv).local_position(self.model);
+        let world_position = self
+            .model
+            .position_local_to_world(local_position, height as f64);
+        let normal = self.model.surface_normal(local_position);
+
+        Transform::from_translation((world_position - self.origin).as_vec3())
+            .with_rotation(Quat::from_rotation_arc(Vec3::Y, normal.as_vec3()))
+    }
+}
+
+/// Marks the root entity of the content of a tile.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TileContentRoot {
+    pub terrain: Entity,
+    pub tile_coordinate: TileCoordinate,
+}
+
+#[derive(Clone)]
+struct TileContentLayer {
+    lod: u32,
+    max_distance: Option<f64>,
+    spawner: Arc<dyn TileSpawner>,
+}
+
+/// Streams entities in and out together with the tiles of the terrain it is attached to.
+///
+/// The content of a tile is spawned once the tile is requested by a view and all of its
+/// attachments are loaded, and despawned once it is released by all views.
+#[derive(Component, Clone, Default)]
+pub struct TileContent {
+    layers: Vec<TileContentLayer>,
+    /// The root entities of the spawned tiles, by layer and tile.
+    spawned: HashMap<(usize, TileCoordinate), Entity>,
+}
+
+impl TileContent {
+    /// Registers the spawner for the tiles of the lod.
+    ///
+    /// If a `max_distance` is specified, only the tiles closer than it to one of the views are spawned.
+    pub fn add_spawner(
+        mut self,
+        lod: u32,
+        max_distance: Option<f64>,
+        spawner: impl TileSpawner,
+    ) -> Self {
+        self.layers.push(TileContentLayer {
+            lod,
+            max_distance,
+            spawner: Arc::new(spawner),
+        });
+        self
+    }
+
+    /// Spawns the content of the newly resident tiles and despawns the one of the released tiles.
+    pub(crate) fn update(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        tile_trees: Res<TerrainViewComponents<TileTree>>,
+        mut terrains: Query<(Entity, &mut TileContent, &TileAtlas, Option<&Parent>)>,
+        #[cfg(feature = "high_precision")] frames: ReferenceFrames,
+    ) {
+        for (terrain, mut tile_content, tile_atlas, parent) in &mut terrains {
+            let TileContent { layers, spawned } = tile_content.as_mut();
+
+            let mut resident_tiles = HashSet::new();
+
+            for (&(tile_tree_terrain, _view), tile_tree) in tile_trees.iter() {
+                if tile_tree_terrain != terrain {
+                    continue;
+                }
+
+                for (tile_coordinate, distance) in tile_tree.requested_tile_distances() {
+                    for (layer_index, layer) in layers.iter().enumerate() {
+                        if tile_coordinate.lod == layer.lod
+                            && layer
+                                .max_distance
+                                .is_none_or(|max_distance| distance < max_distance)
+                            && tile_atlas.state.is_loaded(tile_coordinate)
+                        {
+                            resident_tiles.insert((layer_index, tile_coordinate));
+                        }
+                    }
+                }
+            }
+
+            spawned.retain(|key, &mut root| {
+                let resident = resident_tiles.contains(key);
+
+                if !resident {
+                    commands.entity(root).despawn_recursive();
+                }
+
+                resident
+            });
+
+            #[cfg(feature = "high_precision")]
+            let frame = frames.parent_frame(terrain).unwrap();
+
+            for (layer_index, tile_coordinate) in resident_tiles {
+                if spawned.contains_key(&(layer_index, tile_coordinate)) {
+                    continue;
+                }
+
+                let tile_count = TileCoordinate::count(tile_coordinate.lod) as f64;
+                let tile_center =
+                    DVec2::new(tile_coordinate.x as f64, tile_coordinate.y as f64) + 0.5;
+                let origin = Coordinate::new(tile_coordinate.side, tile_center / tile_count)
+                    .world_position(&tile_atlas.model, 0.0);
+
+                let tile = ContentTile {
+                    terrain,
+                    coordinate: tile_coordinate,
+                    bounds: tile_atlas.tile_bounds(tile_coordinate),
+                    model: &tile_atlas.model,
+                    path: &tile_atlas.path,
+                    asset_server: &asset_server,
+                    origin,
+                };
+
+                #[cfg(feature = "high_precision")]
+                let (cell, translation) = frame.translation_to_grid(origin);
+                #[cfg(not(feature = "high_precision"))]
+                let translation = origin.as_vec3();
+
+                let mut root = commands.spawn((
+                    SpatialBundle::from_transform(Transform::from_translation(translation)),
+                    TileContentRoot {
+                        terrain,
+                        tile_coordinate,
+                    },
+                ));
+
+                #[cfg(feature = "high_precision")]
+                root.insert(cell);
+
+                // the content shares the reference frame of the terrain
+                if let Some(parent) = parent {
+                    root.set_parent(parent.get());
+                }
+
+                layers[layer_index].spawner.spawn(&tile, &mut root);
+
+                spawned.insert((layer_index, tile_coordinate), root.id());
+            }
+        }
+    }
+
+    /// Despawns the content of the terrains, which no longer stream any content.
+    pub(crate) fn cleanup(
+        mut commands: Commands,
+        mut removed: RemovedComponents<TileContent>,
+        roots: Query<(Entity, &TileContentRoot)>,
+    ) {
+        let terrains = removed.read().collect::<HashSet<_>>();
+
+        if terrains.is_empty() {
+            return;
+        }
+
+        for (root, content_root) in &roots {
+            if terrains.contains(&content_root.terrain) {
+                commands.entity(root).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        terrain::TerrainConfig,
+        terrain_data::tile_atlas::{TileAtlasExhausted, TileLoaded, TileRequested, TileUnloaded},
+        terrain_view::TerrainViewConfig,
+    };
+
+    fn content_roots(app: &mut App) -> Vec<(TileCoordinate, String)> {
+        app.world_mut()
+            .query::<(&TileContentRoot, &Name)>()
+            .iter(app.world())
+            .map(|(content_root, name)| (content_root.tile_coordinate, name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn content_follows_the_tiles_requested_by_the_views() {
+        let tile_coordinate = TileCoordinate::new(0, 0, 0, 0);
+        let config = TerrainConfig {
+            lod_count: 1,
+            model: TerrainModel::planar(DVec3::ZERO, 1.0, 0.0, 1.0),
+            ..default()
+        };
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_resource::<TerrainViewComponents<TileTree>>()
+            .add_event::<TileAtlasExhausted>()
+            .add_event::<TileRequested>()
+            .add_event::<TileLoaded>()
+            .add_event::<TileUnloaded>()
+            .add_systems(
+                Update,
+                (
+                    TileTree::compute_requests,
+                    TileAtlas::update,
+                    TileContent::update,
+                    TileContent::cleanup,
+                )
+                    .chain(),
+            );
+
+        let mut tile_atlas = TileAtlas::new(&config);
+        tile_atlas.state.existing_tiles.insert(tile_coordinate);
+        let tile_tree = TileTree::new(&tile_atlas, &TerrainViewConfig::default());
+
+        let tile_content = TileContent::default().add_spawner(
+            0,
+            None,
+            |tile: &ContentTile, root: &mut EntityCommands| {
+                root.insert(Name::new(tile.coordinate.to_string()));
+            },
+        );
+
+        let terrain = app.world_mut().spawn((tile_atlas, tile_content)).id();
+        let view = app.world_mut().spawn(Transform::default()).id();
+        app.world_mut()
+            .resource_mut::<TerrainViewComponents<TileTree>>()
+            .insert((terrain, view), tile_tree);
+
+        // the tile has no attachments, so its content is spawned as soon as it is requested
+        app.update();
+        assert_eq!(
+            content_roots(&mut app),
+            [(tile_coordinate, tile_coordinate.to_string())]
+        );
+
+        // the content is despawned, once no view requests the tile anymore
+        app.world_mut()
+            .resource_mut::<TerrainViewComponents<TileTree>>()
+            .remove(&(terrain, view));
+
+        app.update();
+        assert!(content_roots(&mut app).is_empty());
+    }
+}
@@ -7,12 +7,20 @@ use crate::{
 use bevy::{
     math::{DVec2, DVec3},
     prelude::*,
+    utils::HashMap,
 };
 use bytemuck::{Pod, Zeroable};
 use itertools::iproduct;
 use ndarray::{Array2, Array4};
 use std::iter;
 
+/// The maximum count of positions along the predicted path, for which tiles are prefetched per lod.
+const MAX_PREFETCH_STEPS: u32 = 8;
+/// The maximum count of tiles, which are checked for prefetching per frame and view.
+const MAX_PREFETCH_TILES: u32 = 2048;
+/// The time constant in seconds, with which the velocity of the view is smoothed.
+const VELOCITY_SMOOTHING_TIME: f64 = 0.075;
+
 /// The current state of a tile of a [`TileTree`].
 ///
 /// This indicates, whether or not the tile should be loaded into the [`TileAtlas`).
@@ -30,6 +38,8 @@ struct TileState {
     coordinate: TileCoordinate,
     /// Indicates, whether the tile is currently demanded or released.
     state: RequestState,
+    /// The distance between the tile and the view, used to prioritize loading.
+    distance: f64,
 }
 
 impl Default for TileState {
@@ -37,6 +47,7 @@ impl Default for TileState {
         Self {
             coordinate: TileCoordinate::INVALID,
             state: RequestState::Released,
+            distance: f64::INFINITY,
         }
     }
 }
@@ -109,10 +120,16 @@ pub struct TileTree {
     pub(super) released_tiles: Vec<TileCoordinate>,
     /// Tiles that are requested to be loaded by this tile_tree.
     pub(super) requested_tiles: Vec<TileCoordinate>,
+    /// Tiles along the predicted path of the view, with their distance along the path.
+    prefetched_tiles: HashMap<TileCoordinate, f64>,
+    /// The lod, at which prefetching continues in the next frame.
+    prefetch_lod: u32,
     /// The internal tile states of the tile_tree.
     tiles: Array4<TileState>,
     /// The count of level of detail layers.
     lod_count: u32,
+    /// The count of tiles at lod zero in x and y direction of each side.
+    root_count: UVec2,
     /// The count of tiles in x and y direction per layer.
     pub(crate) tree_size: u32,
     pub(crate) geometry_tile_count: u32,
@@ -121,12 +138,17 @@ pub struct TileTree {
     pub(crate) morph_distance: f64,
     pub(crate) blend_distance: f64,
     pub(crate) load_distance: f64,
+    pub(crate) prefetch_horizon: Option<f64>,
+    pub(crate) max_view_speed: f64,
     pub(crate) subdivision_distance: f64,
     pub(crate) precision_threshold_distance: f64,
     pub(crate) morph_range: f32,
     pub(crate) blend_range: f32,
     pub(crate) origin_lod: u32,
     pub(crate) view_world_position: DVec3,
+    /// The smoothed velocity of the view, used for prefetching.
+    pub(crate) view_velocity: DVec3,
+    previous_view_position: Option<DVec3>,
     pub(crate) approximate_height: f32,
 }
 
@@ -136,8 +158,21 @@ impl TileTree {
         let model = &tile_atlas.model;
         let scale = model.scale();
 
+        // lod zero is always requested and looked up entirely
+        assert!(
+            model.root_count().max_element() <= view_config.tree_size,
+            "The tree size has to be at least the root count of the terrain."
+        );
+        // the root tiles are refined first, so they have to fit into the tile buffers
+        assert!(
+            model.root_count().element_product() * model.side_count()
+                <= view_config.geometry_tile_count,
+            "The geometry tile count has to be at least the amount of root tiles of the terrain."
+        );
+
         Self {
             lod_count: tile_atlas.lod_count,
+            root_count: model.root_count(),
             tree_size: view_config.tree_size,
             geometry_tile_count: view_config.geometry_tile_count,
             refinement_count: view_config.refinement_count,
@@ -145,6 +180,8 @@ impl TileTree {
             morph_distance: view_config.morph_distance * scale,
             blend_distance: view_config.blend_distance * scale,
             load_distance: view_config.load_distance * scale,
+            prefetch_horizon: view_config.prefetch_horizon,
+            max_view_speed: view_config.max_view_speed * scale,
             subdivision_distance: view_config.morph_distance
                 * scale
                 * (1.0 + view_config.subdivision_tolerance),
@@ -153,6 +190,8 @@ impl TileTree {
             precision_threshold_distance: view_config.precision_threshold_distance * scale,
             origin_lod: view_config.origin_lod,
             view_world_position: default(),
+            view_velocity: default(),
+            previous_view_position: None,
             approximate_height: (model.min_height + model.max_height) / 2.0,
             origins: Array2::default((model.side_count() as usize, tile_atlas.lod_count as usize)),
             data: Array4::default((
@@ -169,23 +208,30 @@ impl TileTree {
             )),
             released_tiles: default(),
             requested_tiles: default(),
+            prefetched_tiles: default(),
+            prefetch_lod: 1,
         }
     }
 
-    fn compute_tree_xy(coordinate: Coordinate, tile_count: f64) -> DVec2 {
+    /// The count of tiles of the lod in x and y direction of each side.
+    fn side_tile_count(&self, tile_count: f64) -> DVec2 {
+        self.root_count.as_dvec2() * tile_count
+    }
+
+    fn compute_tree_xy(&self, coordinate: Coordinate, tile_count: f64) -> DVec2 {
         // scale and clamp the coordinate to the tile tree bounds
-        (coordinate.uv * tile_count).min(DVec2::splat(tile_count - 0.000001))
+        (coordinate.uv * tile_count).min(self.side_tile_count(tile_count) - 0.000001)
     }
 
     fn compute_origin(&self, coordinate: Coordinate, lod: u32) -> UVec2 {
         let tile_count = TileCoordinate::count(lod) as f64;
-        let tree_xy = Self::compute_tree_xy(coordinate, tile_count);
+        let tree_xy = self.compute_tree_xy(coordinate, tile_count);
 
         (tree_xy - 0.5 * self.tree_size as f64)
             .round()
             .clamp(
                 DVec2::splat(0.0),
-                DVec2::splat(tile_count - self.tree_size as f64),
+                self.side_tile_count(tile_count) - self.tree_size as f64,
             )
             .as_uvec2()
     }
@@ -194,11 +240,12 @@ impl TileTree {
         &self,
         tile: TileCoordinate,
         view_coordinate: Coordinate,
+        view_position: DVec3,
         model: &TerrainModel,
     ) -> f64 {
         let tile_count = TileCoordinate::count(tile.lod) as f64;
         let tile_xy = IVec2::new(tile.x as i32, tile.y as i32);
-        let view_tile_xy = Self::compute_tree_xy(view_coordinate, tile_count);
+        let view_tile_xy = self.compute_tree_xy(view_coordinate, tile_count);
         let tile_offset = view_tile_xy.as_ivec2() - tile_xy;
         let mut offset = view_tile_xy % 1.0;
 
@@ -217,7 +264,7 @@ impl TileTree {
             Coordinate::new(tile.side, (tile_xy.as_dvec2() + offset) / tile_count)
                 .world_position(model, self.approximate_height);
 
-        tile_world_position.distance(self.view_world_position)
+        tile_world_position.distance(view_position)
     }
 
     pub(super) fn compute_blend(&self, sample_world_position: DVec3) -> (u32, f32) {
@@ -245,7 +292,7 @@ impl TileTree {
         let coordinate = Coordinate::from_world_position(world_position, model);
 
         let tile_count = TileCoordinate::count(tree_lod) as f64;
-        let tree_xy = Self::compute_tree_xy(coordinate, tile_count);
+        let tree_xy = self.compute_tree_xy(coordinate, tile_count);
 
         let entry = self.data[[
             coordinate.side as usize,
@@ -265,10 +312,27 @@ impl TileTree {
         }
     }
 
-    fn update(&mut self, view_position: DVec3, tile_atlas: &TileAtlas) {
+    fn update(&mut self, view_position: DVec3, delta_time: f64, tile_atlas: &TileAtlas) {
         let model = &tile_atlas.model;
         self.view_world_position = view_position;
 
+        if let Some(previous_view_position) = self.previous_view_position {
+            if delta_time > 0.0 {
+                let velocity = (view_position - previous_view_position) / delta_time;
+
+                if velocity.length() > self.max_view_speed {
+                    // the view has been teleported, so its previous motion predicts nothing
+                    self.view_velocity = DVec3::ZERO;
+                } else {
+                    // the smoothing is independent of the frame rate
+                    let factor = 1.0 - (-delta_time / VELOCITY_SMOOTHING_TIME).exp();
+                    self.view_velocity = self.view_velocity.lerp(velocity, factor);
+                }
+            }
+        }
+
+        self.previous_view_position = Some(view_position);
+
         let view_coordinate = Coordinate::from_world_position(self.view_world_position, model);
 
         for side in 0..model.side_count() {
@@ -286,8 +350,12 @@ impl TileTree {
                         y: origin.y + y,
                     };
 
-                    let tile_distance =
-                        self.compute_tile_distance(tile_coordinate, view_coordinate, model);
+                    let tile_distance = self.compute_tile_distance(
+                        tile_coordinate,
+                        view_coordinate,
+                        self.view_world_position,
+                        model,
+                    );
                     let load_distance =
                         self.load_distance / TileCoordinate::count(tile_coordinate.lod) as f64;
 
@@ -315,6 +383,8 @@ impl TileTree {
                         tile.coordinate = tile_coordinate;
                     }
 
+                    tile.distance = tile_distance;
+
                     // request or release tile based on its distance to the view
                     match (tile.state, state) {
                         (RequestState::Released, RequestState::Requested) => {
@@ -330,6 +400,130 @@ impl TileTree {
                 }
             }
         }
+
+        self.prefetch(tile_atlas);
+    }
+
+    /// Requests the tiles within the load distance of the positions along the predicted path
+    /// of the view and releases the ones, which are no longer ahead of it.
+    ///
+    /// The lods are updated in turn, until [`MAX_PREFETCH_TILES`] have been checked this frame.
+    /// The prefetched tiles of the remaining lods are kept, until it is their turn again.
+    fn prefetch(&mut self, tile_atlas: &TileAtlas) {
+        let model = &tile_atlas.model;
+        let mut prefetched_tiles = HashMap::default();
+
+        let path = self.view_velocity * self.prefetch_horizon.unwrap_or(0.0);
+        let path_length = path.length();
+
+        if path_length > 0.0 {
+            let radius = self.tree_size as i32 / 2;
+            let grid_size = (2 * radius + 1).pow(2) as u32;
+            let start_lod = self.prefetch_lod.clamp(1, tile_atlas.lod_count);
+
+            let mut budget = MAX_PREFETCH_TILES;
+            let mut updated_lods = Vec::new();
+
+            // lod 0 is always requested around the view
+            for lod in (start_lod..tile_atlas.lod_count).chain(1..start_lod) {
+                let tile_count = TileCoordinate::count(lod);
+                let side_tile_count = self.side_tile_count(tile_count as f64).as_ivec2();
+                let load_distance = self.load_distance / tile_count as f64;
+                let step_count =
+                    ((path_length / load_distance).ceil() as u32).clamp(1, MAX_PREFETCH_STEPS);
+
+                // at least one lod is updated each frame
+                if !updated_lods.is_empty() && step_count * grid_size > budget {
+                    break;
+                }
+
+                budget = budget.saturating_sub(step_count * grid_size);
+                updated_lods.push(lod);
+                self.prefetch_lod = if lod + 1 < tile_atlas.lod_count {
+                    lod + 1
+                } else {
+                    1
+                };
+
+                for step in 1..=step_count {
+                    let path_fraction = step as f64 / step_count as f64;
+                    let position = self.view_world_position + path_fraction * path;
+                    let coordinate = Coordinate::from_world_position(position, model);
+                    let tile_xy = self
+                        .compute_tree_xy(coordinate, tile_count as f64)
+                        .as_ivec2();
+
+                    for (x, y) in iproduct!(-radius..=radius, -radius..=radius) {
+                        let xy = tile_xy + IVec2::new(x, y);
+
+                        if xy.min_element() < 0 || xy.cmpge(side_tile_count).any() {
+                            continue;
+                        }
+
+                        let tile_coordinate = TileCoordinate {
+                            side: coordinate.side,
+                            lod,
+                            x: xy.x as u32,
+                            y: xy.y as u32,
+                        };
+
+                        let tile_distance = self.compute_tile_distance(
+                            tile_coordinate,
+                            coordinate,
+                            position,
+                            model,
+                        );
+
+                        if tile_distance < load_distance {
+                            let path_distance = path_fraction * path_length + tile_distance;
+                            let distance = prefetched_tiles
+                                .entry(tile_coordinate)
+                                .or_insert(f64::INFINITY);
+                            *distance = f64::min(*distance, path_distance);
+                        }
+                    }
+                }
+            }
+
+            prefetched_tiles.extend(
+                self.prefetched_tiles
+                    .iter()
+                    .filter(|(tile_coordinate, _)| !updated_lods.contains(&tile_coordinate.lod)),
+            );
+        }
+
+        for &tile_coordinate in self.prefetched_tiles.keys() {
+            if !prefetched_tiles.contains_key(&tile_coordinate) {
+                self.released_tiles.push(tile_coordinate);
+            }
+        }
+
+        for &tile_coordinate in prefetched_tiles.keys() {
+            if !self.prefetched_tiles.contains_key(&tile_coordinate) {
+                self.requested_tiles.push(tile_coordinate);
+            }
+        }
+
+        self.prefetched_tiles = prefetched_tiles;
+    }
+
+    /// Returns the distances to the view of all tiles requested by this tile_tree.
+    pub(super) fn requested_tile_distances(
+        &self,
+    ) -> impl Iterator<Item = (TileCoordinate, f64)> + '_ {
+        self.tiles
+            .iter()
+            .filter(|tile| tile.state == RequestState::Requested)
+            .map(|tile| (tile.coordinate, tile.distance))
+    }
+
+    /// Returns the distances along the predicted path of all tiles prefetched by this tile_tree.
+    pub(super) fn prefetched_tile_distances(
+        &self,
+    ) -> impl Iterator<Item = (TileCoordinate, f64)> + '_ {
+        self.prefetched_tiles
+            .iter()
+            .map(|(&tile_coordinate, &distance)| (tile_coordinate, distance))
     }
 
     /// Traverses all tile_trees and updates the tile states,
@@ -337,6 +531,7 @@ impl TileTree {
     pub(crate) fn compute_requests(
         mut tile_trees: ResMut<TerrainViewComponents<TileTree>>,
         tile_atlases: Query<&TileAtlas>,
+        time: Res<Time>,
         #[cfg(feature = "high_precision")] frames: crate::big_space::ReferenceFrames,
         #[cfg(feature = "high_precision")] view_transforms: Query<
             crate::big_space::GridTransformReadOnly,
@@ -354,7 +549,7 @@ impl TileTree {
             #[cfg(not(feature = "high_precision"))]
             let view_position = view_transform.translation.as_dvec3();
 
-            tile_tree.update(view_position, tile_atlas);
+            tile_tree.update(view_position, time.delta_seconds_f64(), tile_atlas);
         }
     }
 
@@ -385,3 +580,109 @@ impl TileTree {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::TerrainConfig;
+    use bevy::utils::HashSet;
+
+    fn prefetched_lods(tile_tree: &TileTree) -> HashSet<u32> {
+        tile_tree
+            .prefetched_tiles
+            .keys()
+            .map(|tile_coordinate| tile_coordinate.lod)
+            .collect()
+    }
+
+    #[test]
+    #[should_panic(expected = "at least the amount of root tiles")]
+    fn root_tiles_have_to_fit_into_the_tile_buffers() {
+        let config = TerrainConfig {
+            model: TerrainModel::planar_rectangle(DVec3::ZERO, 1.0, UVec2::new(4, 3), 0.0, 1.0),
+            ..default()
+        };
+        let view_config = TerrainViewConfig {
+            geometry_tile_count: 11,
+            ..default()
+        };
+
+        TileTree::new(&TileAtlas::new(&config), &view_config);
+    }
+
+    #[test]
+    fn rectangular_terrains_request_all_of_their_root_tiles() {
+        let config = TerrainConfig {
+            lod_count: 2,
+            model: TerrainModel::planar_rectangle(DVec3::ZERO, 1.0, UVec2::new(3, 2), 0.0, 1.0),
+            ..default()
+        };
+
+        let tile_atlas = TileAtlas::new(&config);
+        let mut tile_tree = TileTree::new(&tile_atlas, &TerrainViewConfig::default());
+
+        // the view above the last root tile lies at the end of the uv range of the terrain
+        let view_position = DVec3::new(1.0, 0.0, 0.5);
+        let view_coordinate = Coordinate::from_world_position(view_position, &tile_atlas.model);
+        assert!((view_coordinate.uv - DVec2::new(2.5, 1.5)).length() < 1e-6);
+
+        tile_tree.update(view_position, 0.0, &tile_atlas);
+
+        // lod zero is requested entirely, so every root tile is requested regardless of the view
+        let root_tiles = tile_atlas.model.root_tiles().collect::<Vec<_>>();
+        assert_eq!(root_tiles.len(), 6);
+        assert!(root_tiles
+            .iter()
+            .all(|root_tile| tile_tree.requested_tiles.contains(root_tile)));
+    }
+
+    #[test]
+    fn prefetch_spreads_the_lods_across_frames() {
+        let lod_count = 12;
+        let config = TerrainConfig {
+            lod_count,
+            model: TerrainModel::planar(DVec3::ZERO, 1000.0, 0.0, 1.0),
+            ..default()
+        };
+        let view_config = TerrainViewConfig {
+            prefetch_horizon: Some(5.0),
+            ..default()
+        };
+
+        let tile_atlas = TileAtlas::new(&config);
+        let mut tile_tree = TileTree::new(&tile_atlas, &view_config);
+        tile_tree.view_world_position = DVec3::new(-400.0, 0.0, 0.0);
+        tile_tree.view_velocity = DVec3::new(100.0, 0.0, 0.0);
+
+        tile_tree.prefetch(&tile_atlas);
+
+        let lods = prefetched_lods(&tile_tree);
+        assert!(!lods.contains(&0), "lod 0 is always requested");
+        assert!(
+            lods.len() < lod_count as usize - 1,
+            "the work per frame is capped"
+        );
+
+        // the view moves from the first towards the fifth tile of lod 3 along the path
+        assert!(tile_tree
+            .prefetched_tiles
+            .keys()
+            .any(|tile_coordinate| tile_coordinate.lod == 3 && tile_coordinate.x == 4));
+
+        for _ in 0..lod_count {
+            tile_tree.prefetch(&tile_atlas);
+        }
+
+        assert_eq!(prefetched_lods(&tile_tree), (1..lod_count).collect());
+
+        // once the view stops, all prefetched tiles are released at once
+        tile_tree.view_velocity = DVec3::ZERO;
+        tile_tree.prefetch(&tile_atlas);
+
+        assert!(tile_tree.prefetched_tiles.is_empty());
+        assert!(tile_tree
+            .released_tiles
+            .iter()
+            .any(|tile_coordinate| tile_coordinate.lod == lod_count - 1));
+    }
+}
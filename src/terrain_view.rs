@@ -31,6 +31,14 @@ pub struct TerrainViewConfig {
     pub subdivision_tolerance: f64,
     pub precision_threshold_distance: f64,
     pub load_distance: f64,
+    /// The time in seconds, over which the motion of the view is extrapolated,
+    /// to request the tiles along its predicted path ahead of time.
+    /// These tiles are loaded with a lower priority than the ones around the view.
+    /// Prefetching is disabled, if this is `None`.
+    pub prefetch_horizon: Option<f64>,
+    /// The highest plausible speed of the view, measured in terrain scales per second.
+    /// Faster motion, like teleporting the view, is not extrapolated for prefetching.
+    pub max_view_speed: f64,
     /// The distance measured in tile sizes between adjacent LOD layers.
     /// This currently has to be larger than about 6, since the tiles can only morph to the adjacent layer.
     /// Should the morph distance be too small, this will result in morph transitions suddenly being canceled, by the next LOD.
@@ -53,6 +61,8 @@ impl Default for TerrainViewConfig {
             grid_size: 16,
             subdivision_tolerance: 0.1,
             load_distance: 2.5,
+            prefetch_horizon: None,
+            max_view_speed: 1.0,
             morph_distance: 16.0,
             blend_distance: 2.0,
             morph_range: 0.2,
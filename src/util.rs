@@ -6,7 +6,7 @@ use itertools::Itertools;
 use std::{fmt::Debug, marker::PhantomData, ops::Deref};
 
 pub(crate) fn inverse_mix(a: f32, b: f32, value: f32) -> f32 {
-    return f32::clamp((value - a) / (b - a), 0.0, 1.0);
+    f32::clamp((value - a) / (b - a), 0.0, 1.0)
 }
 
 pub trait CollectArray: Iterator {